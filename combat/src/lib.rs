@@ -20,13 +20,14 @@ use fleet::CombatFleet;
 
 use crate::spawning::ship_spawn;
 use crate::unit::Team;
+use crate::variables::{expire_modifiers, recalculate_variables, VariableEvent, VariableObservers};
 
 mod fleet;
 mod signals;
 mod spawning;
 mod state;
 mod unit;
-mod variables;
+pub mod variables;
 
 mod units;
 
@@ -56,18 +57,27 @@ impl Plugin for CombatPlugin {
         );
 
         app.init_resource::<Events<CombatErrorEvent>>();
+        app.add_event::<VariableEvent>()
+            .init_resource::<VariableObservers>();
 
         app.add_systems(OnEnter(GameState::Combat), init_combat)
             .add_systems(OnExit(GameState::Combat), exit_combat)
             .add_plugins(Shape2dPlugin::default());
 
-        app.add_systems(FixedUpdate, ship_spawn.in_set(CombatSet::PreUpdate));
+        app.add_systems(
+            FixedUpdate,
+            (ship_spawn, expire_modifiers).in_set(CombatSet::PreUpdate),
+        );
 
         app.add_plugins((
             PhysicsPlugins::new(PhysicsUpdate),
             PhysicsDebugPlugin::default(),
         ));
         app.add_systems(FixedUpdate, run_physics.in_set(CombatSet::PhysicsUpdate));
+        app.add_systems(
+            FixedUpdate,
+            recalculate_variables.in_set(CombatSet::PostUpdate),
+        );
 
         app.add_systems(Update, (update).run_if(in_state(GameState::Combat)));
         app.add_systems(Last, (error_handler).run_if(in_state(GameState::Combat)));
@@ -1,6 +1,5 @@
 use bevy::log::info;
 use bevy::prelude::{Assets, Commands, Image, Query, Res, With};
-use bevy_mod_sysfail::sysfail;
 use nohash_hasher::IntSet;
 
 use ehce_core::database::model::ship_build::ShipBuild;
@@ -9,10 +8,8 @@ use ehce_core::mods::ModData;
 use crate::fleet::CombatFleet;
 use crate::unit::ship::{calculate_variables, make_ship};
 use crate::unit::{Team, Unit, UnitBundle};
-use crate::variables::{VariableEvaluationError, Variables};
-use crate::EmitCombatError;
+use crate::variables::Variables;
 
-#[sysfail(EmitCombatError)]
 pub fn ship_spawn(
     ships: Query<&Team, With<Unit>>,
     mut fleets: Query<(&mut CombatFleet, &Team)>,
@@ -44,16 +41,10 @@ pub fn ship_spawn(
             std::mem::take(next.variables),
             &images,
             &mut commands,
-        )?;
+        );
     }
 }
 
-utils::bubbled!(
-    ShipSpawnError("Failed to spawn a ship") {
-        VariableEvaluationError,
-    }
-);
-
 fn spawn_ship(
     db: &ModData,
     build: impl AsRef<ShipBuild>,
@@ -61,14 +52,14 @@ fn spawn_ship(
     variables: Option<Variables>,
     images: &Assets<Image>,
     commands: &mut Commands,
-) -> Result<(), ShipSpawnError> {
+) {
     let build = build.as_ref();
     let ship = &db.registry[build.ship];
     let ship_bundle = make_ship(db, ship, images);
     let variables = if let Some(resources) = variables {
         resources
     } else {
-        calculate_variables(db, ship, build)?
+        calculate_variables(db, ship, build)
     };
 
     commands.spawn((
@@ -79,6 +70,4 @@ fn spawn_ship(
             variables,
         },
     ));
-
-    Ok(())
 }
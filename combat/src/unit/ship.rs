@@ -6,25 +6,39 @@ use ehce_core::database::model::ship::Ship;
 use ehce_core::database::model::ship_build::ShipBuild;
 use ehce_core::mods::ModData;
 
-use crate::variables::{VariableEvaluationError, Variables};
+use crate::variables::{Modifier, ModifierLayer, ModifierSource, Variables};
 
+/// Variables of the ship built by the build
+///
+/// Built-in stats of the ship make up the raw values, while the stats of the
+/// installed components are applied as [ModifierLayer::Flat] modifiers of
+/// [ModifierSource::Component], so they can be removed along with the
+/// component. Raw values set later with [Variables::set] replace only the
+/// built-in stats, the components keep adding to them
 pub fn calculate_variables(
     db: &ModData,
     ship: impl AsRef<Ship>,
     build: impl AsRef<ShipBuild>,
-) -> Result<Variables, VariableEvaluationError> {
+) -> Variables {
     let build = build.as_ref();
 
     let ship = ship.as_ref();
 
-    let stats = build
-        .components
+    let stats = ship
+        .built_in_stats
         .iter()
-        .map(|e| &db.registry[e.component].data.stats)
-        .chain(ship.built_in_stats.iter())
         .flat_map(|id| &id.get(&db.registry).stats)
         .map(|(id, value)| (*id, *value));
-    Variables::from_stats(db, stats)
+    let mut variables = Variables::from_stats(db, stats);
+
+    for (slot, installed) in build.components.iter().enumerate() {
+        let source = ModifierSource::Component(slot);
+        let stats = &db.registry[installed.component].data.stats;
+        for (id, value) in &stats.get(&db.registry).stats {
+            variables.add_modifier(db, *id, Modifier::new(ModifierLayer::Flat, *value, source));
+        }
+    }
+    variables
 }
 
 pub fn make_ship(
@@ -62,3 +76,66 @@ pub struct ShipBundle {
     pub rb: RigidBody,
     pub collider: Collider,
 }
+
+#[cfg(test)]
+mod test {
+    use bevy::asset::Handle;
+
+    use database::fixture::{layer, Fixture};
+    use ehce_core::database::model::ModRegistry;
+    use ehce_core::mods::ModData;
+
+    use super::calculate_variables;
+    use crate::variables::ModifierSource;
+
+    const SHIP: &str = r#"[
+  { type: "Variable", id: "eh:armor", name: "Armor" },
+  { type: "ComponentStats", id: "eh:hull", stats: { "eh:armor": 10 } },
+  { type: "ComponentStats", id: "eh:plating", stats: { "eh:armor": 5 } },
+  { type: "Component", id: "eh:plate", stats: "eh:plating", devices: [] },
+  { type: "Ship", id: "eh:scout", sprite: "scout.png", modelScale: 1, builtInStats: "eh:hull" },
+  {
+    type: "ShipBuild",
+    id: "eh:scout",
+    ship: "eh:scout",
+    components: [
+      { component: "eh:plate", pos: { x: 0, y: 0 } },
+      { component: "eh:plate", pos: { x: 1, y: 0 } },
+    ],
+  },
+]"#;
+
+    /// Database with the scout, which has two copies of the same component
+    /// installed on top of its built-in armor
+    fn mod_data() -> ModData {
+        let assets = Fixture::default().file("eh/ship.json5", SHIP).assets();
+        let mut layer = layer("eh", &assets);
+        layer.images = vec![("eh/scout.png".into(), Handle::weak_from_u128(1))];
+        ModData {
+            mods: vec![],
+            registry: ModRegistry::build([layer]).unwrap(),
+            assets: Default::default(),
+        }
+    }
+
+    #[test]
+    fn components_are_applied_on_top_of_built_in_stats() {
+        let db = mod_data();
+        let build = &db.registry[db.registry.ship_build.key_to_id("eh:scout").unwrap()];
+        let ship = &db.registry[build.data.ship];
+        let armor = db.registry.variable.key_to_id("eh:armor").unwrap();
+
+        let mut variables = calculate_variables(&db, ship, build);
+        assert_eq!(variables.calculate_mut(&db, armor), 20.0);
+
+        // Setting the raw value replaces only the built-in stats
+        variables.set(&db, armor, 1.0);
+        assert_eq!(variables.calculate_mut(&db, armor), 11.0);
+
+        // Copies of the same component are removed one at a time
+        assert!(variables.remove_modifiers(ModifierSource::Component(1)));
+        assert_eq!(variables.calculate_mut(&db, armor), 6.0);
+        assert!(variables.remove_modifiers(ModifierSource::Component(0)));
+        assert_eq!(variables.calculate_mut(&db, armor), 1.0);
+    }
+}
@@ -11,11 +11,22 @@ use ehce_core::database::model::formula::Formula;
 use ehce_core::database::model::{ItemId, VariableId};
 use ehce_core::mods::ModData;
 
+pub use modifier::{expire_modifiers, Modifier, ModifierLayer, ModifierSource};
+pub use observer::{
+    recalculate_variables, ObserverCondition, ObserverId, VariableEvent, VariableObservers,
+};
+
+mod modifier;
+mod observer;
+
 /// Component to track entity variables
 ///
-/// Computed variable dependencies must form an
+/// Computed variable dependencies always form an
 /// [acyclic graph](https://en.wikipedia.org/wiki/Directed_acyclic_graph),
-/// otherwise an error will be returned
+/// since cycles are rejected when the mod is loaded
+///
+/// Modifiers are applied on top of the raw or computed value, and variables
+/// computed from the modified one observe the modified value
 #[derive(Debug, Default, Component)]
 pub struct Variables {
     /// Mapping of variable ID to internal ID
@@ -23,7 +34,6 @@ pub struct Variables {
     /// Cache of default computed values
     wanted_cache: Mutex<IntMap<VariableId, f64>>,
     data: ComputationGraphVec,
-    in_progress: Vec<VariableId>,
 }
 
 impl Clone for Variables {
@@ -32,7 +42,6 @@ impl Clone for Variables {
             ids: self.ids.clone(),
             wanted_cache: Mutex::new(self.wanted_cache.lock().unwrap().clone()),
             data: self.data.clone(),
-            in_progress: self.in_progress.clone(),
         }
     }
 }
@@ -54,155 +63,195 @@ struct ComputationGraph {
     deps: Vec<usize>,
     /// Variables that depend on this variable, used for invalidating cache
     rdeps: Vec<usize>,
+    /// Modifiers applied to the value, in the order they were added
+    modifiers: Vec<Modifier>,
+    /// Value last reported to the observers
+    reported: Option<f64>,
 }
 
 impl Variables {
-    pub fn from_stats(
-        db: &ModData,
-        stats: impl IntoIterator<Item = (VariableId, f64)>,
-    ) -> Result<Self, VariableEvaluationError> {
+    pub fn from_stats(db: &ModData, stats: impl IntoIterator<Item = (VariableId, f64)>) -> Self {
         let mut variables = Self::default();
 
         for (res, amount) in stats {
-            let id = variables.get_id_or_init(db, res)?;
+            let id = variables.get_id_or_init(db, res);
             variables.data.value[id] += amount;
         }
 
-        Ok(variables)
+        variables
     }
 
     /// Calculates value of the variable. Missing variables will get cached,
     /// but won't be fully inserted
-    /// TODO: add cyclical dependencies handling
-    pub fn calculate(
-        &self,
-        db: &ModData,
-        res_id: VariableId,
-    ) -> Result<f64, VariableEvaluationError> {
-        let (formula, value) = if let Some(id) = self.ids.get(&res_id) {
+    pub fn calculate(&self, db: &ModData, res_id: VariableId) -> f64 {
+        let (formula, value, modifiers) = if let Some(id) = self.ids.get(&res_id) {
             if let Some(cached) = self.data.cache[*id] {
-                return Ok(cached);
+                return cached;
             }
 
-            (&self.data.formula[*id], self.data.value[*id])
+            (
+                &self.data.formula[*id],
+                self.data.value[*id],
+                self.data.modifiers[*id].as_slice(),
+            )
         } else {
             if let Some(cached) = self.wanted_cache.lock().unwrap().get(&res_id) {
-                return Ok(*cached);
+                return *cached;
             }
             let res = &db.registry[res_id];
 
             let default = if let Some(default) = &res.data.default {
-                let args = default
-                    .args
-                    .iter()
-                    .map(|e| self.calculate(db, *e))
-                    .try_collect()?;
-                match default.expr.eval_vec(args) {
-                    Ok(data) => data,
-                    Err(err) => return Err(EvaluationError(err, debug_key(db, res_id)).into()),
-                }
+                default.eval_with(|i| self.calculate(db, default.args[i]))
             } else {
                 0.0
             };
 
-            (&res.data.computed, default)
+            (&res.data.computed, default, [].as_slice())
         };
 
-        if let Some(formula) = formula {
-            let args = formula
-                .args
-                .iter()
-                .map(|e| self.calculate(db, *e))
-                .try_collect()?;
-            match formula.expr.eval_vec(args) {
-                Ok(value) => Ok(value),
-                Err(err) => Err(EvaluationError(err, debug_key(db, res_id)).into()),
-            }
+        let value = if let Some(formula) = formula {
+            formula.eval_with(|i| self.calculate(db, formula.args[i]))
         } else {
-            Ok(value)
-        }
+            value
+        };
+        modifier::resolve(value, modifiers)
     }
 
     /// Calculates value of the variable, inserting it if not present, or
     /// updating it if not cached
-    pub fn calculate_mut(
-        &mut self,
-        db: &ModData,
-        res_id: VariableId,
-    ) -> Result<f64, VariableEvaluationError> {
-        let id = self.get_id_or_init(db, res_id)?;
+    pub fn calculate_mut(&mut self, db: &ModData, res_id: VariableId) -> f64 {
+        let id = self.get_id_or_init(db, res_id);
         Self::calculate_inner(
-            db,
-            &self.data.variable_id,
             &self.data.value,
             &mut self.data.cache,
             &self.data.deps,
             &self.data.formula,
+            &self.data.modifiers,
             id,
-            res_id,
         )
     }
 
     /// Sets raw value of the specified variable, inserting it if not present
-    pub fn set(
-        &mut self,
-        db: &ModData,
-        res_id: VariableId,
-        value: f64,
-    ) -> Result<(), VariableEvaluationError> {
-        let id = self.get_id_or_init(db, res_id)?;
+    ///
+    /// Modifiers of the variable, such as the stats of the installed
+    /// components, still apply on top of the new value
+    pub fn set(&mut self, db: &ModData, res_id: VariableId, value: f64) {
+        let id = self.get_id_or_init(db, res_id);
         Self::invalidate_cache(&mut self.data.cache, &self.data.rdeps, id);
         self.data.value[id] = value;
-        Ok(())
     }
 
     /// Increases raw value of the specified variable by a given amount
-    pub fn add(
-        &mut self,
-        db: &ModData,
-        res_id: VariableId,
-        value: f64,
-    ) -> Result<(), VariableEvaluationError> {
-        let id = self.get_id_or_init(db, res_id)?;
+    pub fn add(&mut self, db: &ModData, res_id: VariableId, value: f64) {
+        let id = self.get_id_or_init(db, res_id);
         Self::invalidate_cache(&mut self.data.cache, &self.data.rdeps, id);
         self.data.value[id] += value;
-        Ok(())
     }
 
-    /// Calculates cache for all "dirty" variables, as well as flushes
-    /// [calculate] cache
-    pub fn recalculate_dirty(&mut self, db: &ModData) -> Result<(), VariableEvaluationError> {
-        self.process_calculation_cache(db)?;
+    /// Applies the modifier to the specified variable, inserting it if not
+    /// present
+    pub fn add_modifier(&mut self, db: &ModData, res_id: VariableId, modifier: Modifier) {
+        let id = self.get_id_or_init(db, res_id);
+        Self::invalidate_cache(&mut self.data.cache, &self.data.rdeps, id);
+        self.data.modifiers[id].push(modifier);
+    }
 
-        let mut i = 0;
-        while i < self.data.len() {
-            if self.data.cache[i].is_none() {
-                self.calculate_mut(db, self.data.variable_id[i])?;
+    /// Removes all modifiers applied by the source, returning whether any
+    /// were applied
+    pub fn remove_modifiers(&mut self, source: ModifierSource) -> bool {
+        let mut removed = false;
+        for id in 0..self.data.len() {
+            let modifiers = &mut self.data.modifiers[id];
+            let len = modifiers.len();
+            modifiers.retain(|modifier| modifier.source != source);
+            if modifiers.len() != len {
+                Self::invalidate_cache(&mut self.data.cache, &self.data.rdeps, id);
+                removed = true;
             }
-            i += 1;
         }
+        removed
+    }
 
-        Ok(())
+    /// Modifiers applied to the specified variable
+    pub fn modifiers(&self, res_id: VariableId) -> &[Modifier] {
+        match self.ids.get(&res_id) {
+            Some(id) => &self.data.modifiers[*id],
+            None => &[],
+        }
+    }
+
+    /// Whether any of the modifiers has a duration
+    pub fn has_timed_modifiers(&self) -> bool {
+        self.data
+            .modifiers
+            .iter()
+            .flatten()
+            .any(|modifier| modifier.duration.is_some())
+    }
+
+    /// Counts the durations of the modifiers down by the elapsed time in
+    /// seconds, removing the expired ones
+    pub fn tick_modifiers(&mut self, delta: f64) {
+        for id in 0..self.data.len() {
+            let modifiers = &mut self.data.modifiers[id];
+            let len = modifiers.len();
+            modifiers.retain_mut(|modifier| match &mut modifier.duration {
+                Some(duration) => {
+                    *duration -= delta;
+                    *duration > 0.0
+                }
+                None => true,
+            });
+            if modifiers.len() != len {
+                Self::invalidate_cache(&mut self.data.cache, &self.data.rdeps, id);
+            }
+        }
+    }
+
+    /// Calculates cache for all "dirty" variables, as well as flushes
+    /// [calculate] cache
+    ///
+    /// Variables are calculated in the evaluation order of the registry, so
+    /// dependencies of every variable are already cached by the time it's
+    /// calculated
+    pub fn recalculate_dirty(&mut self, db: &ModData) {
+        self.process_calculation_cache(db);
+
+        let order = db.registry.variable_order();
+        let dirty = (0..self.data.len())
+            .filter(|i| self.data.cache[*i].is_none())
+            .map(|i| self.data.variable_id[i])
+            .sorted_by_key(|id| order.rank(*id))
+            .collect_vec();
+        for id in dirty {
+            self.calculate_mut(db, id);
+        }
+    }
+
+    /// Whether any of the variables needs to be recalculated
+    pub fn is_dirty(&self) -> bool {
+        self.data.cache.iter().any(Option::is_none) || !self.wanted_cache.lock().unwrap().is_empty()
+    }
+
+    /// Records the calculated value of the variable as reported to the
+    /// observers, returning the previously reported value along with it,
+    /// unless this is the first report
+    ///
+    /// Returns `None` if the variable is not tracked or not calculated
+    fn report(&mut self, res_id: VariableId) -> Option<(Option<f64>, f64)> {
+        let id = *self.ids.get(&res_id)?;
+        let new = self.data.cache[id]?;
+        let old = self.data.reported[id].replace(new);
+        Some((old, new))
     }
 
     /// Clears [calculate] cache and initializes all accessed variables
-    pub fn process_calculation_cache(
-        &mut self,
-        db: &ModData,
-    ) -> Result<(), VariableEvaluationError> {
+    pub fn process_calculation_cache(&mut self, db: &ModData) {
         let mut cache = self.wanted_cache.lock().unwrap();
         for id in cache.keys() {
-            Self::get_id_or_init_raw(
-                db,
-                &mut self.ids,
-                &mut self.data,
-                &mut self.in_progress,
-                *id,
-            )?;
+            Self::get_id_or_init_raw(db, &mut self.ids, &mut self.data, *id);
         }
         cache.clear();
-
-        Ok(())
     }
 
     /// Clears all variables stored in a map
@@ -211,41 +260,34 @@ impl Variables {
         self.data.clear();
     }
 
+    /// Calculates the variable, pulling the values of its dependencies
+    /// straight into the formula, without collecting them first
     fn calculate_inner(
-        db: &ModData,
-        rids: &[VariableId],
         values: &[f64],
         cache: &mut [Option<f64>],
         deps: &[Vec<usize>],
         formulas: &[Option<Arc<Formula>>],
+        modifiers: &[Vec<Modifier>],
         id: usize,
-        res_id: VariableId,
-    ) -> Result<f64, VariableEvaluationError> {
+    ) -> f64 {
         if let Some(cached) = &cache[id] {
-            return Ok(*cached);
+            return *cached;
         }
 
         let raw_value = values[id];
         let value = if let Some(formula) = &formulas[id] {
-            let arguments: Vec<f64> = deps[id]
-                .iter()
-                .map(|dep_id| {
-                    Self::calculate_inner(
-                        db, rids, values, cache, deps, formulas, *dep_id, rids[id],
-                    )
-                })
-                .try_collect()?;
-            match formula.expr.eval_vec(arguments) {
-                Ok(data) => data + raw_value,
-                Err(err) => return Err(EvaluationError(err, debug_key(db, res_id)).into()),
-            }
+            let value = formula.eval_with(|i| {
+                Self::calculate_inner(values, cache, deps, formulas, modifiers, deps[id][i])
+            });
+            value + raw_value
         } else {
             raw_value
         };
+        let value = modifier::resolve(value, &modifiers[id]);
 
         cache[id] = Some(value);
 
-        Ok(value)
+        value
     }
 
     fn invalidate_cache(cache: &mut [Option<f64>], rdeps: &[Vec<usize>], id: usize) {
@@ -255,29 +297,18 @@ impl Variables {
         }
     }
 
-    fn get_id_or_init(
-        &mut self,
-        db: &ModData,
-        variable_id: VariableId,
-    ) -> Result<usize, VariableEvaluationError> {
-        Self::get_id_or_init_raw(
-            db,
-            &mut self.ids,
-            &mut self.data,
-            &mut self.in_progress,
-            variable_id,
-        )
+    fn get_id_or_init(&mut self, db: &ModData, variable_id: VariableId) -> usize {
+        Self::get_id_or_init_raw(db, &mut self.ids, &mut self.data, variable_id)
     }
 
     fn get_id_or_init_raw(
         db: &ModData,
         ids: &mut IntMap<VariableId, usize>,
         data: &mut ComputationGraphVec,
-        in_progress: &mut Vec<VariableId>,
         variable_id: VariableId,
-    ) -> Result<usize, VariableEvaluationError> {
+    ) -> usize {
         if let Some(id) = ids.get(&variable_id) {
-            return Ok(*id);
+            return *id;
         }
 
         let res = &db.registry.variable[variable_id];
@@ -289,46 +320,17 @@ impl Variables {
             formula: res.data.computed.clone(),
             deps: vec![],
             rdeps: vec![],
+            modifiers: vec![],
+            reported: None,
         });
 
         let other = ids.insert(variable_id, id);
         debug_assert!(other.is_none(), "Id should be new. id={:?}", variable_id);
 
-        #[inline(always)]
-        fn check_deps(
-            in_progress: &mut Vec<VariableId>,
-            db: &ModData,
-            res: &VariableId,
-        ) -> Result<(), VariableEvaluationError> {
-            if let Some(idx) =
-                in_progress.iter().enumerate().find_map(
-                    |(id, e)| {
-                        if e == res {
-                            Some(id)
-                        } else {
-                            None
-                        }
-                    },
-                )
-            {
-                in_progress.push(*res);
-                let slice = in_progress[idx..]
-                    .iter()
-                    .map(|e| debug_key(db, *e))
-                    .collect_vec();
-                return Err(CircularDependencyError(slice).into());
-            }
-            Ok(())
-        }
-
         if res.data.computed.is_some() || res.data.default.is_some() {
-            in_progress.push(variable_id);
-
             if let Some(computed) = &res.data.computed {
                 for arg in &computed.args {
-                    check_deps(in_progress, db, arg)?;
-
-                    let dep_id = Self::get_id_or_init_raw(db, ids, data, in_progress, *arg)?;
+                    let dep_id = Self::get_id_or_init_raw(db, ids, data, *arg);
                     Self::add_dep(
                         data.deps.as_mut_slice(),
                         data.rdeps.as_mut_slice(),
@@ -339,36 +341,22 @@ impl Variables {
             }
 
             if let Some(default) = &res.data.default {
-                let mut args = Vec::with_capacity(default.args.len());
-                for arg in &default.args {
-                    check_deps(in_progress, db, arg)?;
-
-                    let arg_id = Self::get_id_or_init_raw(db, ids, data, in_progress, *arg)?;
-                    let value = Self::calculate_inner(
-                        db,
-                        &data.variable_id,
+                let default = default.eval_with(|i| {
+                    let arg_id = Self::get_id_or_init_raw(db, ids, data, default.args[i]);
+                    Self::calculate_inner(
                         &data.value,
                         &mut data.cache,
                         &data.deps,
                         &data.formula,
+                        &data.modifiers,
                         arg_id,
-                        *arg,
-                    )?;
-
-                    args.push(value)
-                }
-
-                let default = default
-                    .expr
-                    .eval_vec(args)
-                    .map_err(|e| DefaultEvaluationError(e, debug_key(db, variable_id)))?;
+                    )
+                });
                 data.value[id] = default;
             }
-
-            in_progress.pop();
         }
 
-        Ok(id)
+        id
     }
 
     fn add_dep(
@@ -382,32 +370,96 @@ impl Variables {
     }
 }
 
-fn debug_key(db: &ModData, id: VariableId) -> ItemId {
-    db.registry
-        .variable
-        .id_to_key(id)
-        .cloned()
-        .unwrap_or_else(|| format!("{:?}", id))
-}
-
-utils::bubbled!(VariableEvaluationError {
-    EvaluationError,
-    DefaultEvaluationError,
-    CircularDependencyError,
-});
-
 #[derive(Debug, Clone, Error, Diagnostic)]
 #[error("Variable {} is dirty", .0)]
 pub struct VariableDirtyError(ItemId);
 
-#[derive(Debug, Clone, Error, Diagnostic)]
-#[error("Failed to evaluate Variable({}): {}", .1, .0)]
-pub struct EvaluationError(exmex::ExError, ItemId);
+#[cfg(test)]
+mod test {
+    use bevy::prelude::Entity;
+
+    use database::fixture::Fixture;
+    use ehce_core::database::model::VariableId;
+    use ehce_core::mods::ModData;
+
+    use super::{Modifier, ModifierLayer, ModifierSource, Variables};
+
+    const VARIABLES: &str = r#"[
+  { type: "Variable", id: "eh:base", name: "Base" },
+  { type: "Variable", id: "eh:doubled", name: "Doubled", computed: "{eh:base} * 2" },
+  { type: "Variable", id: "eh:total", name: "Total", computed: "{eh:doubled} + 1" },
+]"#;
+
+    /// Database with the `base` variable, `doubled` computed from it, and
+    /// `total` computed from `doubled`
+    pub(super) fn mod_data() -> ModData {
+        let registry = Fixture::default()
+            .file("eh/variables.json5", VARIABLES)
+            .build()
+            .unwrap();
+        ModData {
+            mods: vec![],
+            registry,
+            assets: Default::default(),
+        }
+    }
 
-#[derive(Debug, Clone, Error, Diagnostic)]
-#[error("Failed to evaluate default value for Variable({}): {}", .1, .0)]
-pub struct DefaultEvaluationError(exmex::ExError, ItemId);
+    pub(super) fn variable(db: &ModData, id: &str) -> VariableId {
+        db.registry.variable.key_to_id(id).unwrap()
+    }
 
-#[derive(Debug, Clone, Error, Diagnostic)]
-#[error("Circular dependency while evaluating the variable. Stack: [{}]", .0.join(", "))]
-pub struct CircularDependencyError(Vec<ItemId>);
+    fn flat(value: f64) -> Modifier {
+        Modifier::new(
+            ModifierLayer::Flat,
+            value,
+            ModifierSource::Entity(Entity::PLACEHOLDER),
+        )
+    }
+
+    #[test]
+    fn adding_modifiers_invalidates_dependent_variables() {
+        let db = mod_data();
+        let (base, total) = (variable(&db, "eh:base"), variable(&db, "eh:total"));
+        let mut variables = Variables::from_stats(&db, [(base, 5.0)]);
+        assert_eq!(variables.calculate_mut(&db, total), 11.0);
+
+        variables.add_modifier(&db, base, flat(1.0));
+        assert!(variables.is_dirty());
+        assert_eq!(variables.calculate_mut(&db, total), 13.0);
+    }
+
+    #[test]
+    fn removing_modifiers_invalidates_dependent_variables() {
+        let db = mod_data();
+        let (base, total) = (variable(&db, "eh:base"), variable(&db, "eh:total"));
+        let mut variables = Variables::from_stats(&db, [(base, 5.0)]);
+        variables.add_modifier(&db, base, flat(1.0));
+        assert_eq!(variables.calculate_mut(&db, total), 13.0);
+
+        let source = ModifierSource::Entity(Entity::PLACEHOLDER);
+        assert!(variables.remove_modifiers(source));
+        assert!(variables.is_dirty());
+        assert_eq!(variables.calculate_mut(&db, total), 11.0);
+        assert!(!variables.remove_modifiers(source));
+    }
+
+    #[test]
+    fn expired_modifiers_invalidate_dependent_variables() {
+        let db = mod_data();
+        let (base, total) = (variable(&db, "eh:base"), variable(&db, "eh:total"));
+        let mut variables = Variables::from_stats(&db, [(base, 5.0)]);
+        variables.add_modifier(&db, base, flat(1.0).with_duration(1.0));
+        assert!(variables.has_timed_modifiers());
+        assert_eq!(variables.calculate_mut(&db, total), 13.0);
+
+        // Modifiers that are still running keep the cache
+        variables.tick_modifiers(0.5);
+        assert!(!variables.is_dirty());
+        assert_eq!(variables.modifiers(base)[0].duration, Some(0.5));
+
+        variables.tick_modifiers(0.5);
+        assert!(variables.is_dirty());
+        assert!(!variables.has_timed_modifiers());
+        assert_eq!(variables.calculate_mut(&db, total), 11.0);
+    }
+}
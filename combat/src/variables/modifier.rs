@@ -0,0 +1,176 @@
+use bevy::prelude::{Entity, Query, Res, Time};
+
+use crate::variables::Variables;
+
+/// Layer of the modifier, which decides how it stacks with the others
+///
+/// Layers are applied in the declaration order, on top of the value of the
+/// variable
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ModifierLayer {
+    /// Added to the value
+    Flat,
+    /// Summed with the other percent modifiers, with the value then scaled
+    /// by `1 + sum`, so `0.1` and `0.2` give a 30% bonus
+    PercentAdditive,
+    /// Multiplies the value, so `1.1` and `1.2` give a 32% bonus
+    Multiplicative,
+    /// Replaces the value, with the override of the highest priority
+    /// winning
+    Override,
+}
+
+/// Whatever applies the modifier, used to remove all of its modifiers at
+/// once
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ModifierSource {
+    /// Entity in the combat, such as an aura or a projectile
+    Entity(Entity),
+    /// Component installed into the ship, by its index among the components
+    /// of the ship build, so every copy of a component is removed on its own
+    Component(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Modifier {
+    pub layer: ModifierLayer,
+    pub value: f64,
+    pub source: ModifierSource,
+    /// Picks the override that wins, later modifiers win between the ones
+    /// of the same priority
+    pub priority: i32,
+    /// Remaining time in seconds, modifiers without a duration stay until
+    /// removed
+    pub duration: Option<f64>,
+}
+
+impl Modifier {
+    pub fn new(layer: ModifierLayer, value: f64, source: ModifierSource) -> Self {
+        Self {
+            layer,
+            value,
+            source,
+            priority: 0,
+            duration: None,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_duration(mut self, duration: f64) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+}
+
+/// Applies the layers of the modifiers to the value
+pub(super) fn resolve(value: f64, modifiers: &[Modifier]) -> f64 {
+    if modifiers.is_empty() {
+        return value;
+    }
+
+    let mut flat = 0.0;
+    let mut percent = 0.0;
+    let mut factor = 1.0;
+    let mut overridden: Option<&Modifier> = None;
+    for modifier in modifiers {
+        match modifier.layer {
+            ModifierLayer::Flat => flat += modifier.value,
+            ModifierLayer::PercentAdditive => percent += modifier.value,
+            ModifierLayer::Multiplicative => factor *= modifier.value,
+            ModifierLayer::Override => {
+                // Later modifiers win the ties
+                if overridden.is_none_or(|other| modifier.priority >= other.priority) {
+                    overridden = Some(modifier);
+                }
+            }
+        }
+    }
+
+    match overridden {
+        Some(modifier) => modifier.value,
+        None => (value + flat) * (1.0 + percent) * factor,
+    }
+}
+
+/// Counts the durations of the modifiers down, removing the expired ones
+pub fn expire_modifiers(mut variables: Query<&mut Variables>, time: Res<Time>) {
+    let delta = time.delta_seconds_f64();
+    for mut variables in variables.iter_mut() {
+        // Most entities don't have any timed modifiers, so change detection
+        // is only triggered for the ones that do
+        if variables.has_timed_modifiers() {
+            variables.tick_modifiers(delta);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::Entity;
+
+    use super::{resolve, Modifier, ModifierLayer, ModifierSource};
+
+    fn modifier(layer: ModifierLayer, value: f64) -> Modifier {
+        Modifier::new(layer, value, ModifierSource::Entity(Entity::PLACEHOLDER))
+    }
+
+    #[test]
+    fn no_modifiers_keep_the_value() {
+        assert_eq!(resolve(10.0, &[]), 10.0);
+    }
+
+    #[test]
+    fn layers_apply_in_order() {
+        let modifiers = [
+            modifier(ModifierLayer::Multiplicative, 2.0),
+            modifier(ModifierLayer::PercentAdditive, 0.25),
+            modifier(ModifierLayer::Flat, 3.0),
+            modifier(ModifierLayer::PercentAdditive, 0.5),
+            modifier(ModifierLayer::Flat, 2.0),
+        ];
+        // (10 + 3 + 2) * (1 + 0.25 + 0.5) * 2
+        assert_eq!(resolve(10.0, &modifiers), 52.5);
+    }
+
+    #[test]
+    fn multiplicative_modifiers_compound() {
+        let modifiers = [
+            modifier(ModifierLayer::Multiplicative, 1.5),
+            modifier(ModifierLayer::Multiplicative, 2.0),
+        ];
+        assert_eq!(resolve(10.0, &modifiers), 30.0);
+    }
+
+    #[test]
+    fn override_replaces_the_other_layers() {
+        let modifiers = [
+            modifier(ModifierLayer::Flat, 3.0),
+            modifier(ModifierLayer::Override, 1.0),
+            modifier(ModifierLayer::Multiplicative, 2.0),
+        ];
+        assert_eq!(resolve(10.0, &modifiers), 1.0);
+    }
+
+    #[test]
+    fn override_of_the_highest_priority_wins() {
+        let modifiers = [
+            modifier(ModifierLayer::Override, 1.0).with_priority(5),
+            modifier(ModifierLayer::Override, 2.0).with_priority(-1),
+        ];
+        assert_eq!(resolve(10.0, &modifiers), 1.0);
+    }
+
+    #[test]
+    fn later_override_wins_the_ties() {
+        let modifiers = [
+            modifier(ModifierLayer::Override, 1.0).with_priority(1),
+            modifier(ModifierLayer::Override, 2.0).with_priority(1),
+            modifier(ModifierLayer::Override, 3.0),
+        ];
+        assert_eq!(resolve(10.0, &modifiers), 2.0);
+    }
+}
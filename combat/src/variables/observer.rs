@@ -0,0 +1,221 @@
+use bevy::prelude::{Entity, Event, EventWriter, Query, Res, Resource};
+use nohash_hasher::IntMap;
+
+use ehce_core::database::model::VariableId;
+use ehce_core::mods::ModData;
+
+use crate::variables::Variables;
+
+/// Condition on the value of the variable, checked every time the variable
+/// is recalculated
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ObserverCondition {
+    /// Any change of the value
+    Changed,
+    /// Value reaching the threshold from below, or dropping under it
+    Threshold(f64),
+    /// Value dropping to zero or under it, such as health of a destroyed
+    /// unit
+    Zero,
+}
+
+impl ObserverCondition {
+    /// Whether the condition holds for the change of the value
+    ///
+    /// The first value of the variable has nothing to compare against, so
+    /// only [Zero](ObserverCondition::Zero) holds for it, letting entities
+    /// spawned without health get destroyed
+    fn holds(&self, old: Option<f64>, new: f64) -> bool {
+        let Some(old) = old else {
+            return *self == ObserverCondition::Zero && new <= 0.0;
+        };
+        match *self {
+            ObserverCondition::Changed => old != new,
+            ObserverCondition::Threshold(threshold) => (old < threshold) != (new < threshold),
+            ObserverCondition::Zero => old > 0.0 && new <= 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ObserverId(usize);
+
+/// Observers of the variables, shared by all entities tracking the observed
+/// variables
+#[derive(Debug, Default, Resource)]
+pub struct VariableObservers {
+    observers: IntMap<VariableId, Vec<(ObserverId, ObserverCondition)>>,
+    next_id: usize,
+}
+
+impl VariableObservers {
+    /// Registers the observer, which sends [VariableEvent] every time the
+    /// condition holds for the variable of any entity
+    pub fn observe(&mut self, variable: VariableId, condition: ObserverCondition) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers
+            .entry(variable)
+            .or_default()
+            .push((id, condition));
+        id
+    }
+
+    /// Removes the observer, returning whether it was registered
+    pub fn remove(&mut self, id: ObserverId) -> bool {
+        let mut removed = false;
+        self.observers.retain(|_, observers| {
+            let len = observers.len();
+            observers.retain(|(other, _)| *other != id);
+            removed |= observers.len() != len;
+            !observers.is_empty()
+        });
+        removed
+    }
+
+    /// Removes all observers
+    pub fn clear(&mut self) {
+        self.observers.clear();
+    }
+}
+
+/// Value of the observed variable met the condition of the observer
+#[derive(Debug, Clone, Event)]
+pub struct VariableEvent {
+    pub entity: Entity,
+    pub variable: VariableId,
+    pub observer: ObserverId,
+    /// Previously reported value, `None` for the first value of the
+    /// variable
+    pub old: Option<f64>,
+    pub new: f64,
+}
+
+/// Recalculates dirty variables of all entities, sending events for the
+/// observed variables that changed
+///
+/// Only the variables tracked by the entity are observed. The first value
+/// of the variable is reported without the old one, see
+/// [ObserverCondition::holds]
+pub fn recalculate_variables(
+    mut entities: Query<(Entity, &mut Variables)>,
+    observers: Res<VariableObservers>,
+    db: Res<ModData>,
+    mut events: EventWriter<VariableEvent>,
+) {
+    for (entity, mut variables) in entities.iter_mut() {
+        if !variables.is_dirty() {
+            continue;
+        }
+        variables.recalculate_dirty(&db);
+        for (variable, registered) in &observers.observers {
+            let Some((old, new)) = variables.report(*variable) else {
+                continue;
+            };
+            events.send_batch(
+                registered
+                    .iter()
+                    .filter(|(_, condition)| condition.holds(old, new))
+                    .map(|(observer, _)| VariableEvent {
+                        entity,
+                        variable: *variable,
+                        observer: *observer,
+                        old,
+                        new,
+                    }),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::{App, Entity, Events, Mut, Update};
+
+    use ehce_core::mods::ModData;
+
+    use super::super::test::{mod_data, variable};
+    use super::{
+        recalculate_variables, ObserverCondition, ObserverId, VariableEvent, VariableObservers,
+    };
+    use crate::variables::Variables;
+
+    #[test]
+    fn changed_holds_for_any_change() {
+        let condition = ObserverCondition::Changed;
+        assert!(condition.holds(Some(1.0), 2.0));
+        assert!(condition.holds(Some(1.0), -1.0));
+        assert!(!condition.holds(Some(1.0), 1.0));
+        assert!(!condition.holds(None, 1.0));
+    }
+
+    #[test]
+    fn threshold_holds_when_crossed_in_either_direction() {
+        let condition = ObserverCondition::Threshold(10.0);
+        assert!(condition.holds(Some(5.0), 10.0));
+        assert!(condition.holds(Some(15.0), 5.0));
+        assert!(!condition.holds(Some(10.0), 15.0));
+        assert!(!condition.holds(Some(5.0), 9.0));
+        assert!(!condition.holds(None, 5.0));
+        assert!(!condition.holds(None, 15.0));
+    }
+
+    #[test]
+    fn zero_holds_when_dropping_to_zero_or_starting_there() {
+        let condition = ObserverCondition::Zero;
+        assert!(condition.holds(Some(5.0), 0.0));
+        assert!(condition.holds(Some(5.0), -1.0));
+        assert!(!condition.holds(Some(0.0), -1.0));
+        assert!(!condition.holds(Some(-1.0), 5.0));
+        assert!(condition.holds(None, 0.0));
+        assert!(!condition.holds(None, 5.0));
+    }
+
+    /// Runs the app once, returning the events sent by the update
+    fn events(app: &mut App) -> Vec<(Entity, ObserverId, Option<f64>, f64)> {
+        app.update();
+        app.world
+            .resource_mut::<Events<VariableEvent>>()
+            .drain()
+            .map(|event| (event.entity, event.observer, event.old, event.new))
+            .collect()
+    }
+
+    #[test]
+    fn observers_receive_events_from_the_spawn_on() {
+        let db = mod_data();
+        let base = variable(&db, "eh:base");
+        let mut observers = VariableObservers::default();
+        let zero = observers.observe(base, ObserverCondition::Zero);
+        let changed = observers.observe(base, ObserverCondition::Changed);
+
+        let mut app = App::new();
+        app.add_event::<VariableEvent>()
+            .insert_resource(observers)
+            .add_systems(Update, recalculate_variables);
+        let dead = app
+            .world
+            .spawn(Variables::from_stats(&db, [(base, 0.0)]))
+            .id();
+        let alive = app
+            .world
+            .spawn(Variables::from_stats(&db, [(base, 5.0)]))
+            .id();
+        app.insert_resource(db);
+
+        assert_eq!(events(&mut app), [(dead, zero, None, 0.0)]);
+
+        app.world.resource_scope(|world, db: Mut<ModData>| {
+            let mut variables = world.get_mut::<Variables>(alive).unwrap();
+            variables.set(&db, base, 0.0);
+        });
+        assert_eq!(
+            events(&mut app),
+            [
+                (alive, zero, Some(5.0), 0.0),
+                (alive, changed, Some(5.0), 0.0)
+            ]
+        );
+        assert!(events(&mut app).is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+//! Small mod shared by the tests of the database and of the crates using it
+
+use std::path::{Path, PathBuf};
+
+use crate::model::{DataFormat, DatabaseAsset, DeserializationError, ModLayer, ModRegistry};
+
+/// Manifest of the `eh` mod, with [SETTINGS] as its defaults
+pub const MANIFEST: &str = r#"{
+  "type": "ModSettings",
+  "id": "eh",
+  "name": "Event Horizon",
+  "version": "0.1.0",
+  "defaults": {
+    "combatSettings": "eh:default"
+  }
+}"#;
+
+/// Combat settings with [FLEET] on both sides
+pub const SETTINGS: &str = r#"{
+  "type": "CombatSettings",
+  "id": "eh:default",
+  "playerFleet": "eh:fleet",
+  "enemyFleet": "eh:fleet"
+}"#;
+
+/// Fleet without any ships
+pub const FLEET: &str = r#"{
+  "type": "Fleet",
+  "id": "eh:fleet",
+  "builds": []
+}"#;
+
+/// Files of the `eh` mod, which starts with the smallest valid database of
+/// [MANIFEST], [SETTINGS] and [FLEET]
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    files: Vec<(PathBuf, String)>,
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Self {
+            files: vec![
+                (PathBuf::from("eh/mod.json"), MANIFEST.to_string()),
+                (PathBuf::from("eh/settings.json"), SETTINGS.to_string()),
+                (PathBuf::from("eh/fleet.json"), FLEET.to_string()),
+            ],
+        }
+    }
+}
+
+impl Fixture {
+    /// Adds the file to the mod, replacing the file with the same path
+    ///
+    /// Format of the file is picked by the extension of the path
+    pub fn file(mut self, path: impl Into<PathBuf>, text: impl Into<String>) -> Self {
+        let path = path.into();
+        let text = text.into();
+        match self.files.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, old)) => *old = text,
+            None => self.files.push((path, text)),
+        }
+        self
+    }
+
+    /// Files of the mod, with paths relative to the mods folder
+    pub fn files(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.files
+            .iter()
+            .map(|(path, text)| (path.as_path(), text.as_str()))
+    }
+
+    /// Parses all files of the mod
+    ///
+    /// Panics if any of the files can't be parsed
+    pub fn assets(&self) -> Vec<(PathBuf, DatabaseAsset)> {
+        self.files().map(|(path, text)| parse(path, text)).collect()
+    }
+
+    /// Builds the registry out of the mod
+    pub fn build(&self) -> Result<ModRegistry, DeserializationError> {
+        let assets = self.assets();
+        ModRegistry::build([layer("eh", &assets)])
+    }
+}
+
+/// Parses the file in the format picked by the extension of the path
+///
+/// Panics if the file can't be parsed
+pub fn parse(path: impl Into<PathBuf>, text: &str) -> (PathBuf, DatabaseAsset) {
+    let path = path.into();
+    let format = DataFormat::from_path(&path)
+        .unwrap_or_else(|| panic!("`{}` should have a known extension", path.display()));
+    let asset = DatabaseAsset::parse(&text.into(), format)
+        .unwrap_or_else(|err| panic!("`{}` should parse: {}", path.display(), err.message()));
+    (path, asset)
+}
+
+/// Layer of the mod with the name made of the parsed files, without images
+pub fn layer<'a>(name: &str, assets: &'a [(PathBuf, DatabaseAsset)]) -> ModLayer<'a> {
+    ModLayer {
+        name: name.to_string(),
+        items: assets
+            .iter()
+            .map(|(path, asset)| (path.clone(), asset))
+            .collect(),
+        images: vec![],
+    }
+}
@@ -1 +1,3 @@
+#[cfg(any(test, feature = "test_fixtures"))]
+pub mod fixture;
 pub mod model;
@@ -3,15 +3,17 @@ use std::collections::hash_map::Entry;
 use std::hash::Hash;
 use std::ops::Index;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use bevy::{asset::Handle, render::texture::Image};
 use duplicate::duplicate_item;
 use itertools::Itertools;
 use paste::paste;
-use rustc_hash::FxHashMap;
-use schemars::schema::RootSchema;
+use rustc_hash::{FxHashMap, FxHashSet};
+use schemars::schema::{InstanceType, RootSchema, SchemaObject};
 use strum_macros::{Display, EnumDiscriminants, EnumIs};
 
+use mod_settings::{Defaults, ModSettings};
 use serialization::RegistryEntry;
 use slabmap::{SlabMap, SlabMapId, SlabMapKeyOrUntypedId, SlabMapUntypedId};
 
@@ -20,19 +22,57 @@ pub mod component;
 pub mod component_stats;
 pub mod device;
 pub mod fleet;
+pub mod migration;
+pub mod mod_settings;
 pub mod ship;
 pub mod ship_build;
 pub mod variable;
 
 pub mod formula;
 
+mod bundle;
+mod cache;
+mod dependencies;
+mod export;
+mod format;
+mod lint;
+mod namespace;
 mod serialization;
+mod unknown_fields;
+mod variable_order;
 
+pub use bundle::DatabaseFile;
+pub use cache::FileCache;
+pub use dependencies::DependencyGraph;
+pub use export::{export_schemas, ExportError};
+pub use format::{DataFormat, FormatError};
+pub use lint::{Lint, LintKind, LintSettings, LintSeverity};
+pub use migration::{DataVersion, CURRENT_DATA_VERSION};
+pub use namespace::{
+    qualify_id, validate_namespace, NamespacedId, QualifiedId, NAMESPACE_SEPARATOR,
+};
+pub use serialization::{
+    DeserializationError, DeserializationErrorKind, DeserializationErrorStackItem, ErrorLocation,
+    ItemReference, ItemReferences,
+};
+pub use unknown_fields::UnknownField;
+pub use variable_order::VariableOrder;
+
+/// Database file loaded from the disk
+///
+/// Items are kept raw until the data version of their mod is known, see
+/// [`DatabaseAsset::load`]
 #[derive(
-    Debug, serde::Deserialize, serde::Serialize, bevy::asset::Asset, bevy::reflect::TypePath,
+    Debug, serde::Serialize, serde::Deserialize, bevy::asset::Asset, bevy::reflect::TypePath,
 )]
 #[serde(transparent)]
-pub struct DatabaseAsset(pub DatabaseItemSerialized);
+pub struct DatabaseAsset(
+    pub serde_json::Value,
+    /// Text of the file the item was loaded from, used to point errors to
+    /// the offending values
+    #[serde(skip)]
+    pub Option<Arc<str>>,
+);
 
 pub trait DatabaseItemTrait {
     fn id(&self) -> SlabMapUntypedId;
@@ -74,6 +114,24 @@ fn insert_serialized<T: DatabaseItemSerializedTrait>(
     }
 }
 
+/// Items of a single kind replaced or added by a hot reload
+#[derive(Debug)]
+struct ReleasedKind<T> {
+    /// Items present before the reload, along with their slots
+    replaced: Vec<(SlabMapId<Option<T>>, T)>,
+    /// Keys of the items that weren't present before the reload
+    added: Vec<ItemId>,
+}
+
+impl<T> Default for ReleasedKind<T> {
+    fn default() -> Self {
+        Self {
+            replaced: Vec::new(),
+            added: Vec::new(),
+        }
+    }
+}
+
 fn convert_raw<T>(raw: ModelStore<Option<T>>) -> ModelStore<T> {
     let mut out: ModelStore<T> = Default::default();
     for (key, id, value) in raw.into_iter().sorted_by_key(|(_, id, _)| *id) {
@@ -85,6 +143,140 @@ fn convert_raw<T>(raw: ModelStore<Option<T>>) -> ModelStore<T> {
     out
 }
 
+impl RawModRegistry {
+    /// Collects items of a single mod, along with the deletions declared by it,
+    /// recording the files they come from
+    ///
+    /// IDs of the items are qualified with the namespace of the mod. Items and
+    /// deletions must be unique within a single mod, duplicates and invalid
+    /// IDs are reported to `errors` and skipped
+    #[allow(clippy::type_complexity)]
+    fn from_layer<'a>(
+        namespace: &str,
+        items: impl IntoIterator<Item = (&'a Path, &'a DatabaseFile)>,
+        files: &mut ItemFiles,
+        errors: &mut Vec<serialization::DeserializationError>,
+    ) -> (Self, FxHashMap<(DatabaseItemKind, ItemId), ItemSource>) {
+        let mut raws = Self::default();
+        let mut deletions = FxHashMap::default();
+        let mut declared = FxHashMap::default();
+        for (path, file) in items {
+            for (position, item) in file.items() {
+                let source = ItemSource {
+                    path: path.to_path_buf(),
+                    position,
+                };
+                let mut item = item.clone();
+                if let Err(err) = item.qualify(namespace) {
+                    errors.push(source.context(err.context(
+                        serialization::DeserializationErrorStackItem::Item(
+                            item.id().clone(),
+                            item.kind(),
+                        ),
+                    )));
+                    continue;
+                }
+                let key = (item.kind(), item.id().clone());
+                if let Some(previous) = declared.insert(key.clone(), source.path.clone()) {
+                    errors.push(
+                        source.context(
+                            serialization::DeserializationError::from(
+                                serialization::DeserializationErrorKind::DuplicateItem {
+                                    id: key.1.clone(),
+                                    kind: key.0,
+                                    path_a: previous,
+                                    path_b: source.path.clone(),
+                                },
+                            )
+                            .context(serialization::DeserializationErrorStackItem::Field("id"))
+                            .context(
+                                serialization::DeserializationErrorStackItem::Item(key.1, key.0),
+                            ),
+                        ),
+                    );
+                } else if item.is_delete() {
+                    deletions.insert(key, source);
+                } else {
+                    raws.insert(item)
+                        .unwrap_or_else(|_| unreachable!("Duplicates should be caught above"));
+                    files.insert(key, source);
+                }
+            }
+        }
+
+        (raws, deletions)
+    }
+}
+
+/// File the item is declared in
+#[derive(Debug, Clone)]
+struct ItemSource {
+    path: PathBuf,
+    /// Position of the item inside the file holding multiple items
+    position: Option<serialization::DeserializationErrorStackItem>,
+}
+
+impl ItemSource {
+    /// Adds the file and the position of the item inside it to the error
+    /// context
+    fn context(
+        &self,
+        err: serialization::DeserializationError,
+    ) -> serialization::DeserializationError {
+        err.at_position(self.position.clone()).context(
+            serialization::DeserializationErrorStackItem::File(self.path.clone()),
+        )
+    }
+}
+
+/// Files of the items along with their text, used to point errors to the
+/// values they originate from
+#[derive(Debug, Default)]
+struct ItemFiles {
+    files: FxHashMap<(DatabaseItemKind, ItemId), ItemSource>,
+    sources: FxHashMap<PathBuf, Arc<str>>,
+}
+
+impl ItemFiles {
+    /// Records the file of the item, overriding the file of the item with the
+    /// same ID
+    fn insert(&mut self, key: (DatabaseItemKind, ItemId), source: ItemSource) {
+        self.files.insert(key, source);
+    }
+
+    /// Records the text of the file the asset was loaded from
+    fn insert_source(&mut self, path: &Path, asset: &DatabaseAsset) {
+        if let Some(source) = &asset.1 {
+            self.sources.insert(path.to_path_buf(), source.clone());
+        }
+    }
+
+    /// Adds files of the failing items to the context of the errors that
+    /// don't have one yet, and locates the offending values in them
+    fn locate(
+        &self,
+        err: serialization::DeserializationError,
+    ) -> serialization::DeserializationError {
+        serialization::DeserializationError::combine(err.flatten().into_iter().map(|mut err| {
+            if err.file().is_none() {
+                let Some(source) = err
+                    .item()
+                    .and_then(|(id, kind)| self.files.get(&(kind, id.clone())))
+                else {
+                    return err;
+                };
+                err = source.context(err);
+            }
+            let file = err.file().expect("File should be present").clone();
+            match self.sources.get(&file) {
+                Some(text) => err.with_location(file, text.clone()),
+                None => err,
+            }
+        }))
+        .expect("Flattened error should not be empty")
+    }
+}
+
 fn drain_one<T>(items: &mut FxHashMap<ItemId, T>) -> Option<T> {
     if let Some(key) = items.keys().next() {
         if let Some(value) = items.remove(&key.clone()) {
@@ -142,7 +334,7 @@ macro_rules! registry {
     ($($name:ident: $ty:ty),*$(,)?) => {
         paste! {
             #[derive(Debug, Clone, EnumDiscriminants, EnumIs)]
-            #[strum_discriminants(derive(Display, Hash))]
+            #[strum_discriminants(derive(Display, Hash, serde::Serialize, serde::Deserialize, schemars::JsonSchema))]
             #[strum_discriminants(name(DatabaseItemKind))]
             pub enum DatabaseItem {
                 $(
@@ -168,6 +360,24 @@ macro_rules! registry {
                 }
             }
 
+            impl DatabaseItemKind {
+                /// All kinds, in the declaration order
+                pub const ALL: &'static [DatabaseItemKind] = &[
+                    $(
+                        Self::[<$name:camel>],
+                    )*
+                ];
+
+                /// Name of the kind as used in the model declarations
+                pub fn name(&self) -> &'static str {
+                    match self {
+                        $(
+                            Self::[<$name:camel>] => stringify!($name),
+                        )*
+                    }
+                }
+            }
+
             impl serialization::ModelDeserializableFallbackType for DatabaseItem {
                 type Serialized = DatabaseItemSerialized;
             }
@@ -224,11 +434,26 @@ macro_rules! registry {
                 }
             )*
 
-            #[derive(Debug, Default)]
+            #[derive(Debug, Default, Clone)]
             pub struct ModRegistry {
                 $(
                     pub $name: ModelStore<RegistryEntry<$ty>>,
                 )*
+                assets: ModAssets,
+                load_order: Vec<ModSettingsId>,
+                inheritance: FxHashSet<(DatabaseItemKind, ItemId)>,
+                dependencies: DependencyGraph,
+                variable_order: VariableOrder,
+                /// Namespaces of the mods by their names
+                namespaces: FxHashMap<String, ItemId>,
+                /// Data versions of the mods by their namespaces
+                data_versions: FxHashMap<ItemId, DataVersion>,
+                /// Named constants declared by the manifests, by the
+                /// namespaces of the mods
+                constants: FxHashMap<ItemId, FxHashMap<String, f64>>,
+                /// Fields not declared by the items, by the files containing
+                /// them
+                unknown_fields: FxHashMap<PathBuf, Vec<UnknownField>>,
             }
 
             impl ModRegistry {
@@ -246,6 +471,45 @@ macro_rules! registry {
                         )*
                     }
                 }
+                pub fn registry_id(&self, kind: DatabaseItemKind, key: &str) -> Option<RegistryId> {
+                    match kind {
+                        $(
+                            DatabaseItemKind::[<$name:camel>] => self.$name.key_to_id(key).map(RegistryId::from),
+                        )*
+                    }
+                }
+                /// IDs of all items of the kind, along with their keys
+                pub fn keys(&self, kind: DatabaseItemKind) -> Vec<(RegistryId, &ItemId)> {
+                    match kind {
+                        $(
+                            DatabaseItemKind::[<$name:camel>] => self.$name.iter().map(|(id, _)| {
+                                let key = self.$name.id_to_key(id).expect("Item should have a key");
+                                (RegistryId::from(id), key)
+                            }).collect(),
+                        )*
+                    }
+                }
+                /// Restores the serialized form of the item
+                pub fn serialize_item(&self, id: RegistryId) -> Option<DatabaseItemSerialized> {
+                    match id.kind {
+                        $(
+                            DatabaseItemKind::[<$name:camel>] => self.$name.get_by_untyped_id(id.id).map(|item| {
+                                DatabaseItemSerialized::[<$name:camel>](serialization::ModelSerializable::serialize(item, self))
+                            }),
+                        )*
+                    }
+                }
+                /// Restores the serialized form of every item, grouped by
+                /// kind in the declaration order
+                pub fn serialize_all(&self) -> Vec<DatabaseItemSerialized> {
+                    let mut items = Vec::new();
+                    $(
+                        items.extend(self.$name.values().map(|item| {
+                            DatabaseItemSerialized::[<$name:camel>](serialization::ModelSerializable::serialize(item, self))
+                        }));
+                    )*
+                    items
+                }
             }
         }
     };
@@ -269,63 +533,597 @@ impl<'a> DatabaseItemRef<'a> {
     }
 }
 
+/// Database items and images of a single mod
+///
+/// Mods are layered on top of each other in the load order, with later mods
+/// being able to override, add or delete items of the earlier ones
+#[derive(Debug, Default)]
+pub struct ModLayer<'a> {
+    /// Name of the mod, used for error reporting
+    pub name: String,
+    /// Database files, each holding one or more items
+    pub items: Vec<(PathBuf, &'a DatabaseAsset)>,
+    pub images: Vec<(PathBuf, Handle<Image>)>,
+}
+
+impl ModLayer<'_> {
+    /// ID of the mod along with the IDs of its dependencies, or `None` if
+    /// the manifest can't be read
+    fn manifest(&self) -> Option<(ItemId, Vec<ItemId>)> {
+        let version = migration::mod_data_version(
+            self.items
+                .iter()
+                .map(|(path, asset)| (path.as_path(), &asset.0)),
+        )
+        .ok()?
+        .unwrap_or(0);
+        self.items.iter().find_map(|(_, asset)| {
+            let (file, _) = asset.load(version).ok()?;
+            let manifest = file.items().find_map(|(_, item)| match item {
+                DatabaseItemSerialized::ModSettings(manifest) => Some(manifest),
+                _ => None,
+            })?;
+            let dependencies = manifest
+                .data
+                .complete()
+                .map(|data| data.dependencies().cloned().collect())
+                .unwrap_or_default();
+            Some((manifest.id.clone(), dependencies))
+        })
+    }
+}
+
+/// Order the layers have to be loaded in, as their indices, with every mod
+/// coming after its dependencies and the given order kept otherwise
+///
+/// Broken manifests, missing dependencies and dependency cycles are left
+/// for [`ModRegistry::build`] to report
+pub fn load_order(layers: &[ModLayer]) -> Vec<usize> {
+    let mut pending: Vec<(usize, Option<ItemId>, Vec<ItemId>)> = layers
+        .iter()
+        .enumerate()
+        .map(|(i, layer)| match layer.manifest() {
+            Some((id, dependencies)) => (i, Some(id), dependencies),
+            None => (i, None, Vec::new()),
+        })
+        .collect();
+
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|(_, _, dependencies)| {
+                dependencies.iter().all(|dependency| {
+                    !pending
+                        .iter()
+                        .any(|(_, id, _)| id.as_ref() == Some(dependency))
+                })
+            })
+            // Cycles are broken at the earliest of the remaining mods
+            .unwrap_or(0);
+        ordered.push(pending.remove(ready).0);
+    }
+    ordered
+}
+
 impl ModRegistry {
+    /// Builds the registry out of the mod layers, in the load order
+    ///
+    /// Duplicate items and images are only reported within a single layer,
+    /// later layers silently override items and images of the earlier ones.
+    ///
+    /// Errors don't stop the build, all of them are collected and returned
+    /// together, each pointing to the file it originates from
     pub fn build<'a>(
-        items: impl IntoIterator<Item = (impl AsRef<Path>, &'a DatabaseAsset)>,
-        images: impl IntoIterator<Item = (impl AsRef<Path>, Handle<Image>)>,
+        layers: impl IntoIterator<Item = ModLayer<'a>>,
     ) -> Result<Self, serialization::DeserializationError> {
         let mut raws = RawModRegistry::default();
-        for (_path, item) in items.into_iter() {
-            if let Err(item) = raws.insert(item.0.clone()) {
-                return Err(serialization::DeserializationErrorKind::DuplicateItem(
-                    item.id().clone(),
-                    item.kind(),
-                )
-                .into());
-            }
-        }
-
         let mut assets = ModAssets::default();
+        let mut manifests: Vec<(ItemId, String)> = Vec::new();
+        let mut namespaces = FxHashMap::default();
+        let mut data_versions = FxHashMap::default();
+        let mut unknown_fields = FxHashMap::default();
+        let mut files = ItemFiles::default();
+        let mut errors = Vec::new();
+        for ModLayer {
+            name,
+            items,
+            images,
+        } in layers
+        {
+            for (path, asset) in &items {
+                files.insert_source(path, asset);
+            }
+            // Items can only be read once the data version of the mod is
+            // known, and a broken version would fail all of them
+            let version = match migration::mod_data_version(
+                items.iter().map(|(path, asset)| (path.as_path(), &asset.0)),
+            ) {
+                Ok(version) => version.unwrap_or(0),
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            let loaded = load_files(&items, version, &mut unknown_fields, &mut errors);
 
-        images
-            .into_iter()
-            .try_for_each(|(path, image): (_, Handle<Image>)| {
-                let path: &Path = path.as_ref();
-                let Some(name) = path.file_name() else {
-                    return Err(serialization::DeserializationErrorKind::MissingName(
-                        path.to_path_buf(),
-                    ));
-                };
-
-                let Some(name) = name.to_str() else {
-                    return Err(serialization::DeserializationErrorKind::NonUtf8Path(
-                        path.to_path_buf(),
-                    ));
-                };
-
-                match assets.images.entry(name.to_ascii_lowercase()) {
-                    Entry::Occupied(e) => {
-                        return Err(serialization::DeserializationErrorKind::DuplicateImage {
-                            name: name.to_string(),
-                            path_a: e.get().0.clone(),
-                            path_b: path.to_path_buf(),
-                        });
+            // Manifest has to be found first, since its ID is the namespace
+            // of all other items of the mod
+            let ids = loaded
+                .iter()
+                .flat_map(|(_, file)| file.items())
+                .filter(|(_, item)| item.is_mod_settings())
+                .map(|(_, item)| item.id())
+                .unique();
+            let namespace = match ids.at_most_one() {
+                Ok(Some(id)) => match manifests.iter().find(|(loaded, _)| loaded == id) {
+                    Some((_, loaded_by)) => {
+                        errors.push(
+                            serialization::DeserializationErrorKind::DuplicateMod {
+                                id: id.clone(),
+                                name_a: loaded_by.clone(),
+                                name_b: name.clone(),
+                            }
+                            .into(),
+                        );
+                        None
                     }
-                    Entry::Vacant(e) => {
-                        e.insert((path.to_path_buf(), image));
+                    None => {
+                        manifests.push((id.clone(), name.clone()));
+                        Some(id.clone())
                     }
+                },
+                Ok(None) => {
+                    errors.push(
+                        serialization::DeserializationErrorKind::MissingManifest(name.clone())
+                            .into(),
+                    );
+                    None
                 }
+                Err(mut ids) => {
+                    errors.push(
+                        serialization::DeserializationErrorKind::MultipleManifests {
+                            name: name.clone(),
+                            id_a: ids.next().expect("Should have multiple items").clone(),
+                            id_b: ids.next().expect("Should have multiple items").clone(),
+                        }
+                        .into(),
+                    );
+                    None
+                }
+            };
+            // Broken manifests are already reported, so the mod name stands
+            // in for the namespace to check the rest of the items anyway
+            let namespace = namespace.unwrap_or_else(|| name.clone());
+            let (layer_raws, deletions) = RawModRegistry::from_layer(
+                &namespace,
+                loaded.iter().map(|(path, file)| (path.as_path(), file)),
+                &mut files,
+                &mut errors,
+            );
+            data_versions.insert(namespace.clone(), version);
+            namespaces.insert(name, namespace);
+            for ((kind, id), source) in deletions {
+                if !raws.remove(kind, &id) {
+                    errors.push(source.context(
+                        serialization::DeserializationErrorKind::MissingItem(id, kind).into(),
+                    ));
+                }
+            }
+            raws.extend(layer_raws);
+            match ModAssets::from_images(images) {
+                Ok(layer_assets) => assets.images.extend(layer_assets.images),
+                Err(err) => errors.push(err),
+            }
+        }
 
-                Ok(())
-            })?;
+        let inheritance = raws.resolve_inheritance().unwrap_or_else(|err| {
+            errors.push(err);
+            Default::default()
+        });
+        // Constants are compiled into the formulas, so they have to be known
+        // before any item is deserialized
+        let constants = raws
+            .mod_settings
+            .iter()
+            .filter_map(|(id, manifest)| Some((id.clone(), manifest_constants(manifest)?.clone())))
+            .collect();
+        errors.extend(raws.mod_settings.values().filter_map(validate_constants));
+
+        // Deserialization errors caused by the broken layering would only
+        // obscure the real ones
+        if let Some(err) = serialization::DeserializationError::combine(errors) {
+            return Err(files.locate(err));
+        }
 
         let partial = PartialModRegistry {
             raw: raws,
             assets,
+            constants,
             ..Default::default()
         };
 
-        partial.deserialize()
+        let mut registry = partial.deserialize().map_err(|err| files.locate(err))?;
+        registry.inheritance = inheritance;
+        registry.namespaces = namespaces;
+        registry.data_versions = data_versions;
+        registry.unknown_fields = unknown_fields;
+        registry.load_order = serialization::collect_all(manifests.into_iter().map(|(id, _)| {
+            registry.mod_settings.key_to_id(&id).ok_or_else(|| {
+                serialization::DeserializationError::from(
+                    serialization::DeserializationErrorKind::MissingItem(
+                        id,
+                        DatabaseItemKind::ModSettings,
+                    ),
+                )
+            })
+        }))?;
+        registry.variable_order =
+            VariableOrder::build(&registry).map_err(|err| files.locate(err))?;
+        registry
+            .validate_manifests()
+            .map_err(|err| files.locate(err))?;
+
+        Ok(registry)
+    }
+
+    /// Re-deserializes provided items into the registry, keeping IDs of all
+    /// already present items stable
+    ///
+    /// Items that are not yet present in the registry get added to it. Since
+    /// other items only store IDs of their dependencies, they observe the
+    /// updated data without being deserialized again.
+    ///
+    /// Layers are matched to the loaded mods by name, and only their items
+    /// are reloaded, images are left as they are. Manifests can't change the
+    /// constants or the data version of their mods, since that affects all
+    /// items of the mod.
+    ///
+    /// Registry is left untouched if any of the items fail to deserialize.
+    /// Returns IDs of all reloaded items, along with their files
+    pub fn hot_reload<'a>(
+        &mut self,
+        layers: impl IntoIterator<Item = ModLayer<'a>>,
+    ) -> Result<Vec<(PathBuf, RegistryId)>, serialization::DeserializationError> {
+        let mut raws = RawModRegistry::default();
+        let mut files = ItemFiles::default();
+        let mut unknown_fields = self.unknown_fields.clone();
+        let mut errors = Vec::new();
+        for ModLayer { name, items, .. } in layers {
+            for (path, asset) in &items {
+                files.insert_source(path, asset);
+                unknown_fields.remove(path);
+            }
+            let Some(namespace) = self.namespaces.get(&name) else {
+                errors.push(serialization::DeserializationErrorKind::UnknownMod(name).into());
+                continue;
+            };
+            // Files of the mod are migrated from its data version, so all of
+            // them have to be loaded again once the manifest changes it
+            let version = self.data_versions.get(namespace).copied().unwrap_or(0);
+            match migration::mod_data_version(
+                items.iter().map(|(path, asset)| (path.as_path(), &asset.0)),
+            ) {
+                Ok(Some(declared)) if declared != version => {
+                    errors.push(
+                        serialization::DeserializationErrorKind::UnsupportedDataVersionChange(
+                            namespace.clone(),
+                        )
+                        .into(),
+                    );
+                    continue;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            }
+            let loaded = load_files(&items, version, &mut unknown_fields, &mut errors);
+            let (layer_raws, deletions) = RawModRegistry::from_layer(
+                namespace,
+                loaded.iter().map(|(path, file)| (path.as_path(), file)),
+                &mut files,
+                &mut errors,
+            );
+            for ((kind, id), source) in deletions {
+                errors.push(source.context(
+                    serialization::DeserializationErrorKind::UnsupportedDeletion(id, kind).into(),
+                ));
+            }
+            // Manifest is a singleton of the mod, so the only one it may
+            // reload is its own
+            for id in layer_raws.mod_settings.keys().filter(|id| *id != namespace) {
+                errors.push(
+                    serialization::DeserializationErrorKind::MultipleManifests {
+                        name: name.clone(),
+                        id_a: namespace.clone(),
+                        id_b: id.clone(),
+                    }
+                    .into(),
+                );
+            }
+            raws.extend(layer_raws);
+        }
+        for manifest in raws.mod_settings.values() {
+            if manifest_constants(manifest) != self.constants.get(&manifest.id) {
+                errors.push(
+                    serialization::DeserializationErrorKind::UnsupportedConstantChange(
+                        manifest.id.clone(),
+                    )
+                    .into(),
+                );
+            }
+        }
+        for (kind, id) in files.files.keys() {
+            if raws.extends(*kind, id) || self.has_inheritance(*kind, id) {
+                errors.push(
+                    serialization::DeserializationErrorKind::UnsupportedInheritance(
+                        id.clone(),
+                        *kind,
+                    )
+                    .into(),
+                );
+            }
+        }
+        if let Some(err) = serialization::DeserializationError::combine(errors) {
+            return Err(files.locate(err));
+        }
+
+        // Items are deserialized in place, with the replaced ones put back
+        // if anything fails
+        let mut partial = PartialModRegistry::take_from(self);
+        partial.raw = raws;
+        let released = partial.release_raw();
+        if let Err(err) = partial.deserialize_raw() {
+            partial.restore(released);
+            partial.put_back(self);
+            return Err(files.locate(err));
+        }
+        partial.put_back(self);
+
+        let validated = VariableOrder::build(self)
+            .map_err(|err| files.locate(err))
+            .and_then(|order| {
+                self.validate_manifests()
+                    .map_err(|err| files.locate(err))
+                    .map(|_| order)
+            });
+        match validated {
+            Ok(order) => self.variable_order = order,
+            Err(err) => {
+                let mut partial = PartialModRegistry::take_from(self);
+                partial.restore(released);
+                partial.put_back(self);
+                return Err(err);
+            }
+        }
+        self.unknown_fields = unknown_fields;
+
+        Ok(files
+            .files
+            .into_iter()
+            .filter_map(|((kind, id), source)| Some((source.path, self.registry_id(kind, &id)?)))
+            .collect())
+    }
+}
+
+type ManifestSerialized =
+    <RegistryEntry<ModSettings> as serialization::ModelDeserializableFallbackType>::Serialized;
+
+/// Constants declared by the manifest, or `None` if the manifest extends
+/// another one and isn't filled in yet
+fn manifest_constants(manifest: &ManifestSerialized) -> Option<&FxHashMap<String, f64>> {
+    match &manifest.data {
+        serialization::ItemDataSerialized::Complete(data) => Some(data.constants()),
+        serialization::ItemDataSerialized::Inherited { .. } => None,
+    }
+}
+
+/// Checks that formulas can refer to all constants of the manifest by their
+/// names
+fn validate_constants(
+    manifest: &ManifestSerialized,
+) -> Option<serialization::DeserializationError> {
+    let errors = manifest_constants(manifest)?
+        .keys()
+        .filter(|name| {
+            let mut chars = name.chars();
+            let valid = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            !valid || formula::is_builtin(name)
+        })
+        .map(|name| {
+            serialization::DeserializationError::from(
+                serialization::DeserializationErrorKind::BadConstantName(name.clone()),
+            )
+            .context(serialization::DeserializationErrorStackItem::MapEntry(
+                name.clone(),
+            ))
+            .context(serialization::DeserializationErrorStackItem::Field(
+                "constants",
+            ))
+            .context(serialization::DeserializationErrorStackItem::Item(
+                manifest.id.clone(),
+                DatabaseItemKind::ModSettings,
+            ))
+        });
+    serialization::DeserializationError::combine(errors)
+}
+
+/// Upgrades the files of the mod from its data version and deserializes
+/// them, recording the fields none of their items declare
+///
+/// Files that fail to load are reported to `errors` and skipped
+fn load_files(
+    items: &[(PathBuf, &DatabaseAsset)],
+    version: DataVersion,
+    unknown_fields: &mut FxHashMap<PathBuf, Vec<UnknownField>>,
+    errors: &mut Vec<serialization::DeserializationError>,
+) -> Vec<(PathBuf, DatabaseFile)> {
+    items
+        .iter()
+        .filter_map(|(path, asset)| match asset.load(version) {
+            Ok((file, unknown)) => {
+                if !unknown.is_empty() {
+                    unknown_fields.insert(path.clone(), unknown);
+                }
+                Some((path.clone(), file))
+            }
+            Err(err) => {
+                errors.push(
+                    err.context(serialization::DeserializationErrorStackItem::File(
+                        path.clone(),
+                    )),
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+impl ModRegistry {
+    /// Manifests of all loaded mods, in the load order
+    pub fn load_order(&self) -> impl Iterator<Item = &RegistryEntry<ModSettings>> {
+        self.load_order.iter().map(|id| &self.mod_settings[*id])
+    }
+
+    /// References between the items, such as components used by ship builds
+    /// or variables used in formulas
+    pub fn dependencies(&self) -> &DependencyGraph {
+        &self.dependencies
+    }
+
+    /// Order in which the variables can be evaluated, dependencies first
+    pub fn variable_order(&self) -> &VariableOrder {
+        &self.variable_order
+    }
+
+    /// Namespace of the mod with the name, which is the ID of its manifest
+    pub fn namespace(&self, mod_name: &str) -> Option<&str> {
+        self.namespaces.get(mod_name).map(String::as_str)
+    }
+
+    /// Qualified form of the ID written in the mod with the name, or `None`
+    /// if the mod is not loaded or the ID is invalid
+    pub fn qualified_id(&self, mod_name: &str, kind: DatabaseItemKind, id: &str) -> Option<ItemId> {
+        qualify_id(id, kind, Some(self.namespace(mod_name)?))
+            .ok()
+            .map(ItemId::from)
+    }
+
+    /// Data version the files of the mod with the name are written in, or
+    /// `None` if the mod is not loaded
+    pub fn data_version(&self, mod_name: &str) -> Option<DataVersion> {
+        self.data_versions.get(self.namespace(mod_name)?).copied()
+    }
+
+    /// Whether the item is a manifest declaring other constants than the
+    /// loaded one, which requires formulas of all items to be compiled again
+    pub fn constants_changed(&self, item: &DatabaseItemSerialized) -> bool {
+        match item {
+            DatabaseItemSerialized::ModSettings(manifest) => {
+                manifest_constants(manifest) != self.constants.get(&manifest.id)
+            }
+            _ => false,
+        }
+    }
+
+    /// Fields of the loaded files that none of their items declare, by the
+    /// paths of the files
+    pub fn unknown_fields(&self) -> impl Iterator<Item = (&Path, &UnknownField)> {
+        self.unknown_fields
+            .iter()
+            .flat_map(|(path, fields)| fields.iter().map(move |field| (path.as_path(), field)))
+    }
+
+    /// Whether the item extends another item or is extended by one
+    pub fn has_inheritance(&self, kind: DatabaseItemKind, id: &str) -> bool {
+        self.inheritance.contains(&(kind, id.to_string()))
+    }
+
+    /// Defaults declared by the last mod in the load order that has them
+    pub fn defaults(&self) -> Option<&Defaults> {
+        self.load_order
+            .iter()
+            .rev()
+            .find_map(|id| self.mod_settings[*id].data.defaults.as_ref())
+    }
+
+    /// Checks that every mod is loaded after all of its dependencies with
+    /// the matching versions, and that at least one mod declares defaults
+    fn validate_manifests(&self) -> Result<(), serialization::DeserializationError> {
+        let mut errors = Vec::new();
+        for (position, id) in self.load_order.iter().enumerate() {
+            let mod_id = self
+                .mod_settings
+                .id_to_key(*id)
+                .expect("Load order should only contain registered mods");
+            for (dependency, requirement) in &self.mod_settings[*id].data.dependencies {
+                let in_manifest = |err: serialization::DeserializationErrorKind| {
+                    serialization::DeserializationError::from(err)
+                        .context(serialization::DeserializationErrorStackItem::MapEntry(
+                            dependency.clone(),
+                        ))
+                        .context(serialization::DeserializationErrorStackItem::Field(
+                            "dependencies",
+                        ))
+                        .context(serialization::DeserializationErrorStackItem::Item(
+                            mod_id.clone(),
+                            DatabaseItemKind::ModSettings,
+                        ))
+                };
+                let Some(loaded) = self.load_order[..position]
+                    .iter()
+                    .find(|id| self.mod_settings.id_to_key(**id) == Some(dependency))
+                else {
+                    errors.push(in_manifest(
+                        serialization::DeserializationErrorKind::MissingDependency {
+                            mod_id: mod_id.clone(),
+                            dependency: dependency.clone(),
+                            requirement: requirement.clone(),
+                        },
+                    ));
+                    continue;
+                };
+                let version = &self.mod_settings[*loaded].data.version;
+                if !requirement.matches(version) {
+                    errors.push(in_manifest(
+                        serialization::DeserializationErrorKind::DependencyVersionMismatch {
+                            mod_id: mod_id.clone(),
+                            dependency: dependency.clone(),
+                            requirement: requirement.clone(),
+                            version: Box::new(version.clone()),
+                        },
+                    ));
+                }
+            }
+        }
+
+        if self.defaults().is_none() {
+            // Reported at the last loaded mod, since defaults of any mod
+            // would do
+            let err = serialization::DeserializationError::from(
+                serialization::DeserializationErrorKind::MissingDefaults,
+            );
+            errors.push(match self.load_order.last() {
+                Some(id) => err.context(serialization::DeserializationErrorStackItem::Item(
+                    self.mod_settings
+                        .id_to_key(*id)
+                        .expect("Load order should only contain registered mods")
+                        .clone(),
+                    DatabaseItemKind::ModSettings,
+                )),
+                None => err,
+            });
+        }
+
+        match serialization::DeserializationError::combine(errors) {
+            None => Ok(()),
+            Some(err) => Err(err),
+        }
     }
 }
 
@@ -335,11 +1133,70 @@ impl DatabaseItemSerialized {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct ModAssets {
     pub images: FxHashMap<String, (PathBuf, Handle<Image>)>,
 }
 
+impl ModAssets {
+    /// File name the image is referred to by in the items
+    fn image_name(&self, image: &Handle<Image>) -> Option<&str> {
+        self.images
+            .values()
+            .find(|(_, handle)| handle.id() == image.id())
+            .and_then(|(path, _)| path.file_name()?.to_str())
+    }
+
+    fn from_images(
+        images: impl IntoIterator<Item = (PathBuf, Handle<Image>)>,
+    ) -> Result<Self, serialization::DeserializationError> {
+        let mut assets = ModAssets::default();
+        let mut errors: Vec<serialization::DeserializationError> = Vec::new();
+
+        for (path, image) in images {
+            let Some(name) = path.file_name() else {
+                errors.push(serialization::DeserializationErrorKind::MissingName(path).into());
+                continue;
+            };
+
+            let Some(name) = name.to_str() else {
+                errors.push(serialization::DeserializationErrorKind::NonUtf8Path(path).into());
+                continue;
+            };
+
+            match assets.images.entry(name.to_ascii_lowercase()) {
+                Entry::Occupied(e) => {
+                    errors.push(
+                        serialization::DeserializationErrorKind::DuplicateImage {
+                            name: name.to_string(),
+                            path_a: e.get().0.clone(),
+                            path_b: path.clone(),
+                        }
+                        .into(),
+                    );
+                }
+                Entry::Vacant(e) => {
+                    e.insert((path.clone(), image));
+                }
+            }
+        }
+
+        match serialization::DeserializationError::combine(errors) {
+            None => Ok(assets),
+            Some(err) => Err(err),
+        }
+    }
+}
+
+/// Removes an item declared by one of the previously loaded mods
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemDeletion {
+    pub kind: DatabaseItemKind,
+    #[schemars(schema_with = "serialization::item_id_schema")]
+    pub id: ItemId,
+}
+
 macro_rules! registry_partial {
     ($($name:ident: $ty:ty),*$(,)?) => {
         paste! {
@@ -347,6 +1204,18 @@ macro_rules! registry_partial {
             pub(crate) struct PartialModRegistry {
                 raw: RawModRegistry,
                 assets: ModAssets,
+                /// Errors of the items that failed to deserialize
+                errors: Vec<serialization::DeserializationError>,
+                dependencies: DependencyGraph,
+                /// Items that are being deserialized, with the innermost one
+                /// last
+                deserializing: Vec<RegistryId>,
+                /// Namespace of the mod declaring the item that is being
+                /// deserialized
+                namespace: Option<ItemId>,
+                /// Named constants declared by the manifests, by the
+                /// namespaces of the mods
+                constants: FxHashMap<ItemId, FxHashMap<String, f64>>,
                 $(
                     pub $name: ModelStore<Option<RegistryEntry<$ty>>>,
                 )*
@@ -358,9 +1227,118 @@ macro_rules! registry_partial {
                         $(
                             $name: convert_raw(self.$name),
                         )*
+                        assets: self.assets,
+                        load_order: Default::default(),
+                        inheritance: Default::default(),
+                        dependencies: self.dependencies,
+                        variable_order: Default::default(),
+                        namespaces: Default::default(),
+                        data_versions: Default::default(),
+                        constants: self.constants,
+                        unknown_fields: Default::default(),
+                    }
+                }
+
+                /// Moves the items out of the registry, leaving the rest of
+                /// it in place
+                fn take_from(registry: &mut ModRegistry) -> Self {
+                    Self {
+                        assets: std::mem::take(&mut registry.assets),
+                        dependencies: std::mem::take(&mut registry.dependencies),
+                        constants: std::mem::take(&mut registry.constants),
+                        $(
+                            $name: std::mem::take(&mut registry.$name).map_values(Some),
+                        )*
+                        ..Default::default()
+                    }
+                }
+
+                /// Moves the items back into the registry they were taken
+                /// from, keeping their IDs
+                fn put_back(self, registry: &mut ModRegistry) {
+                    registry.assets = self.assets;
+                    registry.dependencies = self.dependencies;
+                    registry.constants = self.constants;
+                    $(
+                        registry.$name = self.$name.map_values(|item| {
+                            item.expect("All registered items should be filled before conversion")
+                        });
+                    )*
+                }
+
+                /// Records the reference from the item that is being
+                /// deserialized
+                fn reference(&mut self, dependency: RegistryId) {
+                    if let Some(item) = self.deserializing.last() {
+                        self.dependencies.insert(*item, dependency);
+                    }
+                }
+
+                /// Clears slots of all already present items that are
+                /// awaiting deserialization in the raw registry, so they can
+                /// be filled again under the same ID
+                ///
+                /// Returns the cleared items, to put them back with
+                /// [Self::restore] if the deserialization fails
+                fn release_raw(&mut self) -> ReleasedItems {
+                    let mut released = ReleasedItems::default();
+                    $(
+                        for key in self.raw.$name.keys() {
+                            let Some(id) = self.$name.key_to_id(key) else {
+                                released.$name.added.push(key.clone());
+                                continue;
+                            };
+                            if let Some(item) = self.$name[id].take() {
+                                let registry_id = RegistryId::from(
+                                    id.as_untyped().as_typed_unchecked::<RegistryEntry<$ty>>(),
+                                );
+                                released.dependencies.push((
+                                    registry_id,
+                                    self.dependencies.dependencies(registry_id).collect(),
+                                ));
+                                released.$name.replaced.push((id, item));
+                            }
+                        }
+                    )*
+                    released
+                }
+
+                /// Undoes the deserialization of the released items, putting
+                /// back the old ones and dropping the added ones along with
+                /// their references
+                fn restore(&mut self, released: ReleasedItems) {
+                    $(
+                        for key in released.$name.added {
+                            if let Some(id) = self.$name.key_to_id(&key) {
+                                self.dependencies.clear_dependencies(RegistryId::from(
+                                    id.as_untyped().as_typed_unchecked::<RegistryEntry<$ty>>(),
+                                ));
+                                self.$name.remove(&key);
+                            }
+                        }
+                        for (id, item) in released.$name.replaced {
+                            self.$name[id] = Some(item);
+                        }
+                    )*
+                    for (item, dependencies) in released.dependencies {
+                        self.dependencies.clear_dependencies(item);
+                        for dependency in dependencies {
+                            self.dependencies.insert(item, dependency);
+                        }
                     }
                 }
             }
+
+            /// Items replaced or added by a hot reload, kept to undo it if
+            /// it fails
+            #[derive(Debug, Default)]
+            struct ReleasedItems {
+                /// References of the replaced items before the reload
+                dependencies: Vec<(RegistryId, Vec<RegistryId>)>,
+                $(
+                    $name: ReleasedKind<RegistryEntry<$ty>>,
+                )*
+            }
         }
     };
 }
@@ -376,6 +1354,90 @@ macro_rules! registry_raw {
                 $(
                     [< $name:camel >](<RegistryEntry<$ty> as serialization::ModelDeserializableFallbackType>::Serialized),
                 )*
+                Delete(ItemDeletion),
+            }
+
+            impl DatabaseItemKind {
+                /// Schema of a single item of this kind, for editors that
+                /// know the kind of the file in advance
+                pub fn schema(&self) -> RootSchema {
+                    let mut root = match self {
+                        $(
+                            Self::[<$name:camel>] => schemars::schema_for!(<RegistryEntry<$ty> as serialization::ModelDeserializableFallbackType>::Serialized),
+                        )*
+                    };
+                    let tag = SchemaObject {
+                        instance_type: Some(InstanceType::String.into()),
+                        enum_values: Some(vec![self.to_string().into()]),
+                        ..Default::default()
+                    };
+                    let object = root.schema.object();
+                    object.properties.insert("type".to_string(), tag.into());
+                    object.required.insert("type".to_string());
+                    root
+                }
+            }
+
+            impl DatabaseItemSerialized {
+                /// References to other items, including the variables used
+                /// in formulas and the deleted item
+                pub fn references(&self) -> serialization::ItemReferences {
+                    let mut collector = serialization::ReferenceCollector::default();
+                    serialization::ModelReferences::collect_references(self, &mut collector);
+                    collector.references
+                }
+
+                /// ID of the parent item, if this item extends one
+                pub fn extends(&self) -> Option<&ItemId> {
+                    match self {
+                        $(
+                            Self::[<$name:camel>](s) => s.data.extends(),
+                        )*
+                        Self::Delete(_) => None,
+                    }
+                }
+
+                /// Sets the data version declared by the item, which the
+                /// deletions don't have
+                pub fn set_data_version(&mut self, version: Option<DataVersion>) {
+                    match self {
+                        $(
+                            Self::[<$name:camel>](s) => s.data_version = version,
+                        )*
+                        Self::Delete(_) => {}
+                    }
+                }
+
+                /// Qualifies IDs of the item with the namespace of the mod
+                /// declaring it
+                fn qualify(&mut self, namespace: &str) -> Result<(), serialization::DeserializationError> {
+                    match self {
+                        $(
+                            Self::[<$name:camel>](s) => s.qualify(DatabaseItemKind::[<$name:camel>], namespace),
+                        )*
+                        Self::Delete(s) => {
+                            s.id = qualify_id(&s.id, s.kind, Some(namespace)).map_err(|e| {
+                                serialization::DeserializationError::from(e)
+                                    .context(serialization::DeserializationErrorStackItem::Field("id"))
+                            })?.into();
+                            Ok(())
+                        }
+                    }
+                }
+            }
+
+            impl serialization::ModelReferences for DatabaseItemSerialized {
+                fn collect_references(&self, collector: &mut serialization::ReferenceCollector) {
+                    match self {
+                        $(
+                            Self::[<$name:camel>](s) => serialization::ModelReferences::collect_references(s, collector),
+                        )*
+                        Self::Delete(s) => collector.nested(
+                            serialization::DeserializationErrorStackItem::Field("id"),
+                            |collector| collector.item(s.kind, &s.id),
+                        ),
+                    }
+                }
             }
 
             impl DatabaseItemSerializedTrait for DatabaseItemSerialized {
@@ -384,6 +1446,7 @@ macro_rules! registry_raw {
                         $(
                             Self::[<$name:camel>](s) => s.id(),
                         )*
+                        Self::Delete(s) => &s.id,
                     }
                 }
 
@@ -392,6 +1455,7 @@ macro_rules! registry_raw {
                         $(
                             Self::[<$name:camel>](s) => s.kind(),
                         )*
+                        Self::Delete(s) => s.kind,
                     }
                 }
             }
@@ -412,25 +1476,86 @@ macro_rules! registry_raw {
             }
 
             impl RawModRegistry {
-                pub fn insert(&mut self, item: DatabaseItemSerialized) -> Result<(), DatabaseItemSerialized> {
+                /// Inserts an item, returning it back if the item with the
+                /// same ID is already present
+                ///
+                /// Deletions can't be inserted and are always returned back
+                pub fn insert(&mut self, item: DatabaseItemSerialized) -> Result<(), Box<DatabaseItemSerialized>> {
                     match item {
                         $(
                             DatabaseItemSerialized::[<$name:camel>](item) => {
-                                insert_serialized(&mut self.$name, item).map_err(|e|e.into())
+                                insert_serialized(&mut self.$name, item).map_err(|e| Box::new(e.into()))
                             },
                         )*
+                        DatabaseItemSerialized::Delete(_) => Err(Box::new(item)),
+                    }
+                }
+
+                pub fn extends(&self, kind: DatabaseItemKind, id: &str) -> bool {
+                    match kind {
+                        $(
+                            DatabaseItemKind::[<$name:camel>] => self.$name.get(id).is_some_and(|e| e.data.extends().is_some()),
+                        )*
+                    }
+                }
+
+                pub fn remove(&mut self, kind: DatabaseItemKind, id: &str) -> bool {
+                    match kind {
+                        $(
+                            DatabaseItemKind::[<$name:camel>] => self.$name.remove(id).is_some(),
+                        )*
                     }
                 }
+
+                /// Fills in extending items from their parents
+                ///
+                /// Returns all items that extend or are extended by other items
+                pub fn resolve_inheritance(&mut self) -> Result<FxHashSet<(DatabaseItemKind, ItemId)>, serialization::DeserializationError> {
+                    let mut related = FxHashSet::default();
+                    let mut errors = Vec::new();
+                    $(
+                        let kind = DatabaseItemKind::[<$name:camel>];
+                        match serialization::resolve_inheritance(&mut self.$name, kind) {
+                            Ok(ids) => related.extend(ids.into_iter().map(|id| (kind, id))),
+                            Err(err) => errors.push(err),
+                        }
+                    )*
+                    match serialization::DeserializationError::combine(errors) {
+                        None => Ok(related),
+                        Some(err) => Err(err),
+                    }
+                }
+
+                /// Moves all items of another registry into this one,
+                /// overriding items with the same ID
+                pub fn extend(&mut self, other: RawModRegistry) {
+                    $(
+                        self.$name.extend(other.$name);
+                    )*
+                }
             }
 
             impl PartialModRegistry {
+                /// Deserializes all raw items, reporting errors of all failed
+                /// items together
                 pub fn deserialize(mut self) -> Result<ModRegistry, serialization::DeserializationError> {
+                    self.deserialize_raw()?;
+                    Ok(self.convert())
+                }
+
+                /// Deserializes all raw items into the slots of the registry
+                fn deserialize_raw(&mut self) -> Result<(), serialization::DeserializationError> {
                     $(
                         while let Some(value) = drain_one(&mut self.raw.$name) {
-                            serialization::ModelDeserializable::deserialize(value, &mut self)?;
+                            if let Err(err) = serialization::ModelDeserializable::deserialize(value, self) {
+                                self.errors.push(err);
+                            }
                         }
                     )*
-                    Ok(self.convert())
+                    match serialization::DeserializationError::combine(std::mem::take(&mut self.errors)) {
+                        None => Ok(()),
+                        Some(err) => Err(err),
+                    }
                 }
             }
         }
@@ -494,43 +1619,101 @@ macro_rules! serialization_traits {
                         self,
                         registry: &mut PartialModRegistry,
                     ) -> Result<SlabMapId<RegistryEntry<$ty>>, serialization::DeserializationError> {
-                        let reserved = serialization::reserve(&mut registry.$name, self.id.clone())?;
+                        let reserved = serialization::reserve(&mut registry.$name, self.id.clone());
+                        let item = RegistryId::from(reserved.raw());
+                        registry.dependencies.clear_dependencies(item);
+                        registry.deserializing.push(item);
+                        let namespace = std::mem::replace(&mut registry.namespace, self.namespace);
                         let data = serialization::ModelDeserializable::<$ty>::deserialize(
                             self.data, registry,
-                        )
-                        .map_err(|e| {
-                            e.context(
-                                serialization::DeserializationErrorStackItem::Item(
-                                    self.id,
-                                    <RegistryEntry::<$ty> as ModelKind>::kind(),
-                                ),
-                            )
-                        })?;
+                        );
+                        registry.namespace = namespace;
+                        registry.deserializing.pop();
+                        let data = match data {
+                            Ok(data) => data,
+                            Err(err) => {
+                                // Error is recorded and the slot is left
+                                // unfilled, so items referencing this one
+                                // don't fail along with it
+                                registry.errors.push(err.context(
+                                    serialization::DeserializationErrorStackItem::Item(
+                                        self.id,
+                                        <RegistryEntry::<$ty> as ModelKind>::kind(),
+                                    ),
+                                ));
+                                return Ok(reserved.raw());
+                            }
+                        };
                         let id = reserved.raw();
                         let model = RegistryEntry { id, data };
                         let id = serialization::insert_reserved(&mut registry.$name, reserved, model);
                         Ok(id)
                     }
                 }
+                impl serialization::ModelReferences
+                    for <RegistryEntry<$ty> as serialization::ModelDeserializableFallbackType>::Serialized
+                {
+                    fn collect_references(&self, collector: &mut serialization::ReferenceCollector) {
+                        match &self.data {
+                            serialization::ItemDataSerialized::Complete(data) => {
+                                serialization::ModelReferences::collect_references(data, collector)
+                            }
+                            // Fields of the extending item are untyped
+                            // until the inheritance is resolved
+                            serialization::ItemDataSerialized::Inherited { extends, .. } => {
+                                collector.nested(
+                                    serialization::DeserializationErrorStackItem::Field("extends"),
+                                    |collector| collector.item(DatabaseItemKind::[<$name:camel>], extends),
+                                );
+                            }
+                        }
+                    }
+                }
+                impl serialization::ModelReferences
+                    for serialization::InlineOrIdSerialized<<$ty as serialization::ModelDeserializableFallbackType>::Serialized>
+                {
+                    fn collect_references(&self, collector: &mut serialization::ReferenceCollector) {
+                        match self {
+                            serialization::InlineOrIdSerialized::Id(id) => {
+                                collector.item(DatabaseItemKind::[<$name:camel>], id)
+                            }
+                            serialization::InlineOrIdSerialized::Inline(data) => {
+                                serialization::ModelReferences::collect_references(data, collector)
+                            }
+                        }
+                    }
+                }
+                impl serialization::ModelSerializable<ItemId> for [<$name:camel Id>] {
+                    fn serialize(&self, registry: &ModRegistry) -> ItemId {
+                        registry
+                            .$name
+                            .id_to_key(*self)
+                            .expect("Registry should contain all referenced items")
+                            .clone()
+                    }
+                }
                 #[automatically_derived]
                 impl serialization::ModelDeserializable<[<$name:camel Id>]> for &str {
                     fn deserialize(
                         self,
                         registry: &mut crate::model::PartialModRegistry,
                     ) -> Result<[<$name:camel Id>], serialization::DeserializationError> {
-                        if let Some(id) = serialization::get_reserved_key(&mut registry.$name, self) {
-                            return Ok(id);
-                        }
-                        let Some(other) = registry.raw.$name.remove(self) else {
-                            return Err(
-                                serialization::DeserializationErrorKind::MissingItem(
-                                    self.to_string(),
-                                    <RegistryEntry::<$ty> as ModelKind>::kind(),
-                                )
-                                .into(),
-                            );
+                        let kind = <RegistryEntry::<$ty> as ModelKind>::kind();
+                        let key = registry.qualify(self, kind)?;
+                        let id = match serialization::get_reserved_key(&mut registry.$name, key.as_str()) {
+                            Some(id) => id,
+                            None => {
+                                let Some(other) = registry.raw.$name.remove(&key) else {
+                                    return Err(
+                                        serialization::DeserializationErrorKind::MissingItem(key, kind)
+                                            .into(),
+                                    );
+                                };
+                                other.deserialize(registry)?
+                            }
                         };
-                        other.deserialize(registry)
+                        registry.reference(id.into());
+                        Ok(id)
                     }
                 }
             )*
@@ -550,6 +1733,7 @@ macro_rules! call_with_all_models {
             fleet: $crate::model::fleet::Fleet,
             combat_settings: $crate::model::combat_settings::CombatSettings,
             device: $crate::model::device::Device,
+            mod_settings: $crate::model::mod_settings::ModSettings,
         );
     };
 }
@@ -560,3 +1744,359 @@ call_with_all_models!(registry_partial);
 call_with_all_models!(registry);
 call_with_all_models!(id_index);
 call_with_all_models!(serialization_traits);
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::{layer, parse, Fixture};
+
+    use super::serialization::{DeserializationErrorKind, ITEM_ID_PATTERN};
+    use super::{load_order, DatabaseItemKind, ModRegistry, RegistryId};
+
+    const VARIABLES: &str = r#"[
+  { type: "Variable", id: "eh:speed", name: "Speed" },
+  { type: "Variable", id: "eh:boost", name: "Boost", computed: "{eh:speed} * 2" },
+]"#;
+
+    fn manifest(id: &str, dependencies: &str) -> String {
+        format!(
+            r#"{{ type: "ModSettings", id: "{id}", name: "{id}", version: "0.1.0", dependencies: {{ {dependencies} }} }}"#
+        )
+    }
+
+    fn build(
+        eh: Fixture,
+        dlc: &str,
+    ) -> Result<ModRegistry, super::serialization::DeserializationError> {
+        let eh = eh.assets();
+        let dlc = [
+            parse("dlc/mod.json5", &manifest("dlc", "eh: \"0.1\"")),
+            parse("dlc/items.json5", dlc),
+        ];
+        ModRegistry::build([layer("eh", &eh), layer("dlc", &dlc)])
+    }
+
+    #[test]
+    fn later_layer_overrides_items() {
+        let eh = Fixture::default().file("eh/variables.json5", VARIABLES);
+        let registry = build(
+            eh,
+            r#"{ type: "Variable", id: "eh:speed", name: "Faster" }"#,
+        )
+        .unwrap();
+
+        let speed = registry.variable.get_by_key("eh:speed").unwrap();
+        assert_eq!(speed.data.name, "Faster");
+        assert!(registry.variable.get_by_key("eh:boost").is_some());
+    }
+
+    #[test]
+    fn later_layer_deletes_items() {
+        let eh = Fixture::default().file(
+            "eh/variables.json5",
+            r#"{ type: "Variable", id: "eh:speed", name: "Speed" }"#,
+        );
+        let registry = build(
+            eh,
+            r#"{ type: "Delete", kind: "Variable", id: "eh:speed" }"#,
+        )
+        .unwrap();
+
+        assert!(registry.variable.get_by_key("eh:speed").is_none());
+    }
+
+    #[test]
+    fn duplicates_are_only_reported_within_a_mod() {
+        let item = r#"{ type: "Variable", id: "eh:speed", name: "Speed" }"#;
+        let eh = Fixture::default()
+            .file("eh/speed.json5", item)
+            .file("eh/more.json5", item);
+        let err = build(eh, item).unwrap_err();
+
+        let errors = err.flatten();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        let DeserializationErrorKind::DuplicateItem {
+            id, path_a, path_b, ..
+        } = &errors[0].kind
+        else {
+            panic!("Expected a duplicate, got {}", errors[0]);
+        };
+        assert_eq!(id, "eh:speed");
+        assert_eq!(path_a.to_str(), Some("eh/speed.json5"));
+        assert_eq!(path_b.to_str(), Some("eh/more.json5"));
+
+        let eh = Fixture::default().file("eh/speed.json5", item);
+        build(eh, item).unwrap();
+    }
+
+    #[test]
+    fn mods_with_the_same_id_are_reported() {
+        let eh = Fixture::default().assets();
+        let copy = [parse("copy/mod.json5", &manifest("eh", ""))];
+        let err = ModRegistry::build([layer("eh", &eh), layer("copy", &copy)]).unwrap_err();
+
+        let errors = err.flatten();
+        assert!(
+            errors.iter().any(|err| matches!(
+                &err.kind,
+                DeserializationErrorKind::DuplicateMod { id, name_a, name_b }
+                    if id == "eh" && name_a == "eh" && name_b == "copy"
+            )),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn dependency_version_mismatch_points_to_the_manifest() {
+        let eh = Fixture::default().assets();
+        let dlc = [parse("dlc/mod.json5", &manifest("dlc", "eh: \"0.2\""))];
+        let err = ModRegistry::build([layer("eh", &eh), layer("dlc", &dlc)]).unwrap_err();
+
+        let errors = err.flatten();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert!(
+            matches!(
+                &errors[0].kind,
+                DeserializationErrorKind::DependencyVersionMismatch { mod_id, dependency, .. }
+                    if mod_id == "dlc" && dependency == "eh"
+            ),
+            "{}",
+            errors[0]
+        );
+        assert_eq!(
+            errors[0].file().and_then(|path| path.to_str()),
+            Some("dlc/mod.json5")
+        );
+        assert!(errors[0].location.is_some());
+    }
+
+    #[test]
+    fn mods_without_manifest_are_reported() {
+        let eh = Fixture::default().assets();
+        let dlc = [parse(
+            "dlc/items.json5",
+            r#"{ type: "Variable", id: "speed", name: "Speed" }"#,
+        )];
+        let err = ModRegistry::build([layer("eh", &eh), layer("dlc", &dlc)]).unwrap_err();
+
+        let errors = err.flatten();
+        assert!(
+            errors.iter().any(|err| matches!(
+                &err.kind,
+                DeserializationErrorKind::MissingManifest(name) if name == "dlc"
+            )),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn hot_reload_keeps_a_single_manifest() {
+        let mut registry = Fixture::default().build().unwrap();
+
+        let assets = [parse("eh/other.json5", &manifest("other", ""))];
+        let err = registry.hot_reload([layer("eh", &assets)]).unwrap_err();
+
+        let errors = err.flatten();
+        assert!(
+            errors.iter().any(|err| matches!(
+                &err.kind,
+                DeserializationErrorKind::MultipleManifests { id_a, id_b, .. }
+                    if id_a == "eh" && id_b == "other"
+            )),
+            "{errors:?}"
+        );
+        assert!(registry.mod_settings.get_by_key("other").is_none());
+    }
+
+    #[test]
+    fn extending_items_are_merged_over_their_parents() {
+        let registry = Fixture::default()
+            .file("eh/variables.json5", VARIABLES)
+            .file(
+                "eh/stats.json5",
+                r#"[
+  { type: "ComponentStats", id: "eh:base", stats: { "eh:speed": 1, "eh:boost": 2 } },
+  { type: "ComponentStats", id: "eh:tuned", extends: "eh:base", stats: { "eh:boost": 5 } },
+]"#,
+            )
+            .build()
+            .unwrap();
+
+        let speed = registry.variable.key_to_id("eh:speed").unwrap();
+        let boost = registry.variable.key_to_id("eh:boost").unwrap();
+        let tuned = &registry
+            .component_stats
+            .get_by_key("eh:tuned")
+            .unwrap()
+            .data;
+        assert_eq!(tuned.stats.get(&speed), Some(&1.0));
+        assert_eq!(tuned.stats.get(&boost), Some(&5.0));
+        assert!(registry.has_inheritance(DatabaseItemKind::ComponentStats, "eh:tuned"));
+    }
+
+    #[test]
+    fn broken_merged_field_is_located() {
+        let stats = r#"[
+  { type: "ComponentStats", id: "eh:base", stats: { "eh:speed": 1 } },
+  { type: "ComponentStats", id: "eh:tuned", extends: "eh:base", stats: { "eh:speed": "fast" } },
+]"#;
+        let err = Fixture::default()
+            .file("eh/variables.json5", VARIABLES)
+            .file("eh/stats.json5", stats)
+            .build()
+            .unwrap_err();
+
+        assert!(
+            matches!(err.kind, DeserializationErrorKind::BadInheritedItem(_)),
+            "{err}"
+        );
+        let location = err.location.as_ref().unwrap();
+        assert_eq!(&stats[location.span.clone()], r#"{ "eh:speed": "fast" }"#);
+    }
+
+    #[test]
+    fn inheritance_cycles_are_reported_once() {
+        let err = Fixture::default()
+            .file(
+                "eh/variables.json5",
+                r#"[
+  { type: "Variable", id: "eh:a", extends: "eh:b" },
+  { type: "Variable", id: "eh:b", extends: "eh:a" },
+]"#,
+            )
+            .build()
+            .unwrap_err();
+
+        let DeserializationErrorKind::InheritanceCycle(chain, kind) = &err.kind else {
+            panic!("Expected a cycle, got {err}");
+        };
+        assert_eq!(*kind, DatabaseItemKind::Variable);
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain.first(), chain.last());
+    }
+
+    /// Schema of the kind as JSON, for looking its parts up by pointers
+    fn schema(kind: DatabaseItemKind) -> serde_json::Value {
+        serde_json::to_value(kind.schema()).unwrap()
+    }
+
+    #[test]
+    fn schemas_are_tagged_with_their_kind() {
+        for kind in DatabaseItemKind::ALL {
+            let schema = schema(*kind);
+            assert_eq!(
+                schema.pointer("/properties/type/enum"),
+                Some(&serde_json::json!([kind.to_string()])),
+            );
+            let required = schema.pointer("/required").unwrap().as_array().unwrap();
+            assert!(required.contains(&"type".into()), "{kind}");
+            assert!(required.contains(&"id".into()), "{kind}");
+        }
+    }
+
+    #[test]
+    fn schemas_describe_ranges_ids_and_formulas() {
+        let ship = schema(DatabaseItemKind::Ship);
+        let scale = ship.pointer("/anyOf/0/properties/modelScale").unwrap();
+        assert_eq!(scale["minimum"], 0.1);
+        assert_eq!(scale["maximum"], 100.0);
+        assert_eq!(
+            ship.pointer("/properties/id/pattern"),
+            Some(&ITEM_ID_PATTERN.into())
+        );
+        assert_eq!(
+            ship.pointer("/definitions/Device/oneOf/0/properties/acceleration/description"),
+            Some(&"ID of a `Variable` item".into())
+        );
+        assert_eq!(
+            ship.pointer("/anyOf/1/properties/extends/pattern"),
+            Some(&ITEM_ID_PATTERN.into())
+        );
+
+        let variable = schema(DatabaseItemKind::Variable);
+        let formula = variable
+            .pointer("/definitions/SerializedFormula/anyOf")
+            .unwrap()
+            .as_array()
+            .unwrap();
+        let description = formula[0]["description"].as_str().unwrap();
+        assert!(description.starts_with("Math expression"), "{description}");
+        assert_eq!(formula[0]["type"], "string");
+        assert_eq!(formula[1]["type"], "number");
+    }
+
+    #[test]
+    fn dependency_cycles_are_broken_at_the_earliest_mod() {
+        let a = [parse("a/mod.json5", &manifest("a", "b: \"0.1\""))];
+        let b = [parse("b/mod.json5", &manifest("b", "a: \"0.1\""))];
+        let c = [parse("c/mod.json5", &manifest("c", ""))];
+
+        // Mods outside of the cycle go first, then the cycle is loaded in the
+        // given order
+        let order = load_order(&[layer("a", &a), layer("b", &b), layer("c", &c)]);
+        assert_eq!(order, [2, 0, 1]);
+    }
+
+    #[test]
+    fn hot_reload_keeps_ids_stable() {
+        let mut registry = Fixture::default()
+            .file("eh/variables.json5", VARIABLES)
+            .build()
+            .unwrap();
+        let speed = registry.variable.key_to_id("eh:speed").unwrap();
+        let boost = registry.variable.key_to_id("eh:boost").unwrap();
+
+        let changed = VARIABLES
+            .replace("Speed", "Velocity")
+            .replace("]", r#"{ type: "Variable", id: "eh:drag", name: "Drag" }]"#);
+        let assets = [parse("eh/variables.json5", &changed)];
+        let reloaded = registry.hot_reload([layer("eh", &assets)]).unwrap();
+
+        assert_eq!(registry.variable.key_to_id("eh:speed"), Some(speed));
+        assert_eq!(registry.variable.key_to_id("eh:boost"), Some(boost));
+        assert_eq!(registry.variable[speed].data.name, "Velocity");
+        assert_eq!(
+            registry
+                .dependencies()
+                .dependencies(boost.into())
+                .collect::<Vec<_>>(),
+            [RegistryId::from(speed)]
+        );
+        let drag = registry.variable.key_to_id("eh:drag").unwrap();
+        assert_eq!(reloaded.len(), 3);
+        assert!(reloaded.contains(&("eh/variables.json5".into(), drag.into())));
+    }
+
+    #[test]
+    fn failed_hot_reload_leaves_the_registry_untouched() {
+        let mut registry = Fixture::default()
+            .file("eh/variables.json5", VARIABLES)
+            .build()
+            .unwrap();
+        let speed = registry.variable.key_to_id("eh:speed").unwrap();
+        let boost = registry.variable.key_to_id("eh:boost").unwrap();
+
+        let broken = VARIABLES
+            .replace("Speed", "Velocity")
+            .replace("{eh:speed} * 2", "{eh:missing} * 2")
+            .replace("]", r#"{ type: "Variable", id: "eh:drag", name: "Drag" }]"#);
+        let assets = [parse("eh/variables.json5", &broken)];
+        assert!(registry.hot_reload([layer("eh", &assets)]).is_err());
+
+        assert_eq!(registry.variable[speed].data.name, "Speed");
+        assert_eq!(registry.variable.key_to_id("eh:drag"), None);
+        assert_eq!(
+            registry
+                .dependencies()
+                .dependencies(boost.into())
+                .collect::<Vec<_>>(),
+            [RegistryId::from(speed)]
+        );
+
+        // Slots of the dropped items are reused, without disturbing the IDs
+        // of the present ones
+        let assets = [parse("eh/variables.json5", VARIABLES)];
+        registry.hot_reload([layer("eh", &assets)]).unwrap();
+        assert_eq!(registry.variable.key_to_id("eh:speed"), Some(speed));
+        assert_eq!(registry.variable.key_to_id("eh:boost"), Some(boost));
+    }
+}
@@ -0,0 +1,388 @@
+use std::fmt::Formatter;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{
+    ArrayValidation, InstanceType, ObjectValidation, RootSchema, Schema, SchemaObject,
+    SubschemaValidation,
+};
+use schemars::JsonSchema;
+use serde::de::{Error as _, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::model::migration::{migrate, DataVersion};
+use crate::model::serialization::{
+    DeserializationError, DeserializationErrorKind, DeserializationErrorStackItem, ItemReferences,
+    ModelReferences, ReferenceCollector,
+};
+use crate::model::unknown_fields::unknown_fields;
+use crate::model::{
+    DatabaseAsset, DatabaseItemSerialized, DatabaseItemSerializedTrait, UnknownField,
+};
+
+/// Tag field every item declares, telling single items apart from the maps
+/// of items
+pub(crate) const TYPE_TAG: &str = "type";
+
+/// Items of a single database file
+///
+/// Besides a single item, a file can hold an array of items, or a map of IDs
+/// to items, in which case the items may omit their `id` field
+#[derive(Debug, Clone)]
+pub enum DatabaseFile {
+    Single(DatabaseItemSerialized),
+    Array(Vec<DatabaseItemSerialized>),
+    Map(Vec<DatabaseItemSerialized>),
+}
+
+impl DatabaseFile {
+    /// Items of the file, along with their positions inside it for the files
+    /// holding multiple items
+    pub fn items(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            Option<DeserializationErrorStackItem>,
+            &DatabaseItemSerialized,
+        ),
+    > + '_ {
+        let items = match self {
+            DatabaseFile::Single(item) => std::slice::from_ref(item),
+            DatabaseFile::Array(items) | DatabaseFile::Map(items) => items.as_slice(),
+        };
+        items.iter().enumerate().map(move |(i, item)| {
+            let position = match self {
+                DatabaseFile::Single(_) => None,
+                DatabaseFile::Array(_) => Some(DeserializationErrorStackItem::Index(i)),
+                DatabaseFile::Map(_) => {
+                    Some(DeserializationErrorStackItem::MapEntry(item.id().clone()))
+                }
+            };
+            (position, item)
+        })
+    }
+
+    /// References of all items of the file, with the paths starting at the
+    /// root of the file
+    pub fn references(&self) -> ItemReferences {
+        let mut collector = ReferenceCollector::default();
+        for (position, item) in self.items() {
+            match position {
+                Some(position) => {
+                    collector.nested(position, |collector| item.collect_references(collector))
+                }
+                None => item.collect_references(&mut collector),
+            }
+        }
+        collector.references
+    }
+
+    pub fn schema() -> RootSchema {
+        schemars::schema_for!(Self)
+    }
+}
+
+impl Serialize for DatabaseFile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            DatabaseFile::Single(item) => item.serialize(serializer),
+            DatabaseFile::Array(items) => items.serialize(serializer),
+            DatabaseFile::Map(items) => {
+                serializer.collect_map(items.iter().map(|item| (item.id(), item)))
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DatabaseFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FileVisitor)
+    }
+}
+
+impl DatabaseAsset {
+    /// Upgrades the items of the file from the data version of its mod and
+    /// deserializes them, along with the fields that none of the items
+    /// declare
+    ///
+    /// Unknown fields are looked for in the upgraded items, since the fields
+    /// of the older versions are gone from the schema
+    pub fn load(
+        &self,
+        version: DataVersion,
+    ) -> Result<(DatabaseFile, Vec<UnknownField>), DeserializationError> {
+        let value = migrate(&self.0, version)?;
+        let unknown = unknown_fields(&value);
+        let file = DatabaseFile::deserialize(value.as_ref())
+            .map_err(|err| DeserializationErrorKind::BadFile(err.to_string()))?;
+        Ok((file, unknown))
+    }
+}
+
+/// Raw items of the file, along with their positions laid out the same way
+/// as by [`DatabaseFile::items`]
+pub(crate) fn raw_items(
+    file: &serde_json::Value,
+) -> Vec<(Option<DeserializationErrorStackItem>, &serde_json::Value)> {
+    match file {
+        serde_json::Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (Some(DeserializationErrorStackItem::Index(i)), item))
+            .collect(),
+        serde_json::Value::Object(fields) if fields.contains_key(TYPE_TAG) => vec![(None, file)],
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(id, item)| {
+                (
+                    Some(DeserializationErrorStackItem::MapEntry(id.clone())),
+                    item,
+                )
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+struct FileVisitor;
+
+impl<'de> Visitor<'de> for FileVisitor {
+    type Value = DatabaseFile;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "an item, an array of items or a map of IDs to items")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(DatabaseFile::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries: Vec<(String, serde_json::Value)> =
+            Vec::with_capacity(map.size_hint().unwrap_or_default());
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+
+        if entries.iter().any(|(key, _)| key == TYPE_TAG) {
+            let fields = entries.into_iter().collect();
+            return DatabaseItemSerialized::deserialize(serde_json::Value::Object(fields))
+                .map(DatabaseFile::Single)
+                .map_err(A::Error::custom);
+        }
+
+        entries
+            .into_iter()
+            .map(|(id, value)| bundled_item(id, value).map_err(A::Error::custom))
+            .collect::<Result<_, _>>()
+            .map(DatabaseFile::Map)
+    }
+}
+
+/// Deserializes an item of the map, taking its ID from the key
+fn bundled_item(id: String, value: serde_json::Value) -> Result<DatabaseItemSerialized, String> {
+    let serde_json::Value::Object(mut fields) = value else {
+        return Err(format!("expected item `{id}` to be an object"));
+    };
+    match fields.get("id") {
+        Some(serde_json::Value::String(own)) if *own != id => {
+            return Err(format!("item `{id}` declares a different ID `{own}`"));
+        }
+        Some(_) => {}
+        None => {
+            fields.insert("id".to_string(), id.clone().into());
+        }
+    }
+    DatabaseItemSerialized::deserialize(serde_json::Value::Object(fields))
+        .map_err(|err| format!("item `{id}`: {err}"))
+}
+
+impl JsonSchema for DatabaseFile {
+    fn schema_name() -> String {
+        "DatabaseFile".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let item = gen.subschema_for::<DatabaseItemSerialized>();
+        let array = SchemaObject {
+            instance_type: Some(InstanceType::Array.into()),
+            array: Some(Box::new(ArrayValidation {
+                items: Some(item.clone().into()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        // Items of the map are described in full, so editors still expect
+        // their IDs, even though the keys already provide them
+        let map = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            object: Some(Box::new(ObjectValidation {
+                additional_properties: Some(Box::new(item.clone())),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![item, array.into(), map.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use crate::model::serialization::DeserializationErrorStackItem;
+    use crate::model::{DatabaseItemKind, DatabaseItemSerializedTrait};
+
+    use super::{raw_items, DatabaseFile};
+
+    /// Positions, kinds and IDs of the items of the file
+    fn items(file: &DatabaseFile) -> Vec<(String, DatabaseItemKind, String)> {
+        file.items()
+            .map(|(position, item)| (position_key(position), item.kind(), item.id().clone()))
+            .collect()
+    }
+
+    fn position_key(position: Option<DeserializationErrorStackItem>) -> String {
+        match position {
+            None => String::new(),
+            Some(DeserializationErrorStackItem::Index(i)) => i.to_string(),
+            Some(DeserializationErrorStackItem::MapEntry(key)) => key,
+            Some(position) => panic!("Unexpected position {position}"),
+        }
+    }
+
+    fn error(value: Value) -> String {
+        DatabaseFile::deserialize(value).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn arrays_hold_items_by_index() {
+        let value = json!([
+            { "type": "Variable", "id": "eh:speed", "name": "Speed" },
+            { "type": "Fleet", "id": "eh:fleet", "builds": [] },
+        ]);
+        let file = DatabaseFile::deserialize(&value).unwrap();
+
+        assert!(matches!(file, DatabaseFile::Array(_)));
+        let expected = [
+            (
+                "0".to_string(),
+                DatabaseItemKind::Variable,
+                "eh:speed".to_string(),
+            ),
+            (
+                "1".to_string(),
+                DatabaseItemKind::Fleet,
+                "eh:fleet".to_string(),
+            ),
+        ];
+        assert_eq!(items(&file), expected);
+        let raw = raw_items(&value)
+            .into_iter()
+            .map(|(position, _)| position_key(position))
+            .collect::<Vec<_>>();
+        assert_eq!(raw, ["0", "1"]);
+    }
+
+    #[test]
+    fn maps_take_ids_from_their_keys() {
+        let value = json!({
+            "eh:speed": { "type": "Variable", "name": "Speed" },
+            "eh:fleet": { "type": "Fleet", "id": "eh:fleet", "builds": [] },
+        });
+        let file = DatabaseFile::deserialize(&value).unwrap();
+
+        assert!(matches!(file, DatabaseFile::Map(_)));
+        let mut items = items(&file);
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        let expected = [
+            (
+                "eh:fleet".to_string(),
+                DatabaseItemKind::Fleet,
+                "eh:fleet".to_string(),
+            ),
+            (
+                "eh:speed".to_string(),
+                DatabaseItemKind::Variable,
+                "eh:speed".to_string(),
+            ),
+        ];
+        assert_eq!(items, expected);
+        let mut raw = raw_items(&value)
+            .into_iter()
+            .map(|(position, _)| position_key(position))
+            .collect::<Vec<_>>();
+        raw.sort();
+        assert_eq!(raw, ["eh:fleet", "eh:speed"]);
+    }
+
+    #[test]
+    fn single_items_have_no_position() {
+        let value = json!({ "type": "Variable", "id": "eh:speed", "name": "Speed" });
+        let file = DatabaseFile::deserialize(&value).unwrap();
+
+        assert!(matches!(file, DatabaseFile::Single(_)));
+        assert_eq!(
+            items(&file),
+            [(
+                String::new(),
+                DatabaseItemKind::Variable,
+                "eh:speed".to_string()
+            )]
+        );
+        assert_eq!(raw_items(&value).len(), 1);
+        assert!(raw_items(&value)[0].0.is_none());
+    }
+
+    #[test]
+    fn map_entries_must_be_objects_with_matching_ids() {
+        let err = error(json!({ "eh:speed": "Speed" }));
+        assert!(
+            err.contains("expected item `eh:speed` to be an object"),
+            "{err}"
+        );
+
+        let err = error(json!({
+            "eh:speed": { "type": "Variable", "id": "eh:boost", "name": "Speed" },
+        }));
+        assert!(
+            err.contains("item `eh:speed` declares a different ID `eh:boost`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn array_entries_must_be_items() {
+        let err = error(json!([
+            { "type": "Variable", "id": "eh:speed", "name": "Speed" },
+            "eh:boost",
+        ]));
+        assert!(err.contains("invalid type: string \"eh:boost\""), "{err}");
+        assert!(DatabaseFile::deserialize(json!(42)).is_err());
+    }
+
+    #[test]
+    fn items_without_type_tag_are_rejected() {
+        // Without the tag, the fields of the item are read as a map of items
+        let err = error(json!({ "id": "eh:speed", "name": "Speed" }));
+        assert!(err.contains("to be an object"), "{err}");
+
+        let err = error(json!({ "eh:speed": { "name": "Speed" } }));
+        assert!(err.contains("item `eh:speed`"), "{err}");
+        assert!(err.contains("`type`"), "{err}");
+
+        let err = error(json!([{ "id": "eh:speed", "name": "Speed" }]));
+        assert!(err.contains("`type`"), "{err}");
+    }
+}
@@ -0,0 +1,263 @@
+//! Parsing the text formats, JSON5 in particular, takes most of the time
+//! spent loading large mods. Parsed files are kept in a binary form, which
+//! is read back many times faster, as long as the text of the file stays the
+//! same.
+//!
+//! Entries of the edited files are never read again, so they are pruned once
+//! all files are loaded.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rustc_hash::FxHashSet;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::model::{DataFormat, DatabaseAsset, FormatError};
+
+/// Version of the layout of the cache, bumped whenever the cached values
+/// change their shape
+const CACHE_VERSION: u32 = 1;
+
+/// Cache of the parsed database files, keyed by the hash of their text
+///
+/// Cache only speeds the loading up, so entries that fail to be read or
+/// written are silently parsed from the text again
+#[derive(Debug, Clone)]
+pub struct FileCache {
+    dir: PathBuf,
+    /// Entries looked up through the cache or any of its clones, which are
+    /// kept by [FileCache::prune]
+    used: Arc<Mutex<FxHashSet<PathBuf>>>,
+}
+
+impl FileCache {
+    /// Cache stored in the directory, which is created once the first entry
+    /// is written
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().join(format!("v{CACHE_VERSION}")),
+            used: Default::default(),
+        }
+    }
+
+    /// Parses the text in the format, reusing the value cached for the same
+    /// text
+    pub fn parse<T: Serialize + DeserializeOwned>(
+        &self,
+        text: &str,
+        format: DataFormat,
+    ) -> Result<T, FormatError> {
+        let path = self.entry_path::<T>(text, format);
+        self.used.lock().unwrap().insert(path.clone());
+        if let Some(value) = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| rmp_serde::from_slice(&bytes).ok())
+        {
+            return Ok(value);
+        }
+
+        let value = format.parse(text)?;
+        if let Ok(bytes) = rmp_serde::to_vec_named(&value) {
+            let _ = self.write(&path, &bytes);
+        }
+        Ok(value)
+    }
+
+    /// Removes the entries that weren't looked up since the cache was
+    /// created, along with the entries written by the older versions
+    ///
+    /// Meant to be called once all files are loaded, since the files that
+    /// aren't loaded by then were either edited or removed
+    pub fn prune(&self) -> std::io::Result<()> {
+        if let Some(root) = self.dir.parent() {
+            for entry in read_dir(root)? {
+                let entry = entry?;
+                let old_version = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| name.strip_prefix('v'))
+                    .is_some_and(|version| version.parse::<u32>().is_ok());
+                if old_version && entry.path() != self.dir && entry.file_type()?.is_dir() {
+                    std::fs::remove_dir_all(entry.path())?;
+                }
+            }
+        }
+
+        let used = self.used.lock().unwrap();
+        for entry in read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "msgpack") && !used.contains(&path) {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the directory of the cache along with all of its entries,
+    /// including the ones written by the older versions
+    pub fn clear(&self) -> std::io::Result<()> {
+        let root = self.dir.parent().unwrap_or(&self.dir);
+        match std::fs::remove_dir_all(root) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Path of the entry, keyed by the type of the value as well, since
+    /// the same text parses into different values
+    fn entry_path<T>(&self, text: &str, format: DataFormat) -> PathBuf {
+        let mut hasher = blake3::Hasher::new();
+        for part in [std::any::type_name::<T>(), format.extensions()[0], text] {
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        self.dir
+            .join(format!("{}.msgpack", hasher.finalize().to_hex()))
+    }
+
+    /// Writes the entry through a temporary file, so files loaded in
+    /// parallel never observe a partially written entry
+    fn write(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        std::fs::create_dir_all(&self.dir)?;
+        let temp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&temp, bytes)?;
+        let result = std::fs::rename(&temp, path);
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        result
+    }
+}
+
+/// Entries of the directory, with a missing directory having none
+fn read_dir(
+    dir: &Path,
+) -> std::io::Result<impl Iterator<Item = std::io::Result<std::fs::DirEntry>>> {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => Ok(Some(entries).into_iter().flatten()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None.into_iter().flatten()),
+        Err(err) => Err(err),
+    }
+}
+
+impl DatabaseAsset {
+    /// Parses the text of the database file like [`DatabaseAsset::parse`],
+    /// reusing the items cached for the same text
+    pub fn parse_cached(
+        text: &Arc<str>,
+        format: DataFormat,
+        cache: &FileCache,
+    ) -> Result<Self, FormatError> {
+        let mut asset: Self = cache.parse(text, format)?;
+        if format.supports_locations() {
+            asset.1 = Some(text.clone());
+        }
+        Ok(asset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use crate::model::DataFormat;
+
+    use super::FileCache;
+
+    type Value = BTreeMap<String, i32>;
+
+    const TEXT: &str = "{ a: 1 }";
+
+    /// Empty directory for the cache of the test
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("database_cache_test_{}_{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn value(a: i32) -> Value {
+        [("a".to_string(), a)].into()
+    }
+
+    fn parse(cache: &FileCache, text: &str) -> Value {
+        cache.parse(text, DataFormat::Json5).unwrap()
+    }
+
+    #[test]
+    fn cached_values_are_read_back() {
+        let dir = cache_dir("hit");
+        let cache = FileCache::new(&dir);
+        assert_eq!(parse(&cache, TEXT), value(1));
+
+        // Entry is read instead of the text, as long as the text is the same
+        let path = cache.entry_path::<Value>(TEXT, DataFormat::Json5);
+        std::fs::write(&path, rmp_serde::to_vec_named(&value(2)).unwrap()).unwrap();
+        assert_eq!(parse(&cache, TEXT), value(2));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn changed_text_is_parsed_again() {
+        let dir = cache_dir("miss");
+        let cache = FileCache::new(&dir);
+        assert_eq!(parse(&cache, TEXT), value(1));
+        assert_eq!(parse(&cache, "{ a: 3 }"), value(3));
+        assert_ne!(
+            cache.entry_path::<Value>(TEXT, DataFormat::Json5),
+            cache.entry_path::<Value>(TEXT, DataFormat::Yaml)
+        );
+        assert_ne!(
+            cache.entry_path::<Value>(TEXT, DataFormat::Json5),
+            cache.entry_path::<BTreeMap<String, f64>>(TEXT, DataFormat::Json5)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_entries_are_parsed_from_the_text() {
+        let dir = cache_dir("corrupt");
+        let cache = FileCache::new(&dir);
+        let path = cache.entry_path::<Value>(TEXT, DataFormat::Json5);
+        assert_eq!(parse(&cache, TEXT), value(1));
+
+        std::fs::write(&path, b"not msgpack").unwrap();
+        assert_eq!(parse(&cache, TEXT), value(1));
+        // Entry is written again
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Value>(&bytes).unwrap(), value(1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pruning_removes_entries_not_looked_up() {
+        let dir = cache_dir("prune");
+        let first = FileCache::new(&dir);
+        parse(&first, TEXT);
+        parse(&first, "{ a: 2 }");
+        let old_version = dir.join("v0");
+        std::fs::create_dir_all(&old_version).unwrap();
+
+        // Next load only has the first file left
+        let second = FileCache::new(&dir);
+        parse(&second, TEXT);
+        second.prune().unwrap();
+        assert!(second.entry_path::<Value>(TEXT, DataFormat::Json5).exists());
+        assert!(!second
+            .entry_path::<Value>("{ a: 2 }", DataFormat::Json5)
+            .exists());
+        assert!(!old_version.exists());
+
+        // Pruning a cache that was never written to does nothing
+        FileCache::new(cache_dir("prune_empty")).prune().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
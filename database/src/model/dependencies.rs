@@ -0,0 +1,158 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::model::RegistryId;
+
+/// Graph of references between the items of the registry, recorded as they
+/// are deserialized
+///
+/// Every resolved item ID is an edge from the item containing it to the
+/// referenced item, including variables used in formulas. Inline items are
+/// considered a part of the item they are declared in.
+#[derive(Debug, Default, Clone)]
+pub struct DependencyGraph {
+    /// Items referenced by the item
+    dependencies: FxHashMap<RegistryId, FxHashSet<RegistryId>>,
+    /// Items referencing the item
+    dependents: FxHashMap<RegistryId, FxHashSet<RegistryId>>,
+}
+
+impl DependencyGraph {
+    pub(crate) fn insert(&mut self, item: RegistryId, dependency: RegistryId) {
+        self.dependencies
+            .entry(item)
+            .or_default()
+            .insert(dependency);
+        self.dependents.entry(dependency).or_default().insert(item);
+    }
+
+    /// Forgets all references of the item, so they can be recorded again when
+    /// it gets deserialized anew
+    pub(crate) fn clear_dependencies(&mut self, item: RegistryId) {
+        let Some(dependencies) = self.dependencies.remove(&item) else {
+            return;
+        };
+        for dependency in dependencies {
+            if let Some(dependents) = self.dependents.get_mut(&dependency) {
+                dependents.remove(&item);
+                if dependents.is_empty() {
+                    self.dependents.remove(&dependency);
+                }
+            }
+        }
+    }
+
+    /// Items directly referenced by the item
+    pub fn dependencies(&self, item: RegistryId) -> impl Iterator<Item = RegistryId> + '_ {
+        self.dependencies.get(&item).into_iter().flatten().copied()
+    }
+
+    /// Items directly referencing the item
+    pub fn dependents(&self, item: RegistryId) -> impl Iterator<Item = RegistryId> + '_ {
+        self.dependents.get(&item).into_iter().flatten().copied()
+    }
+
+    /// Items referencing the item either directly or through other items,
+    /// not including the item itself
+    pub fn transitive_dependents(&self, item: RegistryId) -> FxHashSet<RegistryId> {
+        let mut visited = FxHashSet::default();
+        let mut pending = vec![item];
+        while let Some(item) = pending.pop() {
+            for dependent in self.dependents(item) {
+                if visited.insert(dependent) {
+                    pending.push(dependent);
+                }
+            }
+        }
+        visited.remove(&item);
+        visited
+    }
+
+    /// Whether any other item references the item
+    pub fn is_referenced(&self, item: RegistryId) -> bool {
+        self.dependents(item).any(|dependent| dependent != item)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rustc_hash::FxHashSet;
+    use slabmap::SlabMapUntypedId;
+
+    use super::DependencyGraph;
+    use crate::model::{DatabaseItemKind, RegistryId};
+
+    fn id(raw: usize) -> RegistryId {
+        RegistryId {
+            kind: DatabaseItemKind::Variable,
+            id: SlabMapUntypedId::from_raw_unchecked(raw),
+        }
+    }
+
+    fn set(ids: impl IntoIterator<Item = usize>) -> FxHashSet<RegistryId> {
+        ids.into_iter().map(id).collect()
+    }
+
+    /// 1 references 0, 2 references 1, 3 references 0 and itself
+    fn graph() -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        graph.insert(id(1), id(0));
+        graph.insert(id(2), id(1));
+        graph.insert(id(3), id(0));
+        graph.insert(id(3), id(3));
+        graph
+    }
+
+    #[test]
+    fn edges_are_recorded_both_ways() {
+        let graph = graph();
+        assert_eq!(
+            graph.dependencies(id(3)).collect::<FxHashSet<_>>(),
+            set([0, 3])
+        );
+        assert_eq!(
+            graph.dependents(id(0)).collect::<FxHashSet<_>>(),
+            set([1, 3])
+        );
+        assert_eq!(graph.dependencies(id(0)).count(), 0);
+        assert_eq!(graph.dependents(id(2)).count(), 0);
+    }
+
+    #[test]
+    fn cleared_dependencies_drop_both_sides() {
+        let mut graph = graph();
+        graph.clear_dependencies(id(3));
+
+        assert_eq!(graph.dependencies(id(3)).count(), 0);
+        assert_eq!(graph.dependents(id(0)).collect::<FxHashSet<_>>(), set([1]));
+        assert_eq!(graph.dependents(id(3)).count(), 0);
+        // Dependents of the cleared item are kept
+        assert_eq!(graph.dependents(id(1)).collect::<FxHashSet<_>>(), set([2]));
+    }
+
+    #[test]
+    fn transitive_dependents_follow_chains() {
+        let graph = graph();
+        assert_eq!(graph.transitive_dependents(id(0)), set([1, 2, 3]));
+        assert_eq!(graph.transitive_dependents(id(1)), set([2]));
+        assert!(graph.transitive_dependents(id(2)).is_empty());
+    }
+
+    #[test]
+    fn transitive_dependents_stop_at_cycles() {
+        let mut graph = graph();
+        graph.insert(id(0), id(2));
+
+        assert_eq!(graph.transitive_dependents(id(0)), set([1, 2, 3]));
+        assert_eq!(graph.transitive_dependents(id(1)), set([0, 2, 3]));
+        assert_eq!(graph.transitive_dependents(id(3)), FxHashSet::default());
+    }
+
+    #[test]
+    fn self_references_are_not_counted() {
+        let graph = graph();
+        assert!(graph.is_referenced(id(0)));
+        assert!(graph.is_referenced(id(1)));
+        assert!(!graph.is_referenced(id(2)));
+        assert!(!graph.is_referenced(id(3)));
+    }
+}
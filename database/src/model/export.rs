@@ -0,0 +1,198 @@
+use std::path::{Path, PathBuf};
+
+use miette::Diagnostic;
+use rustc_hash::FxHashMap;
+use thiserror::Error;
+
+use crate::model::{
+    DataFormat, DatabaseFile, DatabaseItemKind, DatabaseItemSerialized,
+    DatabaseItemSerializedTrait, FormatError, ItemId, ModRegistry, RegistryId,
+    CURRENT_DATA_VERSION,
+};
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ExportError {
+    #[error("Item {}(#{}) is missing", .0.kind(), .0.id().raw())]
+    MissingItem(RegistryId),
+    #[error("Item {}(#{}) shares the file `{}` with other items", .0.kind(), .0.id().raw(), .1.to_string_lossy())]
+    Bundled(RegistryId, PathBuf),
+    #[error("Failed to serialize {}({})", .1, .0)]
+    Serialization(ItemId, DatabaseItemKind, #[source] Box<FormatError>),
+    #[error("Failed to write file `{}`", .0.to_string_lossy())]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Items {}({}) and {}({}) would both be written to `{}`", .kind_a, .id_a, .kind_b, .id_b, .path.to_string_lossy())]
+    ContestedPath {
+        path: PathBuf,
+        id_a: ItemId,
+        kind_a: DatabaseItemKind,
+        id_b: ItemId,
+        kind_b: DatabaseItemKind,
+    },
+}
+
+impl ModRegistry {
+    /// Writes every item of the registry into the directory, one file per
+    /// item, laid out as `<kind>/<id>.json5`
+    ///
+    /// Items are written in the current data version, which the manifests
+    /// declare for their mods.
+    ///
+    /// Returns paths of the written files
+    pub fn export(&self, dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, ExportError> {
+        let dir = dir.as_ref();
+        let mut items = self.serialize_all();
+        for item in &mut items {
+            if item.kind() == DatabaseItemKind::ModSettings {
+                item.set_data_version(Some(CURRENT_DATA_VERSION));
+            }
+        }
+
+        // Paths are checked upfront, so nothing gets written if some items
+        // can't be told apart by their file names
+        let mut paths: FxHashMap<PathBuf, &DatabaseItemSerialized> = FxHashMap::default();
+        for item in &items {
+            let path = dir
+                .join(item.kind().name())
+                .join(format!("{}.json5", file_name(item.id())));
+            if let Some(other) = paths.insert(path.clone(), item) {
+                return Err(ExportError::ContestedPath {
+                    path,
+                    id_a: other.id().clone(),
+                    kind_a: other.kind(),
+                    id_b: item.id().clone(),
+                    kind_b: item.kind(),
+                });
+            }
+        }
+
+        paths
+            .into_iter()
+            .map(|(path, item)| {
+                write_item(item, &path)?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// Writes a single item into the file, for example the one it was
+    /// originally loaded from
+    ///
+    /// Manifests keep the data version of their mods. Other items are
+    /// written in the current version, which they declare themselves if any
+    /// of the mods is written in an older one, so they are not upgraded
+    /// again once loaded
+    pub fn export_item(&self, id: RegistryId, path: impl AsRef<Path>) -> Result<(), ExportError> {
+        let mut item = self
+            .serialize_item(id)
+            .ok_or(ExportError::MissingItem(id))?;
+        if item.kind() == DatabaseItemKind::ModSettings {
+            item.set_data_version(self.data_versions.get(item.id()).copied());
+        } else if self
+            .data_versions
+            .values()
+            .any(|version| *version != CURRENT_DATA_VERSION)
+        {
+            item.set_data_version(Some(CURRENT_DATA_VERSION));
+        }
+        write_item(&item, path.as_ref())
+    }
+}
+
+/// Writes the schema of all items into `$schema.json`, along with a
+/// separate `<kind>.schema.json` for every kind of items
+///
+/// Returns paths of the written files
+pub fn export_schemas(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, ExportError> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir).map_err(|e| ExportError::Io(dir.to_path_buf(), e))?;
+
+    let schemas = std::iter::once(("$schema.json".to_string(), DatabaseFile::schema())).chain(
+        DatabaseItemKind::ALL
+            .iter()
+            .map(|kind| (format!("{kind}.schema.json"), kind.schema())),
+    );
+    schemas
+        .map(|(name, schema)| {
+            let path = dir.join(name);
+            let text =
+                serde_json::to_string_pretty(&schema).expect("Schemas should always serialize");
+            std::fs::write(&path, text).map_err(|e| ExportError::Io(path.clone(), e))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Writes the item in the format matching the extension of the file,
+/// falling back to JSON5
+fn write_item(item: &DatabaseItemSerialized, path: &Path) -> Result<(), ExportError> {
+    let text = DataFormat::from_path(path)
+        .unwrap_or(DataFormat::Json5)
+        .write(item)
+        .map_err(|e| ExportError::Serialization(item.id().clone(), item.kind(), Box::new(e)))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| ExportError::Io(parent.to_path_buf(), e))?;
+    }
+    std::fs::write(path, text).map_err(|e| ExportError::Io(path.to_path_buf(), e))
+}
+
+/// Replaces characters that can't appear in file names on some platforms,
+/// such as the namespace separator
+fn file_name(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+
+    use crate::fixture::{layer, parse, Fixture};
+    use crate::model::ModRegistry;
+
+    const ITEMS: &str = r#"[
+  { type: "Variable", id: "eh:speed", name: "Speed", default: "2" },
+  { type: "Variable", id: "eh:boost", name: "Boost", computed: "{eh:speed} * 2" },
+  { type: "ComponentStats", id: "eh:stats", stats: { "eh:speed": 1.5 } },
+]"#;
+
+    /// Serialized items of the registry, in a stable order
+    fn items(registry: &ModRegistry) -> Vec<String> {
+        registry
+            .serialize_all()
+            .iter()
+            .map(|item| serde_json::to_string(item).unwrap())
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn exported_items_are_loaded_back() {
+        let registry = Fixture::default()
+            .file("eh/items.json5", ITEMS)
+            .build()
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!("database_export_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let paths = registry.export(&dir).unwrap();
+        let assets = paths
+            .iter()
+            .map(|path| {
+                let text = std::fs::read_to_string(path).unwrap();
+                parse(path.strip_prefix(&dir).unwrap(), &text)
+            })
+            .collect_vec();
+        let loaded = ModRegistry::build([layer("eh", &assets)]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(paths.len(), 6);
+        assert_eq!(items(&loaded), items(&registry));
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::model::DatabaseAsset;
+
+/// Text formats database files can be written in
+///
+/// Formats can be mixed freely inside a single mod, the format of every file
+/// is picked by its extension
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DataFormat {
+    Json5,
+    Toml,
+    Ron,
+    Yaml,
+}
+
+impl DataFormat {
+    pub const ALL: &'static [DataFormat] = &[
+        DataFormat::Json5,
+        DataFormat::Toml,
+        DataFormat::Ron,
+        DataFormat::Yaml,
+    ];
+
+    /// Extensions of the files in the format, in lowercase
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            DataFormat::Json5 => &["json", "json5"],
+            DataFormat::Toml => &["toml"],
+            DataFormat::Ron => &["ron"],
+            DataFormat::Yaml => &["yaml", "yml"],
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let extension = extension.to_ascii_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+
+    /// Whether values of the parsed files can be located in their text, to
+    /// point errors to the offending values
+    ///
+    /// Errors in files of other formats only point to the file itself
+    pub fn supports_locations(self) -> bool {
+        self != DataFormat::Ron
+    }
+
+    pub fn parse<T: DeserializeOwned>(self, text: &str) -> Result<T, FormatError> {
+        Ok(match self {
+            DataFormat::Json5 => serde_json5::from_str(text)?,
+            DataFormat::Toml => toml::from_str(text)?,
+            DataFormat::Ron => ron::from_str(text)?,
+            DataFormat::Yaml => serde_yaml::from_str(text)?,
+        })
+    }
+
+    /// Writes the value in a human-readable form
+    pub fn write<T: Serialize>(self, value: &T) -> Result<String, FormatError> {
+        Ok(match self {
+            // JSON is a subset of JSON5, and there is no pretty JSON5 writer
+            DataFormat::Json5 => serde_json::to_string_pretty(value)?,
+            DataFormat::Toml => toml::to_string_pretty(value)?,
+            DataFormat::Ron => ron::ser::to_string_pretty(value, Default::default())?,
+            DataFormat::Yaml => serde_yaml::to_string(value)?,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error(transparent)]
+    Json5(#[from] serde_json5::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    TomlParse(#[from] toml::de::Error),
+    #[error(transparent)]
+    TomlWrite(#[from] toml::ser::Error),
+    #[error(transparent)]
+    RonParse(#[from] ron::error::SpannedError),
+    #[error(transparent)]
+    RonWrite(#[from] ron::Error),
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+impl FormatError {
+    /// Error message without the position in the text
+    pub fn message(&self) -> String {
+        match self {
+            FormatError::Json5(serde_json5::Error::Message { msg, .. }) => msg.clone(),
+            FormatError::TomlParse(err) => err.message().to_string(),
+            FormatError::RonParse(err) => err.code.to_string(),
+            err => err.to_string(),
+        }
+    }
+
+    /// Byte offset of the error in the parsed text, if the parser reports
+    /// one
+    pub fn offset(&self, text: &str) -> Option<usize> {
+        match self {
+            FormatError::Json5(serde_json5::Error::Message { location, .. }) => {
+                let location = location.as_ref()?;
+                Some(line_column_offset(text, location.line, location.column))
+            }
+            FormatError::TomlParse(err) => Some(err.span()?.start),
+            FormatError::RonParse(err) => Some(line_column_offset(
+                text,
+                err.position.line,
+                err.position.col,
+            )),
+            FormatError::Yaml(err) => {
+                let location = err.location()?;
+                Some(line_column_offset(text, location.line(), location.column()))
+            }
+            FormatError::Json(_) | FormatError::TomlWrite(_) | FormatError::RonWrite(_) => None,
+        }
+    }
+}
+
+impl DatabaseAsset {
+    /// Parses the text of the database file, keeping the text around if the
+    /// values can be located in it
+    pub fn parse(text: &Arc<str>, format: DataFormat) -> Result<Self, FormatError> {
+        let mut asset: Self = format.parse(text)?;
+        if format.supports_locations() {
+            asset.1 = Some(text.clone());
+        }
+        Ok(asset)
+    }
+}
+
+/// Converts one-based line and column, as reported by the parsers, into a
+/// byte offset
+fn line_column_offset(text: &str, line: usize, column: usize) -> usize {
+    let line_start = text
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    line.char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(line_start + line.len(), |(i, _)| line_start + i)
+}
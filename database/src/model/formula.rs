@@ -1,29 +1,93 @@
-use exmex::{Calculate, Express};
-use itertools::Itertools;
-
 use crate::model::serialization::{
-    DeserializationError, DeserializationErrorStackItem, DeserializeFrom, ModelDeserializable,
-    ModelDeserializableFallbackType,
+    collect_all, DeserializationError, DeserializationErrorStackItem, DeserializeFrom,
+    ModelDeserializable, ModelDeserializableFallbackType, ModelReferences, ModelSerializable,
+    ReferenceCollector,
 };
-use crate::model::{PartialModRegistry, VariableId};
+use crate::model::{DatabaseItemKind, ModRegistry, NamespacedId, PartialModRegistry, VariableId};
+
+pub(crate) use program::is_builtin;
+pub use program::{FormulaError, Program};
+
+mod program;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(untagged)]
 pub enum SerializedFormula {
+    /// Math expression, such as `max_health * 0.5 + 10`
+    ///
+    /// Supports `+`, `-`, `*`, `/`, `^`, parentheses and functions like
+    /// `sqrt`, `abs` or `floor`, along with:
+    /// - comparisons `<`, `<=`, `>`, `>=`, `==` and `!=`, giving `1` when
+    ///   they hold and `0` otherwise
+    /// - `if(cond, a, b)`, giving `a` when `cond` is not `0` and `b`
+    ///   otherwise
+    /// - `min(a, b, ...)` and `max(a, b, ...)`
+    /// - `clamp(x, min, max)` and `lerp(a, b, t)`
+    /// - `round_to(x, step)`, `floor_to(x, step)` and `ceil_to(x, step)`,
+    ///   rounding to a multiple of the step
+    /// - constants `PI`, `E` and `TAU`
+    ///
+    /// Other names refer to the constants declared by the mod manifest, or
+    /// `{mod:NAME}` for the constants of other mods. Every remaining name is
+    /// treated as the ID of a variable item
     String(String),
+    /// Constant value
     Number(f64),
 }
 
 #[derive(Debug, Clone)]
 pub struct Formula {
-    pub expr: exmex::FlatEx<f64>,
+    /// Formula compiled when the registry is built
+    pub program: Program,
+    /// Variables used by the formula, in the order of the program variables
     pub args: Vec<VariableId>,
+    /// Formula as it was written in the item
+    pub source: SerializedFormula,
+}
+
+impl Formula {
+    /// Evaluates the formula with the values of [`Formula::args`]
+    pub fn eval(&self, values: &[f64]) -> f64 {
+        self.program.eval(values)
+    }
+
+    /// Evaluates the formula, pulling the value of the argument with the
+    /// index every time the formula uses it
+    pub fn eval_with(&self, value: impl FnMut(usize) -> f64) -> f64 {
+        self.program.eval_with(value)
+    }
 }
 
 impl ModelDeserializableFallbackType for Formula {
     type Serialized = SerializedFormula;
 }
 
+impl ModelSerializable<SerializedFormula> for Formula {
+    fn serialize(&self, _registry: &ModRegistry) -> SerializedFormula {
+        self.source.clone()
+    }
+}
+
+impl ModelReferences for SerializedFormula {
+    fn collect_references(&self, collector: &mut ReferenceCollector) {
+        let SerializedFormula::String(formula) = self else {
+            return;
+        };
+        collector.formula();
+        // Formulas that don't parse yet don't reference anything, and named
+        // constants are only known once the manifests are loaded
+        let Ok(program) = Program::compile(formula, |_| None) else {
+            return;
+        };
+        for name in program.variables() {
+            collector.nested(
+                DeserializationErrorStackItem::ExprVariable(name.to_string()),
+                |collector| collector.item(DatabaseItemKind::Variable, name),
+            );
+        }
+    }
+}
+
 impl ModelDeserializable<Formula> for SerializedFormula {
     fn deserialize(
         self,
@@ -34,8 +98,9 @@ impl ModelDeserializable<Formula> for SerializedFormula {
                 Formula::deserialize_from(formula.as_str(), registry)
             }
             SerializedFormula::Number(num) => Ok(Formula {
-                expr: exmex::FlatEx::from_num(num),
+                program: Program::constant(num),
                 args: vec![],
+                source: SerializedFormula::Number(num),
             }),
         }
     }
@@ -46,21 +111,83 @@ impl ModelDeserializable<Formula> for &str {
         self,
         registry: &mut PartialModRegistry,
     ) -> Result<Formula, DeserializationError> {
-        let formula = exmex::parse::<f64>(self)?;
-
-        let args = formula
-            .var_names()
-            .iter()
-            .map(|id| {
-                VariableId::deserialize_from(id.as_str(), registry).map_err(|e| {
-                    e.context(DeserializationErrorStackItem::ExprVariable(id.to_string()))
-                })
-            })
-            .try_collect()?;
+        let program = Program::compile(self, |name| registry.constant(name)).map_err(|err| {
+            // Errors about a function are pointed at its name
+            match err.name.clone() {
+                Some(name) => DeserializationError::from(err)
+                    .context(DeserializationErrorStackItem::ExprFunction(name)),
+                None => DeserializationError::from(err),
+            }
+        })?;
+
+        let args = collect_all(program.variables().iter().map(|id| {
+            VariableId::deserialize_from(id.as_str(), registry)
+                .map_err(|e| e.context(DeserializationErrorStackItem::ExprVariable(id.to_string())))
+        }))?;
 
         Ok(Formula {
-            expr: formula,
+            program,
             args,
+            source: SerializedFormula::String(self.to_string()),
         })
     }
 }
+
+impl PartialModRegistry {
+    /// Value of the named constant referenced by the item that is being
+    /// deserialized, with relative names resolved against its mod
+    fn constant(&self, name: &str) -> Option<f64> {
+        let id = NamespacedId::parse(name).ok()?;
+        let namespace = id.namespace().or(self.namespace.as_deref())?;
+        self.constants.get(namespace)?.get(id.name()).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::Fixture;
+    use crate::model::serialization::{DeserializationError, DeserializationErrorStackItem};
+
+    fn error(formula: &str) -> DeserializationError {
+        let item = format!(
+            r#"{{ type: "Variable", id: "eh:speed", name: "Speed", computed: "{formula}" }}"#
+        );
+        let err = Fixture::default()
+            .file("eh/speed.json5", item)
+            .build()
+            .unwrap_err();
+        let mut errors = err.flatten();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        errors.remove(0)
+    }
+
+    /// Text the error is pointed at
+    fn located(err: &DeserializationError) -> &str {
+        let location = err.location.as_ref().expect("Error should be located");
+        &location.text[location.span.clone()]
+    }
+
+    #[test]
+    fn function_errors_point_at_the_function() {
+        let err = error("1 + unknown(2)");
+        assert!(
+            matches!(&err.stack[0], DeserializationErrorStackItem::ExprFunction(name) if name == "unknown"),
+            "{err}"
+        );
+        assert!(
+            err.to_string().contains("In expression function `unknown`"),
+            "{err}"
+        );
+        assert_eq!(located(&err), "unknown");
+    }
+
+    #[test]
+    fn missing_variables_point_at_the_variable() {
+        let err = error("{eh:missing} * 2");
+        assert!(
+            matches!(&err.stack[0], DeserializationErrorStackItem::ExprVariable(name) if name == "eh:missing"),
+            "{err}"
+        );
+        assert_eq!(located(&err), "eh:missing");
+    }
+}
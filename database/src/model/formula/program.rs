@@ -0,0 +1,715 @@
+//! Formulas are compiled once, when the registry is built, into a flat list
+//! of operations on a small stack. Evaluation then walks the list without
+//! allocating, pulling the values of the variables only when they are used.
+//!
+//! Operators have the priorities, from the lowest: comparisons, `+`, `-`,
+//! `*`, `/` and `^`. Operators of the same priority are applied from left to
+//! right, and unary operators and functions apply to the operand right after
+//! them, so `-2^2` is `4`. Comparisons give `1` when they hold and `0`
+//! otherwise, and `if` treats every value other than `0` as true.
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use thiserror::Error;
+
+/// Limit on the number of values kept on the stack at once, which grows with
+/// the nesting of the formula
+const MAX_STACK: usize = 64;
+
+/// Error in the text of the formula
+#[derive(Debug, Clone, Error)]
+#[error("{message} at offset {offset}")]
+pub struct FormulaError {
+    pub message: String,
+    /// Byte offset of the error inside the formula
+    pub offset: usize,
+    /// Name of the function the error is about, if any
+    pub name: Option<String>,
+}
+
+impl FormulaError {
+    fn new(message: impl Into<String>, offset: usize) -> Self {
+        Self {
+            message: message.into(),
+            offset,
+            name: None,
+        }
+    }
+
+    fn named(message: impl Into<String>, offset: usize, name: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            ..Self::new(message, offset)
+        }
+    }
+}
+
+/// Formula compiled into operations on a stack
+#[derive(Debug, Clone)]
+pub struct Program {
+    ops: Box<[Op]>,
+    /// Names of the variables used by the formula, sorted and without
+    /// duplicates, in the order the values are pulled by their index
+    variables: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Op {
+    Num(f64),
+    /// Value of the variable with the index
+    Var(usize),
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+    Ternary(fn(f64, f64, f64) -> f64),
+}
+
+/// Built-in function along with the number of arguments it takes
+#[derive(Debug, Copy, Clone)]
+enum Function {
+    Unary(fn(f64) -> f64),
+    Binary(fn(f64, f64) -> f64),
+    Ternary(fn(f64, f64, f64) -> f64),
+    /// Function of any number of arguments, but at least two, folded from
+    /// the left
+    Fold(fn(f64, f64) -> f64),
+}
+
+impl Program {
+    /// Program always evaluating to the value
+    pub fn constant(value: f64) -> Self {
+        Self {
+            ops: Box::new([Op::Num(value)]),
+            variables: Vec::new(),
+        }
+    }
+
+    /// Compiles the formula, looking the names that are neither built-in
+    /// functions nor constants up in the named constants first, and treating
+    /// the rest as variables
+    pub fn compile(
+        text: &str,
+        named_constant: impl Fn(&str) -> Option<f64>,
+    ) -> Result<Self, FormulaError> {
+        let mut parser = Parser {
+            tokens: Lexer::new(text).peekable(),
+            end: text.len(),
+            named_constant: &named_constant,
+            ops: Vec::new(),
+            names: Vec::new(),
+            depth: 0,
+            max_depth: 0,
+        };
+        parser.expression(0)?;
+        if let Some(token) = parser.tokens.next() {
+            let (offset, token) = token?;
+            return Err(FormulaError::new(format!("Unexpected {token}"), offset));
+        }
+        if parser.max_depth > MAX_STACK {
+            return Err(FormulaError::new("Formula is nested too deeply", 0));
+        }
+
+        // Variables are looked up by their sorted position, rather than the
+        // order they are used in
+        let mut variables = parser.names.clone();
+        variables.sort();
+        variables.dedup();
+        let ops = parser
+            .ops
+            .into_iter()
+            .map(|op| match op {
+                Op::Var(i) => Op::Var(
+                    variables
+                        .binary_search(&parser.names[i])
+                        .expect("Variable should be collected"),
+                ),
+                op => op,
+            })
+            .collect();
+        Ok(Self { ops, variables })
+    }
+
+    /// Names of the variables used by the formula, in the order of their
+    /// indices
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Evaluates the program with the values of the variables, given in the
+    /// order of [`Program::variables`]
+    pub fn eval(&self, values: &[f64]) -> f64 {
+        self.eval_with(|i| values[i])
+    }
+
+    /// Evaluates the program, pulling the value of the variable with the
+    /// index every time the program uses it
+    pub fn eval_with(&self, mut value: impl FnMut(usize) -> f64) -> f64 {
+        let mut stack = [0.0; MAX_STACK];
+        let mut len = 0;
+        for op in self.ops.iter() {
+            match *op {
+                Op::Num(num) => {
+                    stack[len] = num;
+                    len += 1;
+                }
+                Op::Var(i) => {
+                    stack[len] = value(i);
+                    len += 1;
+                }
+                Op::Unary(apply) => stack[len - 1] = apply(stack[len - 1]),
+                Op::Binary(apply) => {
+                    len -= 1;
+                    stack[len - 1] = apply(stack[len - 1], stack[len]);
+                }
+                Op::Ternary(apply) => {
+                    len -= 2;
+                    stack[len - 1] = apply(stack[len - 1], stack[len], stack[len + 1]);
+                }
+            }
+        }
+        stack[0]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Name(String),
+    Op(&'static str),
+    Open,
+    Close,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Num(num) => write!(f, "number `{num}`"),
+            Token::Name(name) => write!(f, "name `{name}`"),
+            Token::Op(op) => write!(f, "operator `{op}`"),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            chars: text.char_indices().peekable(),
+        }
+    }
+
+    /// Consumes the characters matching the predicate, returning the end of
+    /// the consumed text
+    fn consume_while(&mut self, mut predicate: impl FnMut(char) -> bool) -> usize {
+        while let Some(&(i, c)) = self.chars.peek() {
+            if !predicate(c) {
+                return i;
+            }
+            self.chars.next();
+        }
+        self.text.len()
+    }
+
+    fn number(&mut self, start: usize) -> Result<Token, FormulaError> {
+        let mut end = self.consume_while(|c| c.is_ascii_digit() || c == '.');
+        // Exponent is only taken if digits follow it
+        let rest = &self.text[end..];
+        if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+            let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            if digits.starts_with(|c: char| c.is_ascii_digit()) {
+                for _ in 0..rest.len() - digits.len() {
+                    self.chars.next();
+                }
+                end = self.consume_while(|c| c.is_ascii_digit());
+            }
+        }
+        let text = &self.text[start..end];
+        text.parse()
+            .map(Token::Num)
+            .map_err(|_| FormulaError::new(format!("`{text}` is not a valid number"), start))
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<(usize, Token), FormulaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.consume_while(char::is_whitespace);
+        let (start, c) = *self.chars.peek()?;
+        let token = match c {
+            '0'..='9' | '.' => self.number(start),
+            '{' => {
+                // Braces hold the names that aren't made of letters, digits
+                // and `_` only, such as qualified IDs
+                self.chars.next();
+                let end = self.consume_while(|c| c != '}');
+                match self.chars.next() {
+                    Some(_) => Ok(Token::Name(self.text[start + 1..end].trim().to_string())),
+                    None => Err(FormulaError::new("Unclosed `{`", start)),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let end = self.consume_while(|c| c.is_alphanumeric() || c == '_');
+                Ok(Token::Name(self.text[start..end].to_string()))
+            }
+            '(' => {
+                self.chars.next();
+                Ok(Token::Open)
+            }
+            ')' => {
+                self.chars.next();
+                Ok(Token::Close)
+            }
+            ',' => {
+                self.chars.next();
+                Ok(Token::Comma)
+            }
+            c => {
+                // Longer operators come first, so `<=` isn't taken for `<`
+                let rest = &self.text[start..];
+                match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+                    Some(op) => {
+                        for _ in 0..op.len() {
+                            self.chars.next();
+                        }
+                        Ok(Token::Op(op))
+                    }
+                    None => {
+                        self.chars.next();
+                        Err(FormulaError::new(
+                            format!("Unexpected character `{c}`"),
+                            start,
+                        ))
+                    }
+                }
+            }
+        };
+        Some(token.map(|token| (start, token)))
+    }
+}
+
+struct Parser<'a> {
+    tokens: Peekable<Lexer<'a>>,
+    /// Length of the text, where errors about its end point to
+    end: usize,
+    named_constant: &'a dyn Fn(&str) -> Option<f64>,
+    ops: Vec<Op>,
+    /// Names of the variables, in the order they are used
+    names: Vec<String>,
+    /// Number of values on the stack after the emitted operations
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Parser<'_> {
+    /// Parses the operators with the priority of at least the given one,
+    /// along with their operands
+    fn expression(&mut self, priority: u8) -> Result<(), FormulaError> {
+        self.unary()?;
+        loop {
+            let op = match self.tokens.peek() {
+                Some(Ok((_, Token::Op(op)))) => *op,
+                _ => return Ok(()),
+            };
+            let (op_priority, apply) = binary(op);
+            if op_priority < priority {
+                return Ok(());
+            }
+            self.tokens.next();
+            self.expression(op_priority + 1)?;
+            self.emit(Op::Binary(apply));
+        }
+    }
+
+    /// Parses the operand along with the unary operators and functions
+    /// applied to it
+    fn unary(&mut self) -> Result<(), FormulaError> {
+        let (offset, token) = self.next()?;
+        match token {
+            Token::Op("-") => {
+                self.unary()?;
+                self.emit(Op::Unary(|x| -x));
+            }
+            Token::Op("+") => self.unary()?,
+            Token::Num(num) => self.emit(Op::Num(num)),
+            Token::Open => {
+                self.expression(0)?;
+                self.expect_close()?;
+            }
+            Token::Name(name) => {
+                if let Some(function) = function(&name) {
+                    self.call(function, &name, offset)?;
+                } else if let Some(value) = constant(&name).or_else(|| (self.named_constant)(&name))
+                {
+                    self.emit(Op::Num(value));
+                } else if let Some(Ok((_, Token::Open))) = self.tokens.peek() {
+                    return Err(FormulaError::named(
+                        format!("Unknown function `{name}`"),
+                        offset,
+                        &name,
+                    ));
+                } else {
+                    self.names.push(name);
+                    self.emit(Op::Var(self.names.len() - 1));
+                }
+            }
+            token => {
+                return Err(FormulaError::new(
+                    format!("Expected a value, got {token}"),
+                    offset,
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses the arguments of the function and applies it to them
+    ///
+    /// Functions of a single argument may omit the parentheses, applying to
+    /// the operand right after them
+    fn call(&mut self, function: Function, name: &str, offset: usize) -> Result<(), FormulaError> {
+        let Some(Ok((_, Token::Open))) = self.tokens.peek() else {
+            return match function {
+                Function::Unary(apply) => {
+                    self.unary()?;
+                    self.emit(Op::Unary(apply));
+                    Ok(())
+                }
+                _ => Err(FormulaError::named(
+                    format!("Arguments of `{name}` must be enclosed in parentheses"),
+                    offset,
+                    name,
+                )),
+            };
+        };
+        self.tokens.next();
+        let mut count = 0;
+        loop {
+            self.expression(0)?;
+            count += 1;
+            if let Some(Ok((_, Token::Comma))) = self.tokens.peek() {
+                self.tokens.next();
+            } else {
+                break;
+            }
+        }
+        self.expect_close()?;
+
+        let (expected, op) = match function {
+            Function::Unary(apply) => ("1 argument", (count == 1).then_some(Op::Unary(apply))),
+            Function::Binary(apply) => ("2 arguments", (count == 2).then_some(Op::Binary(apply))),
+            Function::Ternary(apply) => ("3 arguments", (count == 3).then_some(Op::Ternary(apply))),
+            Function::Fold(apply) => (
+                "at least 2 arguments",
+                (count >= 2).then_some(Op::Binary(apply)),
+            ),
+        };
+        let Some(op) = op else {
+            return Err(FormulaError::named(
+                format!("`{name}` takes {expected}, got {count}"),
+                offset,
+                name,
+            ));
+        };
+        // Folded functions are applied once for every argument after the
+        // first one
+        let times = if let Function::Fold(_) = function {
+            count - 1
+        } else {
+            1
+        };
+        for _ in 0..times {
+            self.emit(op);
+        }
+        Ok(())
+    }
+
+    fn expect_close(&mut self) -> Result<(), FormulaError> {
+        match self.next()? {
+            (_, Token::Close) => Ok(()),
+            (offset, token) => Err(FormulaError::new(
+                format!("Expected `)`, got {token}"),
+                offset,
+            )),
+        }
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), FormulaError> {
+        self.tokens
+            .next()
+            .unwrap_or_else(|| Err(FormulaError::new("Unexpected end of the formula", self.end)))
+    }
+
+    /// Emits the operation, applying it right away to constant operands
+    fn emit(&mut self, op: Op) {
+        match (op, self.ops.as_slice()) {
+            (Op::Unary(apply), [.., Op::Num(x)]) => {
+                let x = *x;
+                self.ops.pop();
+                self.ops.push(Op::Num(apply(x)));
+            }
+            (Op::Binary(apply), [.., Op::Num(a), Op::Num(b)]) => {
+                let (a, b) = (*a, *b);
+                self.ops.truncate(self.ops.len() - 2);
+                self.ops.push(Op::Num(apply(a, b)));
+                self.depth -= 1;
+            }
+            (Op::Ternary(apply), [.., Op::Num(a), Op::Num(b), Op::Num(c)]) => {
+                let (a, b, c) = (*a, *b, *c);
+                self.ops.truncate(self.ops.len() - 3);
+                self.ops.push(Op::Num(apply(a, b, c)));
+                self.depth -= 2;
+            }
+            (Op::Num(_) | Op::Var(_), _) => {
+                self.ops.push(op);
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+            (Op::Binary(_), _) => {
+                self.ops.push(op);
+                self.depth -= 1;
+            }
+            (Op::Ternary(_), _) => {
+                self.ops.push(op);
+                self.depth -= 2;
+            }
+            (Op::Unary(_), _) => self.ops.push(op),
+        }
+    }
+}
+
+/// Binary operators, with the longer ones before the ones they start with
+const OPERATORS: [&str; 11] = ["<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "^"];
+
+/// Priority and the operation of the binary operator
+fn binary(op: &str) -> (u8, fn(f64, f64) -> f64) {
+    match op {
+        "<" => (0, |a, b| truth(a < b)),
+        "<=" => (0, |a, b| truth(a <= b)),
+        ">" => (0, |a, b| truth(a > b)),
+        ">=" => (0, |a, b| truth(a >= b)),
+        "==" => (0, |a, b| truth(a == b)),
+        "!=" => (0, |a, b| truth(a != b)),
+        "+" => (1, |a, b| a + b),
+        "-" => (2, |a, b| a - b),
+        "*" => (3, |a, b| a * b),
+        "/" => (4, |a, b| a / b),
+        "^" => (5, f64::powf),
+        _ => unreachable!("Lexer should only produce known operators"),
+    }
+}
+
+fn truth(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Whether the name is taken by a built-in function or constant, which
+/// always take precedence over the named constants and variables
+pub(crate) fn is_builtin(name: &str) -> bool {
+    function(name).is_some() || constant(name).is_some()
+}
+
+fn constant(name: &str) -> Option<f64> {
+    Some(match name {
+        "PI" | "π" => std::f64::consts::PI,
+        "E" => std::f64::consts::E,
+        "TAU" | "τ" => std::f64::consts::TAU,
+        _ => return None,
+    })
+}
+
+fn function(name: &str) -> Option<Function> {
+    Some(match name {
+        "abs" => Function::Unary(f64::abs),
+        "signum" => Function::Unary(f64::signum),
+        "sin" => Function::Unary(f64::sin),
+        "cos" => Function::Unary(f64::cos),
+        "tan" => Function::Unary(f64::tan),
+        "asin" => Function::Unary(f64::asin),
+        "acos" => Function::Unary(f64::acos),
+        "atan" => Function::Unary(f64::atan),
+        "sinh" => Function::Unary(f64::sinh),
+        "cosh" => Function::Unary(f64::cosh),
+        "tanh" => Function::Unary(f64::tanh),
+        "floor" => Function::Unary(f64::floor),
+        "ceil" => Function::Unary(f64::ceil),
+        "round" => Function::Unary(f64::round),
+        "trunc" => Function::Unary(f64::trunc),
+        "fract" => Function::Unary(f64::fract),
+        "exp" => Function::Unary(f64::exp),
+        "sqrt" => Function::Unary(f64::sqrt),
+        "cbrt" => Function::Unary(f64::cbrt),
+        "ln" | "log" => Function::Unary(f64::ln),
+        "log2" => Function::Unary(f64::log2),
+        "log10" => Function::Unary(f64::log10),
+        "min" => Function::Fold(f64::min),
+        "max" => Function::Fold(f64::max),
+        // Rounds to a multiple of the step
+        "round_to" => Function::Binary(|x, step| (x / step).round() * step),
+        "floor_to" => Function::Binary(|x, step| (x / step).floor() * step),
+        "ceil_to" => Function::Binary(|x, step| (x / step).ceil() * step),
+        // Unlike `f64::clamp`, doesn't panic when the bounds are swapped
+        "clamp" => Function::Ternary(|x, min, max| x.max(min).min(max)),
+        "lerp" => Function::Ternary(|a, b, t| a + (b - a) * t),
+        // Both branches are evaluated, since formulas have no side effects
+        "if" => Function::Ternary(|cond, a, b| if cond != 0.0 { a } else { b }),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use exmex::prelude::*;
+
+    use super::{Program, MAX_STACK};
+
+    fn eval(text: &str, values: &[f64]) -> f64 {
+        Program::compile(text, |_| None)
+            .unwrap_or_else(|err| panic!("`{text}` should compile: {err}"))
+            .eval(values)
+    }
+
+    fn error_offset(text: &str) -> usize {
+        match Program::compile(text, |_| None) {
+            Ok(_) => panic!("`{text}` should not compile"),
+            Err(err) => err.offset,
+        }
+    }
+
+    #[test]
+    fn formulas_match_exmex() {
+        let values = [1.5, -2.0, 0.25];
+        let formulas = [
+            // Operator priorities
+            "1 + 2 - 3",
+            "1 - 2 + 3",
+            "10 - 4 - 3",
+            "2 * 3 / 4 * 5",
+            "12 / 3 / 2",
+            "2 * 3 ^ 2",
+            "2 ^ 3 ^ 2",
+            "1 + 2 * 3 - 4 / 5 ^ 2",
+            "-2^2",
+            "-(2^2)",
+            "2 * -3",
+            // Functions, with and without parentheses
+            "sin x",
+            "sin x ^ 2",
+            "sin(x ^ 2)",
+            "-sin x * cos y",
+            "sqrt(abs(y)) + exp z",
+            "ln 10 + log10 1000 + log2 8",
+            "floor(x) + ceil(x) + round(x) + trunc(y) + fract(x)",
+            "tanh x + atan y",
+            "signum y * cbrt 27",
+            // Numbers and names
+            "PI * x + E + TAU + π + τ",
+            "{x} * {y} + {z}",
+            "x * y + z",
+        ];
+        for text in formulas {
+            let expected = exmex::parse::<f64>(text)
+                .unwrap_or_else(|err| panic!("exmex should parse `{text}`: {err}"));
+            let program = Program::compile(text, |_| None)
+                .unwrap_or_else(|err| panic!("`{text}` should compile: {err}"));
+            assert_eq!(
+                program.variables().len(),
+                expected.var_names().len(),
+                "variables of `{text}`"
+            );
+            let values = &values[..program.variables().len()];
+            let expected = expected.eval(values).unwrap();
+            let actual = program.eval(values);
+            assert!(
+                actual == expected || actual.is_nan() && expected.is_nan(),
+                "`{text}` gives {actual}, exmex gives {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn extensions_evaluate() {
+        assert_eq!(eval(".5 + 2.", &[]), 2.5);
+        assert_eq!(eval("1e3 + 2.5E-2 + 1.5e+1", &[]), 1015.025);
+        assert_eq!(eval("1 + 1 == 2", &[]), 1.0);
+        assert_eq!(eval("1 < 2 + 3", &[]), 1.0);
+        assert_eq!(eval("x >= 2", &[1.0]), 0.0);
+        assert_eq!(eval("if(x > 0, 10, 20)", &[-1.0]), 20.0);
+        assert_eq!(eval("min(3, x, 2)", &[1.0]), 1.0);
+        assert_eq!(eval("max(3, x, 2)", &[1.0]), 3.0);
+        assert_eq!(eval("clamp(x, 0, 1)", &[1.5]), 1.0);
+        assert_eq!(eval("clamp(x, 1, 0)", &[0.5]), 0.0);
+        assert_eq!(eval("lerp(10, 20, x)", &[0.25]), 12.5);
+        assert_eq!(eval("round_to(x, 0.5)", &[1.3]), 1.5);
+        assert_eq!(eval("floor_to(x, 5)", &[14.0]), 10.0);
+        assert_eq!(eval("ceil_to(x, 5)", &[11.0]), 15.0);
+    }
+
+    #[test]
+    fn named_constants_take_precedence_over_variables() {
+        let program = Program::compile("scale * x", |name| (name == "scale").then_some(2.0))
+            .expect("Formula should compile");
+        assert_eq!(program.variables(), ["x"]);
+        assert_eq!(program.eval(&[3.0]), 6.0);
+    }
+
+    #[test]
+    fn variables_are_sorted() {
+        let program = Program::compile("b - a + b", |_| None).expect("Formula should compile");
+        assert_eq!(program.variables(), ["a", "b"]);
+        assert_eq!(program.eval(&[1.0, 10.0]), 19.0);
+    }
+
+    #[test]
+    fn nesting_is_limited_by_the_stack() {
+        let nested = |depth: usize| {
+            let mut text = "x".to_string();
+            for _ in 1..depth {
+                text = format!("x + ({text})");
+            }
+            text
+        };
+        assert_eq!(eval(&nested(MAX_STACK), &[1.0]), MAX_STACK as f64);
+        assert_eq!(error_offset(&nested(MAX_STACK + 1)), 0);
+
+        // Constant operands are folded, but still count while parsed
+        let folded = format!("{}1{}", "1 + (".repeat(MAX_STACK), ")".repeat(MAX_STACK));
+        assert!(Program::compile(&folded, |_| None).is_err());
+    }
+
+    #[test]
+    fn errors_point_to_the_offending_text() {
+        assert_eq!(error_offset("1 + "), 4);
+        assert_eq!(error_offset("1 + * 2"), 4);
+        assert_eq!(error_offset("(1 + 2"), 6);
+        assert_eq!(error_offset("1 + 2)"), 5);
+        assert_eq!(error_offset("1 $ 2"), 2);
+        assert_eq!(error_offset("1 + {x"), 4);
+        assert_eq!(error_offset("1.2.3 + 1"), 0);
+        assert_eq!(error_offset("2 * foo(1)"), 4);
+        assert_eq!(error_offset("1 + min 2"), 4);
+        assert_eq!(error_offset("clamp(1, 2)"), 0);
+
+        let err = Program::compile("2 * foo(1)", |_| None).unwrap_err();
+        assert_eq!(err.name.as_deref(), Some("foo"));
+    }
+
+    #[test]
+    fn lexer_handles_multibyte_text_after_numbers() {
+        assert_eq!(error_offset("2π"), 1);
+        assert_eq!(error_offset("2e€"), 1);
+    }
+}
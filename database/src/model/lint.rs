@@ -0,0 +1,317 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use miette::Diagnostic;
+use rustc_hash::FxHashSet;
+use thiserror::Error;
+
+use crate::model::{DatabaseItemKind, ItemId, ModRegistry, RegistryId, UnknownField};
+
+/// Kinds of items that are used by the game directly, every other item has
+/// to be reachable from one of them
+const ROOT_KINDS: &[DatabaseItemKind] = &[
+    DatabaseItemKind::Fleet,
+    DatabaseItemKind::CombatSettings,
+    DatabaseItemKind::ModSettings,
+];
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum LintSeverity {
+    /// Lint is not checked at all
+    Allow,
+    #[default]
+    Warn,
+    /// Lint fails the validation
+    Deny,
+}
+
+impl Display for LintSeverity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintSeverity::Allow => write!(f, "allow"),
+            LintSeverity::Warn => write!(f, "warn"),
+            LintSeverity::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+impl FromStr for LintSeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(LintSeverity::Allow),
+            "warn" => Ok(LintSeverity::Warn),
+            "deny" => Ok(LintSeverity::Deny),
+            _ => Err(format!(
+                "Unknown lint severity `{s}`, expected `allow`, `warn` or `deny`"
+            )),
+        }
+    }
+}
+
+/// Severities of every lint
+#[derive(Debug, Clone, Default)]
+pub struct LintSettings {
+    /// Items that can't be reached from fleets, combat settings or mod
+    /// manifests
+    pub unused_items: LintSeverity,
+    /// Images that no ship uses as a sprite
+    pub unused_images: LintSeverity,
+    /// Fields of the files that none of their items declare, usually typos
+    pub unknown_fields: LintSeverity,
+}
+
+#[derive(Debug, Error, Clone)]
+pub enum LintKind {
+    #[error("Item {}({}) is not used by any fleet, combat settings or mod manifest", .1, .0)]
+    UnusedItem(ItemId, DatabaseItemKind),
+    #[error("Image `{}` at `{}` is not used by any ship", .0, .1.to_string_lossy())]
+    UnusedImage(String, PathBuf),
+    #[error("Unknown field {} in `{}`", .0, .1.to_string_lossy())]
+    UnknownField(UnknownField, PathBuf),
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("{}", .kind)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub severity: LintSeverity,
+}
+
+impl Diagnostic for Lint {
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(match self.severity {
+            LintSeverity::Deny => miette::Severity::Error,
+            LintSeverity::Allow | LintSeverity::Warn => miette::Severity::Warning,
+        })
+    }
+}
+
+impl ModRegistry {
+    /// Checks the registry for the items and images that are loaded, but not
+    /// used by anything, and for the fields of the files no item declares
+    ///
+    /// Items taking part in inheritance are never reported, since parents
+    /// are only used through the data of the items extending them
+    pub fn lint(&self, settings: &LintSettings) -> Vec<Lint> {
+        let mut lints = Vec::new();
+
+        if settings.unused_items != LintSeverity::Allow {
+            let reachable = self.reachable_items();
+            let mut unused = DatabaseItemKind::ALL
+                .iter()
+                .flat_map(|kind| self.keys(*kind))
+                .filter(|(id, key)| {
+                    !reachable.contains(id) && !self.has_inheritance(id.kind(), key)
+                })
+                .map(|(id, key)| (id.kind(), key))
+                .collect::<Vec<_>>();
+            unused.sort_by(|a, b| (a.0.name(), a.1).cmp(&(b.0.name(), b.1)));
+            lints.extend(unused.into_iter().map(|(kind, key)| Lint {
+                kind: LintKind::UnusedItem(key.clone(), kind),
+                severity: settings.unused_items,
+            }));
+        }
+
+        if settings.unused_images != LintSeverity::Allow {
+            let used = self
+                .ship
+                .values()
+                .map(|ship| ship.data.sprite.id())
+                .collect::<FxHashSet<_>>();
+            let mut unused = self
+                .assets
+                .images
+                .iter()
+                .filter(|(_, (_, handle))| !used.contains(&handle.id()))
+                .map(|(name, (path, _))| (name, path))
+                .collect::<Vec<_>>();
+            unused.sort();
+            lints.extend(unused.into_iter().map(|(name, path)| Lint {
+                kind: LintKind::UnusedImage(name.clone(), path.clone()),
+                severity: settings.unused_images,
+            }));
+        }
+
+        if settings.unknown_fields != LintSeverity::Allow {
+            let mut unknown = self.unknown_fields().collect::<Vec<_>>();
+            unknown.sort_by_key(|(path, field)| (*path, field.path()));
+            lints.extend(unknown.into_iter().map(|(path, field)| Lint {
+                kind: LintKind::UnknownField(field.clone(), path.to_path_buf()),
+                severity: settings.unknown_fields,
+            }));
+        }
+
+        lints
+    }
+
+    /// Items of the root kinds along with everything they reference, directly
+    /// or through other items
+    fn reachable_items(&self) -> FxHashSet<RegistryId> {
+        let mut pending = ROOT_KINDS
+            .iter()
+            .flat_map(|kind| self.keys(*kind))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        let mut reachable = pending.iter().copied().collect::<FxHashSet<_>>();
+        while let Some(item) = pending.pop() {
+            for dependency in self.dependencies().dependencies(item) {
+                if reachable.insert(dependency) {
+                    pending.push(dependency);
+                }
+            }
+        }
+        reachable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use bevy::asset::Handle;
+
+    use crate::fixture::{layer, Fixture};
+    use crate::model::{DatabaseItemKind, ModRegistry};
+
+    use super::{LintKind, LintSettings, LintSeverity};
+
+    const SETTINGS: &str = r#"{
+  type: "CombatSettings",
+  id: "eh:default",
+  playerFleet: "eh:fleet",
+  enemyFleet: "eh:fleet",
+  enemyFleat: "eh:fleet",
+}"#;
+
+    fn registry() -> ModRegistry {
+        Fixture::default()
+            .file("eh/settings.json", SETTINGS)
+            .build()
+            .unwrap()
+    }
+
+    /// Unknown fields reported with the severity, along with the severities
+    /// of their lints
+    fn unknown_fields(severity: LintSeverity) -> Vec<(String, LintSeverity)> {
+        let settings = LintSettings {
+            unused_items: LintSeverity::Allow,
+            unused_images: LintSeverity::Allow,
+            unknown_fields: severity,
+        };
+        registry()
+            .lint(&settings)
+            .into_iter()
+            .map(|lint| match lint.kind {
+                LintKind::UnknownField(field, path) => {
+                    assert_eq!(path, Path::new("eh/settings.json"));
+                    (field.to_string(), lint.severity)
+                }
+                kind => panic!("Unexpected lint {kind}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unknown_fields_are_warned_about_by_default() {
+        assert_eq!(
+            unknown_fields(LintSeverity::default()),
+            [(
+                "`enemyFleat`, did you mean `enemyFleet`?".to_string(),
+                LintSeverity::Warn
+            )]
+        );
+        assert!(unknown_fields(LintSeverity::Allow).is_empty());
+    }
+
+    #[test]
+    fn strict_mode_denies_unknown_fields() {
+        let denied = unknown_fields(LintSeverity::Deny);
+        assert_eq!(denied.len(), 1);
+        assert_eq!(denied[0].1, LintSeverity::Deny);
+    }
+
+    const SHIPS: &str = r#"[
+  { type: "Fleet", id: "eh:fleet", builds: ["eh:scout"] },
+  { type: "ShipBuild", id: "eh:scout", ship: "eh:scout", components: [] },
+  { type: "Ship", id: "eh:scout", sprite: "Scout.png", modelScale: 1 },
+  { type: "Ship", id: "eh:drone", sprite: "drone.png", modelScale: 1 },
+  { type: "Variable", id: "eh:speed", name: "Speed" },
+  { type: "Variable", id: "eh:boost", name: "Boost", computed: "{eh:speed} * 2" },
+]"#;
+
+    /// Registry of [SHIPS] with the images of the names
+    fn ships(images: &[&str]) -> ModRegistry {
+        let assets = Fixture::default().file("eh/fleet.json", SHIPS).assets();
+        let mut layer = layer("eh", &assets);
+        layer.images = images
+            .iter()
+            .zip(1..)
+            .map(|(name, id)| {
+                (
+                    PathBuf::from("eh/images").join(name),
+                    Handle::weak_from_u128(id),
+                )
+            })
+            .collect();
+        ModRegistry::build([layer]).unwrap()
+    }
+
+    /// Settings with every lint but the selected one allowed
+    fn only(lint: fn(&mut LintSettings) -> &mut LintSeverity) -> LintSettings {
+        let mut settings = LintSettings {
+            unused_items: LintSeverity::Allow,
+            unused_images: LintSeverity::Allow,
+            unknown_fields: LintSeverity::Allow,
+        };
+        *lint(&mut settings) = LintSeverity::Warn;
+        settings
+    }
+
+    #[test]
+    fn items_unreachable_from_roots_are_reported() {
+        let registry = ships(&["scout.png", "drone.png"]);
+        let unused = registry
+            .lint(&only(|s| &mut s.unused_items))
+            .into_iter()
+            .map(|lint| match lint.kind {
+                LintKind::UnusedItem(id, kind) => (kind, id),
+                kind => panic!("Unexpected lint {kind}"),
+            })
+            .collect::<Vec<_>>();
+
+        // References from unreachable items don't make their targets used
+        assert_eq!(
+            unused,
+            [
+                (DatabaseItemKind::Ship, "eh:drone".to_string()),
+                (DatabaseItemKind::Variable, "eh:boost".to_string()),
+                (DatabaseItemKind::Variable, "eh:speed".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn images_without_ships_are_reported() {
+        let registry = ships(&["scout.png", "drone.png", "Unused.png"]);
+        let unused = registry
+            .lint(&only(|s| &mut s.unused_images))
+            .into_iter()
+            .map(|lint| match lint.kind {
+                LintKind::UnusedImage(name, path) => (name, path),
+                kind => panic!("Unexpected lint {kind}"),
+            })
+            .collect::<Vec<_>>();
+
+        // Ships use images whether they are reachable or not
+        assert_eq!(
+            unused,
+            [(
+                "unused.png".to_string(),
+                PathBuf::from("eh/images/Unused.png")
+            )]
+        );
+    }
+}
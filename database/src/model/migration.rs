@@ -0,0 +1,437 @@
+//! Items are written in the data version of their mod, which the manifest
+//! declares in its `dataVersion` field, unless they declare a version of
+//! their own. Before the items get deserialized, they are upgraded to the
+//! current version by applying the migrations one by one, each working on
+//! the raw fields of a single item.
+//!
+//! Mods that don't declare the version are assumed to be written in the
+//! version 0, the one before the versions were introduced. Manifests are
+//! never migrated, since they declare the version themselves.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::model::bundle::{raw_items, TYPE_TAG};
+use crate::model::serialization::{
+    DeserializationError, DeserializationErrorKind, DeserializationErrorStackItem,
+};
+use crate::model::DatabaseItemKind;
+
+/// Version of the shape of the items
+pub type DataVersion = u32;
+
+/// Fields of a single item
+pub type ItemFields = Map<String, Value>;
+
+/// Field declaring the data version of the item, or of the whole mod in the
+/// manifest
+pub const DATA_VERSION_FIELD: &str = "dataVersion";
+
+/// Data version of the current shape of the items
+pub const CURRENT_DATA_VERSION: DataVersion = MIGRATIONS.len() as DataVersion;
+
+/// Upgrade of the items from the previous data version
+struct Migration {
+    /// Upgrades a single item, possibly splitting it into several ones
+    upgrade: fn(ItemFields) -> Result<Vec<ItemFields>, String>,
+}
+
+/// Migrations in the version order, the first one upgrading the items from
+/// the version 0 to the version 1
+const MIGRATIONS: &[Migration] = &[];
+
+/// Kind of the item, as written in its `type` tag
+pub fn item_type(item: &ItemFields) -> Option<&str> {
+    item.get(TYPE_TAG)?.as_str()
+}
+
+/// Renames the field of the item, keeping its value
+///
+/// Fields that are already present under the new name are left as they are
+pub fn rename_field(item: &mut ItemFields, from: &str, to: &str) {
+    if item.contains_key(to) {
+        return;
+    }
+    if let Some(value) = item.remove(from) {
+        item.insert(to.to_string(), value);
+    }
+}
+
+/// Replaces the string value of the field, such as a tag of an enum
+pub fn rename_value(item: &mut ItemFields, field: &str, from: &str, to: &str) {
+    if let Some(value) = item.get_mut(field) {
+        if value.as_str() == Some(from) {
+            *value = Value::String(to.to_string());
+        }
+    }
+}
+
+/// Moves the fields out of the item into a new item of the kind, for
+/// splitting an item into several ones
+///
+/// New item has to be given an ID, and is usually referenced by the
+/// original one
+pub fn split_fields(
+    item: &mut ItemFields,
+    kind: DatabaseItemKind,
+    id: String,
+    fields: &[&str],
+) -> ItemFields {
+    let mut split = ItemFields::new();
+    split.insert(TYPE_TAG.to_string(), Value::String(kind.to_string()));
+    split.insert("id".to_string(), Value::String(id));
+    for field in fields {
+        if let Some(value) = item.remove(*field) {
+            split.insert(field.to_string(), value);
+        }
+    }
+    split
+}
+
+/// Data version declared by the manifest among the files of the mod, or
+/// `None` if none of the files hold the manifest
+pub fn mod_data_version<'a>(
+    files: impl IntoIterator<Item = (&'a Path, &'a Value)>,
+) -> Result<Option<DataVersion>, DeserializationError> {
+    for (path, file) in files {
+        for (position, item) in raw_items(file) {
+            let Some(item) = item.as_object().filter(|item| is_manifest(item)) else {
+                continue;
+            };
+            let version = match item.get(DATA_VERSION_FIELD) {
+                Some(version) => parse_version(version, CURRENT_DATA_VERSION).map_err(|err| {
+                    DeserializationError::from(err)
+                        .context(DeserializationErrorStackItem::Field("data_version"))
+                        .at_position(position)
+                        .context(DeserializationErrorStackItem::File(path.to_path_buf()))
+                })?,
+                None => 0,
+            };
+            return Ok(Some(version));
+        }
+    }
+    Ok(None)
+}
+
+/// Upgrades the items of the file from the data version of the mod to the
+/// current one
+///
+/// Items declaring their own version are upgraded from it instead. The
+/// version fields are dropped from all items but the manifests, since the
+/// upgraded items are always in the current version
+pub(crate) fn migrate(
+    file: &Value,
+    version: DataVersion,
+) -> Result<Cow<'_, Value>, DeserializationError> {
+    migrate_with(file, version, MIGRATIONS)
+}
+
+/// Upgrades the items of the file with the migrations, the last of which
+/// upgrades them to the current version
+fn migrate_with<'a>(
+    file: &'a Value,
+    version: DataVersion,
+    migrations: &[Migration],
+) -> Result<Cow<'a, Value>, DeserializationError> {
+    let declared = raw_items(file)
+        .into_iter()
+        .filter_map(|(_, item)| item.as_object())
+        .any(|item| item.contains_key(DATA_VERSION_FIELD) && !is_manifest(item));
+    if version == migrations.len() as DataVersion && !declared {
+        return Ok(Cow::Borrowed(file));
+    }
+
+    let mut errors = Vec::new();
+    let mut upgrade = |position: Option<DeserializationErrorStackItem>, item: &Value| {
+        migrate_item(item, version, migrations).unwrap_or_else(|err| {
+            errors.push(err.at_position(position));
+            Vec::new()
+        })
+    };
+    let migrated = match file {
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .flat_map(|(i, item)| upgrade(Some(DeserializationErrorStackItem::Index(i)), item))
+                .collect(),
+        ),
+        Value::Object(fields) if fields.contains_key(TYPE_TAG) => {
+            let mut items = upgrade(None, file);
+            if items.len() == 1 {
+                items.remove(0)
+            } else {
+                Value::Array(items)
+            }
+        }
+        Value::Object(fields) => {
+            let mut out = Map::new();
+            for (key, item) in fields {
+                let items = upgrade(
+                    Some(DeserializationErrorStackItem::MapEntry(key.clone())),
+                    item,
+                );
+                for (i, item) in items.into_iter().enumerate() {
+                    // Items split off the original one are stored under
+                    // their own IDs
+                    let id = match item.get("id").and_then(Value::as_str) {
+                        Some(id) if i > 0 => id.to_string(),
+                        _ => key.clone(),
+                    };
+                    out.insert(id, item);
+                }
+            }
+            Value::Object(out)
+        }
+        other => other.clone(),
+    };
+
+    match DeserializationError::combine(errors) {
+        None => Ok(Cow::Owned(migrated)),
+        Some(err) => Err(err),
+    }
+}
+
+fn migrate_item(
+    item: &Value,
+    version: DataVersion,
+    migrations: &[Migration],
+) -> Result<Vec<Value>, DeserializationError> {
+    // Values that aren't items are reported by the deserialization
+    let Value::Object(fields) = item else {
+        return Ok(vec![item.clone()]);
+    };
+    // Version of the manifest is the version of the whole mod, which is
+    // already checked
+    if is_manifest(fields) {
+        return Ok(vec![item.clone()]);
+    }
+    let version = match fields.get(DATA_VERSION_FIELD) {
+        Some(declared) => {
+            parse_version(declared, migrations.len() as DataVersion).map_err(|err| {
+                DeserializationError::from(err)
+                    .context(DeserializationErrorStackItem::Field("data_version"))
+            })?
+        }
+        None => version,
+    };
+
+    let mut items = vec![fields.clone()];
+    for (i, migration) in migrations.iter().enumerate().skip(version as usize) {
+        let mut upgraded = Vec::with_capacity(items.len());
+        for item in items {
+            let items = (migration.upgrade)(item).map_err(|message| {
+                DeserializationErrorKind::FailedMigration {
+                    version: i as DataVersion + 1,
+                    message,
+                }
+            })?;
+            upgraded.extend(items);
+        }
+        items = upgraded;
+    }
+    Ok(items
+        .into_iter()
+        .map(|mut item| {
+            item.remove(DATA_VERSION_FIELD);
+            Value::Object(item)
+        })
+        .collect())
+}
+
+fn parse_version(
+    value: &Value,
+    current: DataVersion,
+) -> Result<DataVersion, DeserializationErrorKind> {
+    let version = value
+        .as_u64()
+        .and_then(|version| DataVersion::try_from(version).ok())
+        .ok_or_else(|| DeserializationErrorKind::BadDataVersion(value.to_string()))?;
+    if version > current {
+        return Err(DeserializationErrorKind::UnsupportedDataVersion(version));
+    }
+    Ok(version)
+}
+
+fn is_manifest(item: &ItemFields) -> bool {
+    item_type(item) == Some(DatabaseItemKind::ModSettings.to_string().as_str())
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Migrations standing in for the real ones, which only exist once the
+    /// shape of the items changes
+    const SAMPLE_MIGRATIONS: &[Migration] = &[
+        Migration {
+            upgrade: rename_ship_fields,
+        },
+        Migration {
+            upgrade: split_ship_health,
+        },
+    ];
+
+    fn rename_ship_fields(mut item: ItemFields) -> Result<Vec<ItemFields>, String> {
+        if item_type(&item) == Some("Ship") {
+            rename_field(&mut item, "scale", "modelScale");
+            rename_value(&mut item, "engine", "Ion", "Plasma");
+        }
+        Ok(vec![item])
+    }
+
+    fn split_ship_health(mut item: ItemFields) -> Result<Vec<ItemFields>, String> {
+        if item_type(&item) != Some("Ship") || !item.contains_key("health") {
+            return Ok(vec![item]);
+        }
+        let Some(id) = item.get("id").and_then(Value::as_str) else {
+            return Err("Ship with health is missing an ID".to_string());
+        };
+        let id = format!("{id}_health");
+        let variable = split_fields(
+            &mut item,
+            DatabaseItemKind::Variable,
+            id.clone(),
+            &["health"],
+        );
+        item.insert("healthVariable".to_string(), Value::String(id));
+        Ok(vec![item, variable])
+    }
+
+    fn migrated(file: Value, version: DataVersion) -> Value {
+        migrate_with(&file, version, SAMPLE_MIGRATIONS)
+            .expect("File should migrate")
+            .into_owned()
+    }
+
+    #[test]
+    fn single_item_is_migrated() {
+        assert_eq!(
+            migrated(
+                json!({ "type": "Ship", "id": "scout", "scale": 2, "engine": "Ion" }),
+                0
+            ),
+            json!({ "type": "Ship", "id": "scout", "modelScale": 2, "engine": "Plasma" }),
+        );
+    }
+
+    #[test]
+    fn split_single_item_becomes_an_array() {
+        assert_eq!(
+            migrated(json!({ "type": "Ship", "id": "scout", "health": 100 }), 1),
+            json!([
+                { "type": "Ship", "id": "scout", "healthVariable": "scout_health" },
+                { "type": "Variable", "id": "scout_health", "health": 100 },
+            ]),
+        );
+    }
+
+    #[test]
+    fn array_items_are_migrated_from_their_own_versions() {
+        let file = json!([
+            { "type": "ModSettings", "id": "mod", "dataVersion": 0 },
+            { "type": "Ship", "id": "a", "scale": 1, "health": 10 },
+            { "type": "Ship", "id": "b", "dataVersion": 1, "scale": 1, "engine": "Ion" },
+            { "type": "Ship", "id": "c", "dataVersion": 2, "scale": 1 },
+        ]);
+        assert_eq!(
+            migrated(file, 0),
+            json!([
+                { "type": "ModSettings", "id": "mod", "dataVersion": 0 },
+                { "type": "Ship", "id": "a", "modelScale": 1, "healthVariable": "a_health" },
+                { "type": "Variable", "id": "a_health", "health": 10 },
+                { "type": "Ship", "id": "b", "scale": 1, "engine": "Ion" },
+                { "type": "Ship", "id": "c", "scale": 1 },
+            ]),
+        );
+    }
+
+    #[test]
+    fn map_items_split_off_are_stored_under_their_ids() {
+        let file = json!({
+            "a": { "type": "Ship", "id": "a", "health": 10 },
+            "b": { "type": "Ship", "scale": 2 },
+        });
+        assert_eq!(
+            migrated(file, 0),
+            json!({
+                "a": { "type": "Ship", "id": "a", "healthVariable": "a_health" },
+                "a_health": { "type": "Variable", "id": "a_health", "health": 10 },
+                "b": { "type": "Ship", "modelScale": 2 },
+            }),
+        );
+    }
+
+    #[test]
+    fn current_files_are_not_copied() {
+        let file = json!([{ "type": "Ship", "id": "a", "scale": 1 }]);
+        assert!(matches!(
+            migrate_with(&file, 2, SAMPLE_MIGRATIONS),
+            Ok(Cow::Borrowed(_))
+        ));
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let file = json!([
+            { "type": "Ship", "id": "a" },
+            { "type": "Ship", "id": "b", "dataVersion": 3 },
+        ]);
+        let err = migrate_with(&file, 0, SAMPLE_MIGRATIONS).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            DeserializationErrorKind::UnsupportedDataVersion(3)
+        ));
+        assert!(matches!(
+            err.stack.as_slice(),
+            [
+                DeserializationErrorStackItem::Field("data_version"),
+                DeserializationErrorStackItem::Index(1),
+            ]
+        ));
+
+        let manifest = json!({
+            "type": "ModSettings",
+            "id": "mod",
+            "dataVersion": CURRENT_DATA_VERSION + 1,
+        });
+        let err = mod_data_version([(Path::new("mod.json"), &manifest)]).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            DeserializationErrorKind::UnsupportedDataVersion(version)
+                if version == CURRENT_DATA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn failed_migrations_report_their_version() {
+        let file = json!({ "type": "Ship", "health": 10 });
+        let err = migrate_with(&file, 0, SAMPLE_MIGRATIONS).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            DeserializationErrorKind::FailedMigration { version: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn mod_version_is_read_from_the_manifest() {
+        let ship = json!({ "type": "Ship", "id": "a" });
+        let manifest = json!([ship.clone(), { "type": "ModSettings", "id": "mod" }]);
+        assert_eq!(
+            mod_data_version([(Path::new("ship.json"), &ship)]).unwrap(),
+            None
+        );
+        assert_eq!(
+            mod_data_version([
+                (Path::new("ship.json"), &ship),
+                (Path::new("mod.json"), &manifest),
+            ])
+            .unwrap(),
+            Some(0)
+        );
+    }
+}
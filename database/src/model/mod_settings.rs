@@ -1,13 +1,46 @@
 use database_model_macro::database_model;
+use rustc_hash::FxHashMap;
+use semver::{Version, VersionReq};
 
-use crate::model::CombatSettingsId;
+use crate::model::serialization::{
+    DeserializationError, DeserializationErrorKind, ModelDeserializable,
+    ModelDeserializableFallbackType, ModelSerializable,
+};
+use crate::model::{CombatSettingsId, ItemId, ModRegistry, PartialModRegistry};
 
+/// Manifest of a mod, every mod must declare exactly one
+///
+/// ID of the manifest is used as the ID of the mod itself
 #[database_model]
 #[derive(Debug, Clone)]
 pub struct ModSettings {
+    /// Human-readable name of the mod
     pub name: String,
-    pub mod_id: String,
-    pub defaults: Defaults,
+    pub version: Version,
+    /// Mods that must be loaded before this one, along with the accepted
+    /// version ranges
+    #[model(ty = FxHashMap < ItemId, String >)]
+    #[model_serde(default)]
+    pub dependencies: FxHashMap<ItemId, VersionReq>,
+    /// Defaults override the ones declared by the earlier mods
+    pub defaults: Option<Defaults>,
+    /// Named constants, which formulas of the mod's items refer to by their
+    /// names, and formulas of other mods as `{mod:NAME}`
+    #[model(ty = FxHashMap < String, f64 >)]
+    #[model_serde(default)]
+    pub constants: FxHashMap<String, f64>,
+}
+
+impl ModSettingsSerialized {
+    /// IDs of the mods that must be loaded before this one
+    pub fn dependencies(&self) -> impl Iterator<Item = &ItemId> {
+        self.dependencies.keys()
+    }
+
+    /// Named constants usable in the formulas
+    pub fn constants(&self) -> &FxHashMap<String, f64> {
+        &self.constants
+    }
 }
 
 #[database_model]
@@ -15,3 +48,42 @@ pub struct ModSettings {
 pub struct Defaults {
     pub combat_settings: CombatSettingsId,
 }
+
+impl ModelDeserializableFallbackType for Version {
+    type Serialized = String;
+}
+
+impl ModelDeserializable<Version> for &str {
+    fn deserialize(
+        self,
+        _registry: &mut PartialModRegistry,
+    ) -> Result<Version, DeserializationError> {
+        Version::parse(self).map_err(|e| DeserializationErrorKind::BadVersion(e.to_string()).into())
+    }
+}
+
+impl ModelSerializable<String> for Version {
+    fn serialize(&self, _registry: &ModRegistry) -> String {
+        self.to_string()
+    }
+}
+
+impl ModelDeserializableFallbackType for VersionReq {
+    type Serialized = String;
+}
+
+impl ModelDeserializable<VersionReq> for &str {
+    fn deserialize(
+        self,
+        _registry: &mut PartialModRegistry,
+    ) -> Result<VersionReq, DeserializationError> {
+        VersionReq::parse(self)
+            .map_err(|e| DeserializationErrorKind::BadVersion(e.to_string()).into())
+    }
+}
+
+impl ModelSerializable<String> for VersionReq {
+    fn serialize(&self, _registry: &ModRegistry) -> String {
+        self.to_string()
+    }
+}
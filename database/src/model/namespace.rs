@@ -0,0 +1,214 @@
+//! Every item belongs to the namespace of the mod declaring it, which is the
+//! ID of the mod's manifest. Items are stored under their qualified IDs, such
+//! as `eh:tarus`, while a mod may refer to its own items by the relative IDs,
+//! such as `tarus`. Items of other mods always have to be referred to by the
+//! qualified IDs.
+//!
+//! Manifests don't belong to any namespace, since their IDs are the
+//! namespaces themselves
+
+use std::fmt::{Display, Formatter};
+
+use crate::model::serialization::{DeserializationError, DeserializationErrorKind};
+use crate::model::{DatabaseItemKind, ItemId, PartialModRegistry};
+
+/// Separates the namespace from the name in qualified IDs
+pub const NAMESPACE_SEPARATOR: char = ':';
+
+/// Item ID split into the namespace and the name
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct NamespacedId<'a> {
+    namespace: Option<&'a str>,
+    name: &'a str,
+}
+
+impl<'a> NamespacedId<'a> {
+    /// Parses either a relative or a qualified ID
+    pub fn parse(id: &'a str) -> Result<Self, DeserializationErrorKind> {
+        let (namespace, name) = match id.split_once(NAMESPACE_SEPARATOR) {
+            Some((namespace, name)) => (Some(namespace), name),
+            None => (None, id),
+        };
+        if !namespace.is_none_or(is_valid_part) || !is_valid_part(name) {
+            return Err(DeserializationErrorKind::BadItemId(id.to_string()));
+        }
+        Ok(Self { namespace, name })
+    }
+
+    /// Namespace of the ID, if it is qualified
+    pub fn namespace(&self) -> Option<&'a str> {
+        self.namespace
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Qualified form of the ID, with relative IDs resolved against the
+    /// namespace of the mod they are written in
+    pub fn qualify(&self, namespace: &str) -> QualifiedId {
+        QualifiedId::new(Some(self.namespace.unwrap_or(namespace)), self.name)
+    }
+}
+
+impl Display for NamespacedId<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.namespace {
+            Some(namespace) => write!(f, "{namespace}{NAMESPACE_SEPARATOR}{}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Validated item ID, which always carries the namespace unless it is the ID
+/// of a manifest
+///
+/// Converts into the [ItemId] the items are registered under
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct QualifiedId {
+    id: ItemId,
+    /// Byte offset of the separator, if the ID has a namespace
+    separator: Option<usize>,
+}
+
+impl QualifiedId {
+    fn new(namespace: Option<&str>, name: &str) -> Self {
+        match namespace {
+            Some(namespace) => Self {
+                id: format!("{namespace}{NAMESPACE_SEPARATOR}{name}"),
+                separator: Some(namespace.len()),
+            },
+            None => Self {
+                id: name.to_string(),
+                separator: None,
+            },
+        }
+    }
+
+    /// Namespace of the ID, which is `None` only for the manifests
+    pub fn namespace(&self) -> Option<&str> {
+        self.separator.map(|i| &self.id[..i])
+    }
+
+    pub fn name(&self) -> &str {
+        match self.separator {
+            Some(i) => &self.id[i + NAMESPACE_SEPARATOR.len_utf8()..],
+            None => &self.id,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Display for QualifiedId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+impl From<QualifiedId> for ItemId {
+    fn from(id: QualifiedId) -> Self {
+        id.id
+    }
+}
+
+/// Checks that the manifest ID can be used as a namespace
+pub fn validate_namespace(id: &str) -> Result<(), DeserializationErrorKind> {
+    if is_valid_part(id) {
+        Ok(())
+    } else {
+        Err(DeserializationErrorKind::BadNamespace(id.to_string()))
+    }
+}
+
+/// Resolves the ID of an item of the kind, written in the mod with the
+/// namespace
+///
+/// Manifest IDs are only validated, since they never belong to a namespace
+pub fn qualify_id(
+    id: &str,
+    kind: DatabaseItemKind,
+    namespace: Option<&str>,
+) -> Result<QualifiedId, DeserializationErrorKind> {
+    if kind == DatabaseItemKind::ModSettings {
+        validate_namespace(id)?;
+        return Ok(QualifiedId::new(None, id));
+    }
+    let parsed = NamespacedId::parse(id)?;
+    match parsed.namespace().or(namespace) {
+        Some(namespace) => Ok(parsed.qualify(namespace)),
+        None => Err(DeserializationErrorKind::MissingNamespace(id.to_string())),
+    }
+}
+
+impl PartialModRegistry {
+    /// Resolves the ID referenced by the item that is being deserialized
+    pub(crate) fn qualify(
+        &self,
+        id: &str,
+        kind: DatabaseItemKind,
+    ) -> Result<ItemId, DeserializationError> {
+        Ok(qualify_id(id, kind, self.namespace.as_deref())?.into())
+    }
+}
+
+fn is_valid_part(part: &str) -> bool {
+    !part.is_empty()
+        && part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '/' | '-'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::qualify_id;
+    use crate::model::serialization::DeserializationErrorKind;
+    use crate::model::DatabaseItemKind;
+
+    #[test]
+    fn relative_ids_get_the_namespace_of_their_mod() {
+        let id = qualify_id("tarus", DatabaseItemKind::Ship, Some("eh")).unwrap();
+        assert_eq!(id.as_str(), "eh:tarus");
+        assert_eq!(id.namespace(), Some("eh"));
+        assert_eq!(id.name(), "tarus");
+    }
+
+    #[test]
+    fn qualified_ids_keep_their_namespace() {
+        let id = qualify_id("other:tarus", DatabaseItemKind::Ship, Some("eh")).unwrap();
+        assert_eq!(id.as_str(), "other:tarus");
+        assert_eq!(id.namespace(), Some("other"));
+        assert_eq!(id.name(), "tarus");
+    }
+
+    #[test]
+    fn manifest_ids_have_no_namespace() {
+        let id = qualify_id("eh", DatabaseItemKind::ModSettings, Some("other")).unwrap();
+        assert_eq!(id.as_str(), "eh");
+        assert_eq!(id.namespace(), None);
+        assert_eq!(id.name(), "eh");
+        assert!(matches!(
+            qualify_id("eh:mod", DatabaseItemKind::ModSettings, None),
+            Err(DeserializationErrorKind::BadNamespace(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_ids_are_rejected() {
+        assert!(matches!(
+            qualify_id("tarus", DatabaseItemKind::Ship, None),
+            Err(DeserializationErrorKind::MissingNamespace(_))
+        ));
+        for id in ["", "eh:", ":tarus", "eh:ta rus", "eh:tarus:2"] {
+            assert!(
+                matches!(
+                    qualify_id(id, DatabaseItemKind::Ship, Some("eh")),
+                    Err(DeserializationErrorKind::BadItemId(_))
+                ),
+                "{id}"
+            );
+        }
+    }
+}
@@ -1,30 +1,69 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
 use std::ops::Index;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use bevy::asset::Handle;
 use duplicate::{duplicate, duplicate_item};
-use exmex::ExError;
+use itertools::Itertools;
 use miette::Diagnostic;
-use serde::{Deserialize, Serialize};
+use rustc_hash::{FxHashMap, FxHashSet};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{
+    InstanceType, Metadata, ObjectValidation, Schema, SchemaObject, StringValidation,
+    SubschemaValidation,
+};
+use schemars::JsonSchema;
+use semver::{Version, VersionReq};
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer, Serialize};
 use thiserror::Error;
 
-use slabmap::{SlabMap, SlabMapDuplicateError, SlabMapId};
+use slabmap::{SlabMap, SlabMapId};
 
-use crate::model::{DatabaseItemKind, DatabaseItemTrait, ItemId, ModelKind, PartialModRegistry};
+use crate::model::formula::FormulaError;
+use crate::model::migration::{DataVersion, CURRENT_DATA_VERSION};
+use crate::model::namespace::qualify_id;
+use crate::model::{
+    DatabaseItemKind, DatabaseItemTrait, ItemId, ModRegistry, ModelKind, PartialModRegistry,
+};
+
+pub use location::ErrorLocation;
+pub use references::{ItemReference, ItemReferences};
+pub(crate) use references::{ModelReferences, ReferenceCollector};
 
 mod diagnostic;
+mod location;
+mod references;
+mod spans;
 
 #[derive(Debug, Error, Clone)]
 pub enum DeserializationErrorKind {
     #[error("Item {}({}) is missing", .1, .0)]
     MissingItem(ItemId, DatabaseItemKind),
-    #[error("Item {}({}) is already declared", .1, .0)]
-    DuplicateItem(ItemId, DatabaseItemKind),
+    #[error("`{}` is not a valid item ID, expected `name` or `namespace:name` made of ASCII letters, digits, `_`, `.`, `/` and `-`", .0)]
+    BadItemId(String),
+    #[error("Relative item ID `{}` is used outside of any mod, expected `namespace:name`", .0)]
+    MissingNamespace(String),
+    #[error("Item {}({}) is declared both in `{}` and `{}`", .kind, .id, .path_a.to_string_lossy(), .path_b.to_string_lossy())]
+    DuplicateItem {
+        id: ItemId,
+        kind: DatabaseItemKind,
+        path_a: PathBuf,
+        path_b: PathBuf,
+    },
+    #[error("Mod `{}` is declared both by `{}` and `{}`", .id, .name_a, .name_b)]
+    DuplicateMod {
+        id: ItemId,
+        name_a: String,
+        name_b: String,
+    },
+    #[error("Item {}({}) can't be deleted without a full reload", .1, .0)]
+    UnsupportedDeletion(ItemId, DatabaseItemKind),
     #[error("Image `{}` is missing", .0)]
     MissingImage(String),
     #[error("Image name `{}` is contested by `{}` and `{}`", .name, .path_a.to_string_lossy(), .path_b.to_string_lossy())]
@@ -42,22 +81,86 @@ pub enum DeserializationErrorKind {
     #[error("File path at `{}` is not UTF8", .0.to_string_lossy())]
     NonUtf8Path(PathBuf),
     #[error("Failed to parse an expression: {}", .0)]
-    BadExpression(ExError),
+    BadExpression(FormulaError),
+    #[error("`{}` can't be used as a constant name, expected ASCII letters, digits and `_` not naming a built-in function or constant", .0)]
+    BadConstantName(String),
+    #[error("Failed to parse a version: {}", .0)]
+    BadVersion(String),
+    #[error("`{}` is not a valid data version, expected a non-negative integer", .0)]
+    BadDataVersion(String),
+    #[error("Data version {} is newer than the supported version {}", .0, CURRENT_DATA_VERSION)]
+    UnsupportedDataVersion(DataVersion),
+    #[error("Failed to migrate the item to data version {}: {}", .version, .message)]
+    FailedMigration {
+        version: DataVersion,
+        message: String,
+    },
+    #[error("Failed to read the items of the file: {}", .0)]
+    BadFile(String),
+    #[error("Mod `{}` doesn't declare a ModSettings manifest", .0)]
+    MissingManifest(String),
+    #[error("Mod ID `{}` can't be used as a namespace, expected ASCII letters, digits, `_`, `.`, `/` and `-`", .0)]
+    BadNamespace(ItemId),
+    #[error("Mod `{}` is not loaded", .0)]
+    UnknownMod(String),
+    #[error("Mod `{}` declares multiple ModSettings manifests: `{}` and `{}`", .name, .id_a, .id_b)]
+    MultipleManifests {
+        name: String,
+        id_a: ItemId,
+        id_b: ItemId,
+    },
+    #[error("Mod `{}` requires mod `{}` of version `{}` to be loaded before it", .mod_id, .dependency, .requirement)]
+    MissingDependency {
+        mod_id: ItemId,
+        dependency: ItemId,
+        requirement: VersionReq,
+    },
+    #[error("Mod `{}` requires mod `{}` of version `{}`, but version `{}` is loaded", .mod_id, .dependency, .requirement, .version)]
+    DependencyVersionMismatch {
+        mod_id: ItemId,
+        dependency: ItemId,
+        requirement: VersionReq,
+        version: Box<Version>,
+    },
+    #[error("None of the loaded mods declare default settings")]
+    MissingDefaults,
+    #[error("Items of kind {} extend each other in a cycle: {}", .1, .0.join(" -> "))]
+    InheritanceCycle(Vec<ItemId>, DatabaseItemKind),
+    #[error("Variables depend on each other in a cycle: {}", .0.join(" -> "))]
+    VariableCycle(Vec<ItemId>),
+    #[error("Item {}({}) takes part in inheritance and can't be reloaded without a full reload", .1, .0)]
+    UnsupportedInheritance(ItemId, DatabaseItemKind),
+    #[error("Constants of mod `{}` changed and can't be reloaded without a full reload", .0)]
+    UnsupportedConstantChange(ItemId),
+    #[error("Data version of mod `{}` changed and can't be reloaded without a full reload", .0)]
+    UnsupportedDataVersionChange(ItemId),
+    #[error("Parent `{}` of the item is not resolved", .0)]
+    UnresolvedParent(ItemId),
+    #[error("Failed to fill in the item from its parent: {}", .0)]
+    BadInheritedItem(String),
+    #[error("{} errors occurred", .0.len())]
+    Multiple(Vec<DeserializationError>),
 }
 
 #[derive(Debug, Clone)]
 pub enum DeserializationErrorStackItem {
+    File(PathBuf),
     Item(ItemId, DatabaseItemKind),
     Field(&'static str),
     Index(usize),
     MapEntry(String),
     // all JSON keys are strings, so we expect deserialized value to be reasonably displayable
     ExprVariable(String),
+    /// Function called by the expression, for the errors about the call
+    ExprFunction(String),
 }
 
 impl Display for DeserializationErrorStackItem {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            DeserializationErrorStackItem::File(path) => {
+                write!(f, "In file `{}`", path.to_string_lossy())
+            }
             DeserializationErrorStackItem::Item(id, kind) => write!(f, "In item <{kind}>`{id}`"),
             DeserializationErrorStackItem::Field(name) => write!(f, "In field {name}"),
             DeserializationErrorStackItem::Index(i) => write!(f, "In item at position {i}"),
@@ -67,6 +170,9 @@ impl Display for DeserializationErrorStackItem {
             DeserializationErrorStackItem::ExprVariable(name) => {
                 write!(f, "In expression variable `{name}`")
             }
+            DeserializationErrorStackItem::ExprFunction(name) => {
+                write!(f, "In expression function `{name}`")
+            }
         }
     }
 }
@@ -75,6 +181,7 @@ impl Display for DeserializationErrorStackItem {
 pub struct DeserializationError {
     pub kind: DeserializationErrorKind,
     pub stack: Vec<DeserializationErrorStackItem>,
+    pub location: Option<Box<ErrorLocation>>,
 }
 
 impl Display for DeserializationError {
@@ -83,6 +190,9 @@ impl Display for DeserializationError {
         for item in &self.stack {
             write!(f, "\n{}", item)?;
         }
+        if let Some(location) = &self.location {
+            write!(f, "\nAt {}", location)?;
+        }
         Ok(())
     }
 }
@@ -92,6 +202,95 @@ impl DeserializationError {
         self.stack.push(item);
         self
     }
+
+    /// Combines errors into a single one, returning `None` if there are no
+    /// errors at all
+    pub fn combine(errors: impl IntoIterator<Item = DeserializationError>) -> Option<Self> {
+        let mut errors = errors
+            .into_iter()
+            .flat_map(DeserializationError::flatten)
+            .collect_vec();
+        match errors.len() {
+            0 => None,
+            1 => errors.pop(),
+            _ => Some(DeserializationErrorKind::Multiple(errors).into()),
+        }
+    }
+
+    /// Splits combined errors into individual ones, each of them carrying the
+    /// full context stack
+    pub fn flatten(self) -> Vec<DeserializationError> {
+        match self.kind {
+            DeserializationErrorKind::Multiple(errors) => errors
+                .into_iter()
+                .flat_map(DeserializationError::flatten)
+                .map(|mut err| {
+                    err.stack.extend(self.stack.iter().cloned());
+                    err
+                })
+                .collect(),
+            kind => vec![DeserializationError {
+                kind,
+                stack: self.stack,
+                location: self.location,
+            }],
+        }
+    }
+
+    /// Adds the position of the item inside the file holding multiple items,
+    /// placing it right below the item in the stack
+    pub(crate) fn at_position(mut self, position: Option<DeserializationErrorStackItem>) -> Self {
+        if let Some(position) = position {
+            let index = self
+                .stack
+                .iter()
+                .position(|item| matches!(item, DeserializationErrorStackItem::Item(..)))
+                .unwrap_or(self.stack.len());
+            self.stack.insert(index, position);
+        }
+        self
+    }
+
+    /// Item that the error originates from, if any
+    pub fn item(&self) -> Option<(&ItemId, DatabaseItemKind)> {
+        self.stack.iter().find_map(|item| match item {
+            DeserializationErrorStackItem::Item(id, kind) => Some((id, *kind)),
+            _ => None,
+        })
+    }
+
+    /// Points the error to the offending value inside the file text
+    ///
+    /// Combined errors should be flattened first, since each of them points
+    /// to its own value
+    pub fn with_location(mut self, path: PathBuf, text: Arc<str>) -> Self {
+        self.location = Some(Box::new(ErrorLocation::new(path, text, &self.stack)));
+        self
+    }
+
+    /// File that the error originates from, if any
+    pub fn file(&self) -> Option<&PathBuf> {
+        self.stack.iter().find_map(|item| match item {
+            DeserializationErrorStackItem::File(path) => Some(path),
+            _ => None,
+        })
+    }
+}
+
+/// Collects all values, reporting every error instead of stopping at the
+/// first one
+pub(crate) fn collect_all<T, C: FromIterator<T>>(
+    results: impl IntoIterator<Item = Result<T, DeserializationError>>,
+) -> Result<C, DeserializationError> {
+    let mut errors = Vec::new();
+    let collected = results
+        .into_iter()
+        .filter_map(|result| result.map_err(|err| errors.push(err)).ok())
+        .collect();
+    match DeserializationError::combine(errors) {
+        None => Ok(collected),
+        Some(err) => Err(err),
+    }
 }
 
 impl From<DeserializationErrorKind> for DeserializationError {
@@ -99,26 +298,27 @@ impl From<DeserializationErrorKind> for DeserializationError {
         DeserializationError {
             kind: value,
             stack: Default::default(),
+            location: None,
         }
     }
 }
 
-impl From<ExError> for DeserializationError {
-    fn from(value: ExError) -> Self {
+impl From<FormulaError> for DeserializationError {
+    fn from(value: FormulaError) -> Self {
         DeserializationErrorKind::BadExpression(value).into()
     }
 }
 
-impl<T: ModelKind> From<SlabMapDuplicateError<ItemId, T>> for DeserializationError {
-    fn from(SlabMapDuplicateError(id, _): SlabMapDuplicateError<ItemId, T>) -> Self {
-        DeserializationErrorKind::DuplicateItem(id, T::kind()).into()
-    }
-}
-
 pub(crate) trait ModelDeserializable<T> {
     fn deserialize(self, registry: &mut PartialModRegistry) -> Result<T, DeserializationError>;
 }
 
+/// Reverse of [ModelDeserializable], restoring the serialized form of the
+/// model with string IDs, image names and formula text
+pub(crate) trait ModelSerializable<T> {
+    fn serialize(&self, registry: &ModRegistry) -> T;
+}
+
 pub trait ModelDeserializableFallbackType {
     type Serialized;
 }
@@ -160,9 +360,22 @@ duplicate! {
         }
     }
 
+    impl ModelSerializable<ty> for ty {
+        #[inline(always)]
+        #[allow(clippy::clone_on_copy)]
+        fn serialize(&self, _registry: &ModRegistry) -> ty {
+            self.clone()
+        }
+    }
+
     impl ModelDeserializableFallbackType for ty {
         type Serialized = ty;
     }
+
+    impl ModelReferences for ty {
+        #[inline(always)]
+        fn collect_references(&self, _collector: &mut ReferenceCollector) {}
+    }
 }
 
 impl<T: ModelDeserializable<R>, R> ModelDeserializable<Option<R>> for Option<T> {
@@ -175,6 +388,13 @@ impl<T: ModelDeserializable<R>, R> ModelDeserializable<Option<R>> for Option<T>
     }
 }
 
+impl<T: ModelSerializable<R>, R> ModelSerializable<Option<R>> for Option<T> {
+    #[inline(always)]
+    fn serialize(&self, registry: &ModRegistry) -> Option<R> {
+        self.as_ref().map(|e| e.serialize(registry))
+    }
+}
+
 impl<T: ModelDeserializableFallbackType> ModelDeserializableFallbackType for Option<T> {
     type Serialized = Option<T::Serialized>;
 }
@@ -189,6 +409,13 @@ impl<T: ModelDeserializable<R>, R> ModelDeserializable<Arc<R>> for Serialization
     }
 }
 
+impl<T: ModelSerializable<R>, R> ModelSerializable<SerializationBoxingWrapper<R>> for Arc<T> {
+    #[inline(always)]
+    fn serialize(&self, registry: &ModRegistry) -> SerializationBoxingWrapper<R> {
+        SerializationBoxingWrapper((**self).serialize(registry))
+    }
+}
+
 impl<T: ModelDeserializableFallbackType> ModelDeserializableFallbackType for Arc<T> {
     type Serialized = SerializationBoxingWrapper<T::Serialized>;
 }
@@ -199,13 +426,17 @@ impl<T: ModelDeserializable<R>, R> ModelDeserializable<Vec<R>> for Vec<T> {
         self,
         registry: &mut PartialModRegistry,
     ) -> Result<Vec<R>, DeserializationError> {
-        self.into_iter()
-            .enumerate()
-            .map(|(i, e)| {
-                e.deserialize(registry)
-                    .map_err(|e| e.context(DeserializationErrorStackItem::Index(i)))
-            })
-            .collect()
+        collect_all(self.into_iter().enumerate().map(|(i, e)| {
+            e.deserialize(registry)
+                .map_err(|e| e.context(DeserializationErrorStackItem::Index(i)))
+        }))
+    }
+}
+
+impl<T: ModelSerializable<R>, R> ModelSerializable<Vec<R>> for Vec<T> {
+    #[inline]
+    fn serialize(&self, registry: &ModRegistry) -> Vec<R> {
+        self.iter().map(|e| e.serialize(registry)).collect()
     }
 }
 
@@ -226,16 +457,30 @@ impl<
         self,
         registry: &mut PartialModRegistry,
     ) -> Result<HashMap<Key, Value, Hasher>, DeserializationError> {
-        self.into_iter()
-            .map(|(k, v)| {
-                let v = v.deserialize(registry).map_err(|e| {
-                    e.context(DeserializationErrorStackItem::MapEntry(k.to_string()))
-                })?;
-                // TODO: providing context here requires cloning a key, which is
-                // less than desirable, but not providing context is pretty bad
-                let k = k.deserialize(registry)?;
-                Ok((k, v))
-            })
+        collect_all(self.into_iter().map(|(k, v)| {
+            let v = v
+                .deserialize(registry)
+                .map_err(|e| e.context(DeserializationErrorStackItem::MapEntry(k.to_string())))?;
+            // TODO: providing context here requires cloning a key, which is
+            // less than desirable, but not providing context is pretty bad
+            let k = k.deserialize(registry)?;
+            Ok((k, v))
+        }))
+    }
+}
+
+impl<
+        Key: ModelSerializable<RawKey>,
+        RawKey: Eq + Hash,
+        Value: ModelSerializable<RawValue>,
+        RawValue,
+        Hasher: BuildHasher,
+        RawHasher: BuildHasher + Default,
+    > ModelSerializable<HashMap<RawKey, RawValue, RawHasher>> for HashMap<Key, Value, Hasher>
+{
+    fn serialize(&self, registry: &ModRegistry) -> HashMap<RawKey, RawValue, RawHasher> {
+        self.iter()
+            .map(|(k, v)| (k.serialize(registry), v.serialize(registry)))
             .collect()
     }
 }
@@ -254,12 +499,103 @@ impl ModelDeserializable<Handle<bevy::prelude::Image>> for String {
     }
 }
 
+impl ModelSerializable<String> for Handle<bevy::prelude::Image> {
+    fn serialize(&self, registry: &ModRegistry) -> String {
+        registry
+            .assets
+            .image_name(self)
+            .expect("Images of the registry should all be registered")
+            .to_string()
+    }
+}
+
 impl ModelDeserializableFallbackType for Handle<bevy::prelude::Image> {
     type Serialized = String;
 }
 
 impl<T: DatabaseItemTrait> ModelDeserializableFallbackType for SlabMapId<T> {
-    type Serialized = ItemId;
+    type Serialized = ItemIdSerialized<T>;
+}
+
+/// Pattern every item ID is expected to match, either relative such as
+/// `tarus` or qualified such as `eh:tarus`
+pub const ITEM_ID_PATTERN: &str = r"^([A-Za-z0-9_./-]+:)?[A-Za-z0-9_./-]+$";
+
+/// Schema of an item ID, used for the fields that can't be typed with
+/// [ItemIdSerialized]
+pub(crate) fn item_id_schema(_gen: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        string: Some(Box::new(StringValidation {
+            pattern: Some(ITEM_ID_PATTERN.to_string()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// ID referencing an item of the specific kind
+///
+/// Serialized the same way as a plain [ItemId], the kind is only used to
+/// describe the reference in the schema
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ItemIdSerialized<T>(pub ItemId, #[serde(skip)] PhantomData<fn() -> T>);
+
+impl<T> ItemIdSerialized<T> {
+    pub fn new(id: ItemId) -> Self {
+        Self(id, PhantomData)
+    }
+}
+
+impl<T> Debug for ItemIdSerialized<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl<T> Clone for ItemIdSerialized<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl<T: ModelKind> JsonSchema for ItemIdSerialized<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        format!("ItemId_{}", T::kind())
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = item_id_schema(gen).into_object();
+        schema.metadata().description = Some(format!("ID of a `{}` item", T::kind()));
+        schema.into()
+    }
+}
+
+impl<T> ModelDeserializable<SlabMapId<T>> for ItemIdSerialized<T>
+where
+    for<'a> &'a str: ModelDeserializable<SlabMapId<T>>,
+{
+    fn deserialize(
+        self,
+        registry: &mut PartialModRegistry,
+    ) -> Result<SlabMapId<T>, DeserializationError> {
+        self.0.as_str().deserialize(registry)
+    }
+}
+
+impl<T> ModelSerializable<ItemIdSerialized<T>> for SlabMapId<T>
+where
+    SlabMapId<T>: ModelSerializable<ItemId>,
+{
+    fn serialize(&self, registry: &ModRegistry) -> ItemIdSerialized<T> {
+        ItemIdSerialized::new(ModelSerializable::<ItemId>::serialize(self, registry))
+    }
 }
 
 impl<T> ModelDeserializable<T> for String
@@ -334,12 +670,25 @@ impl<T: ApplyMax> ApplyMax for Option<T> {
 #[serde(transparent)]
 pub struct SerializationBoxingWrapper<T>(T);
 
+/// Reserves the slot for the item, panics if the item is already filled in
+///
+/// Duplicate items are reported while collecting the layers, so every raw
+/// item is only deserialized once
 pub(crate) fn reserve<T>(
     map: &mut SlabMap<ItemId, Option<T>>,
     key: ItemId,
-) -> Result<SlabMapReservation<T>, SlabMapDuplicateError<ItemId, Option<T>>> {
-    map.insert_new(key, None)
-        .map(|e| SlabMapReservation(e.as_untyped().as_typed_unchecked()))
+) -> SlabMapReservation<T> {
+    // Slots released for hot reloading are filled again in place, keeping
+    // the ID of the item stable
+    if let Some(id) = map.key_to_id(&key) {
+        if map[id].is_none() {
+            return SlabMapReservation(id.as_untyped().as_typed_unchecked());
+        }
+    }
+    let id = map.insert_new(key, None).unwrap_or_else(|_| {
+        panic!("Duplicate items should be rejected while collecting the layers")
+    });
+    SlabMapReservation(id.as_untyped().as_typed_unchecked())
 }
 
 pub(crate) struct SlabMapReservation<T>(SlabMapId<T>);
@@ -382,10 +731,336 @@ pub struct RegistryEntry<Data> {
 
 #[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "camelCase")]
+#[serde(bound(deserialize = "DataSerialized: DeserializeOwned"))]
 pub struct RegistryEntrySerialized<DataSerialized> {
+    #[schemars(schema_with = "item_id_schema")]
     pub id: ItemId,
+    /// Data version the item is written in, overriding the version of its
+    /// mod
+    ///
+    /// Manifests declare the version of the whole mod in this field instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_version: Option<DataVersion>,
     #[serde(flatten)]
-    pub data: DataSerialized,
+    pub data: ItemDataSerialized<DataSerialized>,
+    /// Namespace of the mod declaring the item, which relative IDs
+    /// referenced by it are resolved against
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub namespace: Option<ItemId>,
+}
+
+impl<DataSerialized> RegistryEntrySerialized<DataSerialized> {
+    /// Qualifies IDs of the item and its parent, and remembers the namespace
+    /// for the IDs referenced by it
+    pub(crate) fn qualify(
+        &mut self,
+        kind: DatabaseItemKind,
+        namespace: &str,
+    ) -> Result<(), DeserializationError> {
+        self.id = qualify_id(&self.id, kind, Some(namespace))
+            .map_err(|e| {
+                DeserializationError::from(e).context(DeserializationErrorStackItem::Field("id"))
+            })?
+            .into();
+        if let ItemDataSerialized::Inherited { extends, .. } = &mut self.data {
+            *extends = qualify_id(extends, kind, Some(namespace))
+                .map_err(|e| {
+                    DeserializationError::from(e)
+                        .context(DeserializationErrorStackItem::Field("extends"))
+                })?
+                .into();
+        }
+        self.namespace = Some(namespace.to_string());
+        Ok(())
+    }
+}
+
+/// Data of the serialized item, which can either be complete or extend
+/// another item of the same kind
+///
+/// Fields of the extending item are kept as-is until all mods are layered,
+/// and then merged over the ones of the parent, see [merge_fields]
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ItemDataSerialized<DataSerialized> {
+    Complete(DataSerialized),
+    Inherited {
+        extends: ItemId,
+        #[serde(flatten)]
+        fields: serde_json::Map<String, serde_json::Value>,
+    },
+}
+
+impl<DataSerialized> ItemDataSerialized<DataSerialized> {
+    pub fn extends(&self) -> Option<&ItemId> {
+        match self {
+            ItemDataSerialized::Complete(_) => None,
+            ItemDataSerialized::Inherited { extends, .. } => Some(extends),
+        }
+    }
+
+    /// Data of the item, if it doesn't extend another one
+    pub fn complete(&self) -> Option<&DataSerialized> {
+        match self {
+            ItemDataSerialized::Complete(data) => Some(data),
+            ItemDataSerialized::Inherited { .. } => None,
+        }
+    }
+}
+
+impl<'de, DataSerialized: DeserializeOwned> Deserialize<'de>
+    for ItemDataSerialized<DataSerialized>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = serde_json::Map::deserialize(deserializer)?;
+        match fields.remove("extends") {
+            None => DataSerialized::deserialize(serde_json::Value::Object(fields))
+                .map(ItemDataSerialized::Complete)
+                .map_err(D::Error::custom),
+            Some(serde_json::Value::String(extends)) => {
+                Ok(ItemDataSerialized::Inherited { extends, fields })
+            }
+            Some(other) => Err(D::Error::custom(format!(
+                "expected an item ID in the `extends` field, got `{other}`"
+            ))),
+        }
+    }
+}
+
+impl<DataSerialized: JsonSchema> JsonSchema for ItemDataSerialized<DataSerialized> {
+    fn schema_name() -> String {
+        format!("Inheritable_{}", DataSerialized::schema_name())
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let inherited = SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Fills in all missing fields from the item of the same kind. Nested objects are merged field by field, while lists and other values replace the ones of the parent".to_string(),
+                ),
+                ..Default::default()
+            })),
+            object: Some(Box::new(ObjectValidation {
+                properties: [("extends".to_string(), item_id_schema(gen))]
+                    .into_iter()
+                    .collect(),
+                required: ["extends".to_string()].into_iter().collect(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![DataSerialized::json_schema(gen), inherited.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl<Data, DataSerialized: ModelDeserializable<Data>> ModelDeserializable<Data>
+    for ItemDataSerialized<DataSerialized>
+{
+    fn deserialize(self, registry: &mut PartialModRegistry) -> Result<Data, DeserializationError> {
+        match self {
+            ItemDataSerialized::Complete(data) => data.deserialize(registry),
+            ItemDataSerialized::Inherited { extends, .. } => {
+                Err(DeserializationErrorKind::UnresolvedParent(extends).into())
+            }
+        }
+    }
+}
+
+/// Fills in fields of all extending items from their parents, turning them
+/// into complete ones
+///
+/// Items that fail to resolve are left as-is, and errors of all of them are
+/// reported together.
+/// Returns IDs of all items that extend or are extended by other items
+pub(crate) fn resolve_inheritance<DataSerialized: Serialize + DeserializeOwned>(
+    items: &mut FxHashMap<ItemId, RegistryEntrySerialized<DataSerialized>>,
+    kind: DatabaseItemKind,
+) -> Result<FxHashSet<ItemId>, DeserializationError> {
+    let mut related = FxHashSet::default();
+    let mut errors = Vec::new();
+    // Items that are already known to be unresolvable, so the same cycle or
+    // missing parent doesn't get reported by every item down the chain
+    let mut failed = FxHashSet::default();
+    let inherited = items
+        .iter()
+        .filter(|(_, item)| item.data.extends().is_some())
+        .map(|(id, _)| id.clone())
+        .collect_vec();
+    for id in inherited {
+        if failed.contains(&id) {
+            continue;
+        }
+        let Some(mut parent) = items[&id].data.extends().cloned() else {
+            // Already resolved as a parent of another item
+            continue;
+        };
+        let mut chain = vec![id];
+        let fields = loop {
+            if failed.contains(&parent) {
+                break None;
+            }
+            if chain.contains(&parent) {
+                chain.push(parent);
+                errors.push(
+                    DeserializationError::from(DeserializationErrorKind::InheritanceCycle(
+                        chain.clone(),
+                        kind,
+                    ))
+                    .context(DeserializationErrorStackItem::Field("extends"))
+                    .context(DeserializationErrorStackItem::Item(chain[0].clone(), kind)),
+                );
+                break None;
+            }
+            let Some(item) = items.get(&parent) else {
+                let child = chain.last().expect("Chain should not be empty").clone();
+                errors.push(
+                    DeserializationError::from(DeserializationErrorKind::MissingItem(parent, kind))
+                        .context(DeserializationErrorStackItem::Field("extends"))
+                        .context(DeserializationErrorStackItem::Item(child, kind)),
+                );
+                break None;
+            };
+            match &item.data {
+                ItemDataSerialized::Complete(data) => match serde_json::to_value(data) {
+                    Ok(value) => {
+                        related.insert(parent);
+                        break Some(match value {
+                            serde_json::Value::Object(fields) => fields,
+                            _ => Default::default(),
+                        });
+                    }
+                    Err(err) => {
+                        errors.push(
+                            DeserializationError::from(DeserializationErrorKind::BadInheritedItem(
+                                err.to_string(),
+                            ))
+                            .context(DeserializationErrorStackItem::Item(parent, kind)),
+                        );
+                        break None;
+                    }
+                },
+                ItemDataSerialized::Inherited { extends, .. } => {
+                    let extends = extends.clone();
+                    chain.push(parent);
+                    parent = extends;
+                }
+            }
+        };
+
+        let Some(mut fields) = fields else {
+            failed.extend(chain);
+            continue;
+        };
+
+        let mut chain = chain.into_iter().rev();
+        while let Some(id) = chain.next() {
+            let item = items
+                .get_mut(&id)
+                .expect("Chain should consist of present items");
+            let ItemDataSerialized::Inherited {
+                fields: own_fields, ..
+            } = &item.data
+            else {
+                unreachable!("Chain should only consist of extending items");
+            };
+            let parent_fields = fields.clone();
+            merge_fields(&mut fields, own_fields);
+            match DataSerialized::deserialize(serde_json::Value::Object(fields.clone())) {
+                Ok(data) => {
+                    item.data = ItemDataSerialized::Complete(data);
+                    related.insert(id);
+                }
+                Err(err) => {
+                    let field = failed_field::<DataSerialized>(&parent_fields, own_fields);
+                    errors.push(
+                        DeserializationError::from(DeserializationErrorKind::BadInheritedItem(
+                            err.to_string(),
+                        ))
+                        .context(match field {
+                            Some(field) => DeserializationErrorStackItem::MapEntry(field),
+                            None => DeserializationErrorStackItem::Field("extends"),
+                        })
+                        .context(DeserializationErrorStackItem::Item(id.clone(), kind)),
+                    );
+                    // Items extending the failed one can't be resolved either
+                    failed.insert(id);
+                    failed.extend(&mut chain);
+                }
+            }
+        }
+    }
+
+    match DeserializationError::combine(errors) {
+        None => Ok(related),
+        Some(err) => Err(err),
+    }
+}
+
+/// Merges fields of the extending item over the ones of its parent
+///
+/// Nested objects are merged field by field, all other values, including
+/// lists, replace the ones of the parent
+fn merge_fields(
+    fields: &mut serde_json::Map<String, serde_json::Value>,
+    own_fields: &serde_json::Map<String, serde_json::Value>,
+) {
+    for (key, value) in own_fields {
+        match (fields.get_mut(key), value) {
+            (Some(serde_json::Value::Object(fields)), serde_json::Value::Object(own_fields)) => {
+                merge_fields(fields, own_fields)
+            }
+            _ => {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Finds the own field of the extending item that fails to deserialize when
+/// merged over the fields of its parent
+///
+/// Merged fields are deserialized out of a JSON value, which doesn't
+/// remember where in the file they come from, so errors are pointed to the
+/// offending field this way instead. Returns `None` if the fields only fail
+/// together
+fn failed_field<DataSerialized: DeserializeOwned>(
+    parent_fields: &serde_json::Map<String, serde_json::Value>,
+    own_fields: &serde_json::Map<String, serde_json::Value>,
+) -> Option<String> {
+    own_fields.iter().find_map(|(key, value)| {
+        let mut fields = parent_fields.clone();
+        merge_fields(
+            &mut fields,
+            &[(key.clone(), value.clone())].into_iter().collect(),
+        );
+        DataSerialized::deserialize(serde_json::Value::Object(fields))
+            .is_err()
+            .then(|| key.clone())
+    })
+}
+
+impl<Data: ModelSerializable<DataSerialized>, DataSerialized>
+    ModelSerializable<RegistryEntrySerialized<DataSerialized>> for RegistryEntry<Data>
+where
+    SlabMapId<RegistryEntry<Data>>: ModelSerializable<ItemId>,
+{
+    fn serialize(&self, registry: &ModRegistry) -> RegistryEntrySerialized<DataSerialized> {
+        // Inheritance is already resolved, so items are always complete
+        RegistryEntrySerialized {
+            id: self.id.serialize(registry),
+            data_version: None,
+            data: ItemDataSerialized::Complete(self.data.serialize(registry)),
+            namespace: None,
+        }
+    }
 }
 
 impl<Data: ModelDeserializableFallbackType> ModelDeserializableFallbackType
@@ -410,10 +1085,23 @@ pub enum InlineOrId<Data> {
 #[serde(rename_all = "camelCase")]
 #[serde(untagged)]
 pub enum InlineOrIdSerialized<DataSerialized> {
-    Id(ItemId),
+    Id(#[schemars(schema_with = "item_id_schema")] ItemId),
     Inline(DataSerialized),
 }
 
+impl<Data: ModelSerializable<DataSerialized>, DataSerialized>
+    ModelSerializable<InlineOrIdSerialized<DataSerialized>> for InlineOrId<Data>
+where
+    SlabMapId<RegistryEntry<Data>>: ModelSerializable<ItemId>,
+{
+    fn serialize(&self, registry: &ModRegistry) -> InlineOrIdSerialized<DataSerialized> {
+        match self {
+            InlineOrId::Id(id) => InlineOrIdSerialized::Id(id.serialize(registry)),
+            InlineOrId::Inline(data) => InlineOrIdSerialized::Inline(data.serialize(registry)),
+        }
+    }
+}
+
 impl<Data: ModelDeserializableFallbackType> ModelDeserializableFallbackType for InlineOrId<Data> {
     type Serialized = InlineOrIdSerialized<Data::Serialized>;
 }
@@ -1,11 +1,13 @@
 use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
 
-use miette::Diagnostic;
+use itertools::Itertools;
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceCode};
 use thiserror::Error;
 
 use crate::model::serialization::DeserializationError;
 
-use super::{DeserializationErrorKind, DeserializationErrorStackItem};
+use super::{DeserializationErrorKind, DeserializationErrorStackItem, ErrorLocation};
 
 #[derive(Debug)]
 enum ItemDiagnosticKind {
@@ -17,6 +19,9 @@ impl Display for ItemDiagnosticKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match &self {
             ItemDiagnosticKind::Path(path) => match path {
+                DeserializationErrorStackItem::File(path) => {
+                    write!(f, "Failed to deserialize file `{}`", path.to_string_lossy())
+                }
                 DeserializationErrorStackItem::Item(id, kind) => {
                     write!(f, "Failed to deserialize {kind}({id})")
                 }
@@ -32,6 +37,9 @@ impl Display for ItemDiagnosticKind {
                 DeserializationErrorStackItem::ExprVariable(name) => {
                     write!(f, "Failed to resolve expression variable `{name}`")
                 }
+                DeserializationErrorStackItem::ExprFunction(name) => {
+                    write!(f, "Failed to call expression function `{name}`")
+                }
             },
             ItemDiagnosticKind::Cause(cause) => {
                 write!(f, "{cause}")
@@ -40,21 +48,198 @@ impl Display for ItemDiagnosticKind {
     }
 }
 
+/// Offending value highlighted in the file it comes from
+#[derive(Debug)]
+struct Snippet {
+    source: NamedSource,
+    label: LabeledSpan,
+}
+
+impl Snippet {
+    fn new(location: &ErrorLocation, message: String) -> Self {
+        Snippet {
+            source: NamedSource::new(location.path.to_string_lossy(), location.text.to_string()),
+            label: LabeledSpan::new_with_span(
+                Some(message),
+                (location.span.start, location.span.len()),
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 #[error("{}", .0)]
-struct ItemDiagnostic(ItemDiagnosticKind, Option<Box<ItemDiagnostic>>);
+struct ItemDiagnostic(
+    ItemDiagnosticKind,
+    Option<Box<ItemDiagnostic>>,
+    Option<Snippet>,
+);
 
 impl Diagnostic for ItemDiagnostic {
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.2.as_ref().map(|e| &e.source as &dyn SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.2
+            .as_ref()
+            .map(|e| Box::new(std::iter::once(e.label.clone())) as Box<dyn Iterator<Item = _>>)
+    }
+
     fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
         self.1.as_ref().map(|e| e.as_ref() as &dyn Diagnostic)
     }
 }
 
+impl ItemDiagnostic {
+    fn new(err: DeserializationError) -> Self {
+        // Snippet is attached to the outermost diagnostic, since only that
+        // one gets its source code rendered
+        let snippet = err
+            .location
+            .as_deref()
+            .map(|location| Snippet::new(location, err.kind.to_string()));
+        let mut diagnostic = err.stack.into_iter().fold(
+            ItemDiagnostic(ItemDiagnosticKind::Cause(err.kind), None, None),
+            |err, item| ItemDiagnostic(ItemDiagnosticKind::Path(item), Some(Box::new(err)), None),
+        );
+        diagnostic.2 = snippet;
+        diagnostic
+    }
+}
+
+/// Errors originating from the same file
+#[derive(Debug, Error)]
+struct FileDiagnostic {
+    file: Option<PathBuf>,
+    errors: Vec<ItemDiagnostic>,
+}
+
+impl Display for FileDiagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            None => write!(f, "Outside of item files"),
+            Some(file) => write!(f, "In file `{}`", file.to_string_lossy()),
+        }
+    }
+}
+
+impl Diagnostic for FileDiagnostic {
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        Some(Box::new(self.errors.iter().map(|e| e as &dyn Diagnostic)))
+    }
+}
+
+#[derive(Debug, Error)]
+enum DeserializationDiagnostic {
+    #[error("{}", .0)]
+    Single(ItemDiagnostic),
+    #[error("{} deserialization errors occurred", .0.iter().map(|e| e.errors.len()).sum::<usize>())]
+    Multiple(Vec<FileDiagnostic>),
+}
+
+impl Diagnostic for DeserializationDiagnostic {
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        match self {
+            DeserializationDiagnostic::Single(err) => err.source_code(),
+            DeserializationDiagnostic::Multiple(_) => None,
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        match self {
+            DeserializationDiagnostic::Single(err) => err.labels(),
+            DeserializationDiagnostic::Multiple(_) => None,
+        }
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        match self {
+            DeserializationDiagnostic::Single(err) => err.diagnostic_source(),
+            DeserializationDiagnostic::Multiple(_) => None,
+        }
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        match self {
+            DeserializationDiagnostic::Single(_) => None,
+            DeserializationDiagnostic::Multiple(files) => {
+                Some(Box::new(files.iter().map(|e| e as &dyn Diagnostic)))
+            }
+        }
+    }
+}
+
 impl DeserializationError {
+    /// Converts the error into a diagnostic, with combined errors being
+    /// grouped by the files they originate from
     pub fn diagnostic(self) -> impl Diagnostic {
-        self.stack.into_iter().fold(
-            ItemDiagnostic(ItemDiagnosticKind::Cause(self.kind), None),
-            |err, item| ItemDiagnostic(ItemDiagnosticKind::Path(item), Some(Box::new(err))),
-        )
+        let mut errors = self.flatten();
+        if errors.len() == 1 {
+            let err = errors.pop().expect("Should have exactly one error");
+            return DeserializationDiagnostic::Single(ItemDiagnostic::new(err));
+        }
+
+        let files = errors
+            .into_iter()
+            .map(|err| (err.file().cloned(), err))
+            .into_group_map()
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(file, errors)| FileDiagnostic {
+                file,
+                errors: errors
+                    .into_iter()
+                    .map(|mut err| {
+                        // File is already shown by the group
+                        err.stack
+                            .retain(|item| !matches!(item, DeserializationErrorStackItem::File(_)));
+                        ItemDiagnostic::new(err)
+                    })
+                    .collect(),
+            })
+            .collect();
+        DeserializationDiagnostic::Multiple(files)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use miette::Diagnostic;
+
+    use crate::fixture::Fixture;
+
+    #[test]
+    fn errors_of_all_fields_and_files_are_grouped_by_file() {
+        let err = Fixture::default()
+            .file(
+                "eh/variables.json5",
+                r#"[
+  { type: "Variable", id: "eh:speed", name: "Speed", computed: "{eh:missing} * 2", default: "{eh:absent}" },
+  { type: "Variable", id: "eh:drag", name: "Drag", computed: "{eh:lost}" },
+]"#,
+            )
+            .file(
+                "eh/stats.json5",
+                r#"{ type: "ComponentStats", id: "eh:stats", stats: { "eh:gone": 1 } }"#,
+            )
+            .build()
+            .unwrap_err();
+        assert_eq!(err.clone().flatten().len(), 4, "{err}");
+
+        let diagnostic = err.diagnostic();
+        assert_eq!(diagnostic.to_string(), "4 deserialization errors occurred");
+        let files = diagnostic
+            .related()
+            .unwrap()
+            .map(|file| (file.to_string(), file.related().unwrap().count()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                ("In file `eh/stats.json5`".to_string(), 1),
+                ("In file `eh/variables.json5`".to_string(), 3),
+            ]
+        );
     }
 }
@@ -0,0 +1,451 @@
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::model::DataFormat;
+
+use super::spans::SpanTree;
+use super::DeserializationErrorStackItem;
+
+/// Position of the offending value inside the item file
+#[derive(Debug, Clone)]
+pub struct ErrorLocation {
+    pub path: PathBuf,
+    pub text: Arc<str>,
+    /// Byte range of the offending value in the text
+    pub span: Range<usize>,
+}
+
+impl ErrorLocation {
+    /// Locates the value pointed to by the error stack inside the file text
+    ///
+    /// Falls back to the closest enclosing value if the exact one can't be
+    /// found, for example when the value was inherited from another item
+    pub fn new(path: PathBuf, text: Arc<str>, stack: &[DeserializationErrorStackItem]) -> Self {
+        // Stack is ordered from the innermost item, and only the part below
+        // the item itself points inside the file
+        let end = stack
+            .iter()
+            .position(|item| {
+                matches!(
+                    item,
+                    DeserializationErrorStackItem::Item(..)
+                        | DeserializationErrorStackItem::File(_)
+                )
+            })
+            .unwrap_or(stack.len());
+        let segments: Vec<_> = stack[..end]
+            .iter()
+            .rev()
+            .map(|item| match item {
+                DeserializationErrorStackItem::Field(name) => Segment::Key(camel_case(name)),
+                DeserializationErrorStackItem::Index(i) => Segment::Index(*i),
+                DeserializationErrorStackItem::MapEntry(key) => Segment::Key(key.clone()),
+                DeserializationErrorStackItem::ExprVariable(name)
+                | DeserializationErrorStackItem::ExprFunction(name) => {
+                    Segment::Substring(name.clone())
+                }
+                DeserializationErrorStackItem::Item(..)
+                | DeserializationErrorStackItem::File(_) => {
+                    unreachable!("Stack should be cut before the item")
+                }
+            })
+            .collect();
+
+        let span = match DataFormat::from_path(&path) {
+            Some(DataFormat::Toml) => {
+                SpanTree::toml(&text).map(|tree| tree.locate(&text, &segments))
+            }
+            Some(DataFormat::Yaml) => {
+                SpanTree::yaml(&text).map(|tree| tree.locate(&text, &segments))
+            }
+            _ => {
+                let mut scanner = Scanner {
+                    text: &text,
+                    pos: 0,
+                };
+                scanner.locate(&segments).or_else(|| {
+                    scanner.pos = 0;
+                    scanner.value()
+                })
+            }
+        }
+        .unwrap_or_default();
+
+        Self { path, text, span }
+    }
+
+    /// One-based line and column of the start of the value
+    pub fn line_column(&self) -> (usize, usize) {
+        let before = &self.text[..self.span.start];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .map_or(0, |line| line.chars().count())
+            + 1;
+        (line, column)
+    }
+}
+
+impl Display for ErrorLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (line, column) = self.line_column();
+        write!(f, "{}:{line}:{column}", self.path.to_string_lossy())
+    }
+}
+
+/// Serialized field names are camelCase, while the stack holds the Rust ones
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = !out.is_empty();
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[derive(Debug)]
+pub(super) enum Segment {
+    Key(String),
+    Index(usize),
+    /// Part of a string value, such as a variable inside a formula
+    Substring(String),
+}
+
+/// Minimal JSON5 scanner that only keeps track of value positions
+///
+/// Files reaching the registry were already parsed successfully, so the
+/// scanner is lenient and simply gives up on anything unexpected
+struct Scanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            let text = self.text;
+            let rest = &text[self.pos..];
+            let trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '\u{feff}');
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                self.pos += comment.find("*/").map_or(trimmed.len(), |end| end + 4);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Option<()> {
+        self.skip_trivia();
+        if self.peek()? != c {
+            return None;
+        }
+        self.pos += 1;
+        Some(())
+    }
+
+    /// Skips a quoted string, returning its content with escapes stripped
+    fn string(&mut self) -> Option<String> {
+        let quote = self.peek()?;
+        self.pos += 1;
+        let mut out = String::new();
+        let text = self.text;
+        let mut chars = text[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        out.push(escaped);
+                    }
+                }
+                c if c == quote as char => {
+                    self.pos += i + 1;
+                    return Some(out);
+                }
+                c => out.push(c),
+            }
+        }
+        None
+    }
+
+    fn key(&mut self) -> Option<String> {
+        self.skip_trivia();
+        match self.peek()? {
+            b'"' | b'\'' => self.string(),
+            _ => {
+                let text = self.text;
+                let rest = &text[self.pos..];
+                let len = rest
+                    .find(|c: char| c == ':' || c.is_whitespace() || c == '/')
+                    .unwrap_or(rest.len());
+                self.pos += len;
+                (len > 0).then(|| rest[..len].to_string())
+            }
+        }
+    }
+
+    /// Skips a value, returning its span
+    fn value(&mut self) -> Option<Range<usize>> {
+        self.skip_trivia();
+        let start = self.pos;
+        match self.peek()? {
+            b'{' => {
+                self.pos += 1;
+                while !self.close(b'}')? {
+                    self.key()?;
+                    self.expect(b':')?;
+                    self.value()?;
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                while !self.close(b']')? {
+                    self.value()?;
+                }
+            }
+            b'"' | b'\'' => {
+                self.string()?;
+            }
+            _ => {
+                let text = self.text;
+                let rest = &text[start..];
+                let len = rest
+                    .find(|c: char| matches!(c, ',' | '}' | ']' | '/') || c.is_whitespace())
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return None;
+                }
+                self.pos += len;
+            }
+        }
+        Some(start..self.pos)
+    }
+
+    /// Skips a separating comma, returning whether the collection is closed
+    fn close(&mut self, end: u8) -> Option<bool> {
+        self.skip_trivia();
+        if self.peek()? == b',' {
+            self.pos += 1;
+            self.skip_trivia();
+        }
+        if self.peek()? == end {
+            self.pos += 1;
+            return Some(true);
+        }
+        Some(false)
+    }
+
+    /// Finds the span of the value at the path, starting from the value at
+    /// the current position
+    ///
+    /// Returns the span of the deepest value that could be found, or `None`
+    /// if even the first segment of the path is missing
+    fn locate(&mut self, path: &[Segment]) -> Option<Range<usize>> {
+        let Some((segment, rest)) = path.split_first() else {
+            return self.value();
+        };
+        self.skip_trivia();
+        match (self.peek()?, segment) {
+            (b'{', Segment::Key(key)) => {
+                self.pos += 1;
+                while !self.close(b'}')? {
+                    let found = self.key()? == *key;
+                    self.expect(b':')?;
+                    if found {
+                        return self.locate_or_skip(rest);
+                    }
+                    self.value()?;
+                }
+                None
+            }
+            (b'[', Segment::Index(index)) => {
+                self.pos += 1;
+                let mut i = 0;
+                while !self.close(b']')? {
+                    if i == *index {
+                        return self.locate_or_skip(rest);
+                    }
+                    self.value()?;
+                    i += 1;
+                }
+                None
+            }
+            (b'"' | b'\'', Segment::Substring(part)) => {
+                let span = self.value()?;
+                let offset = self.text[span.clone()].find(part.as_str())?;
+                let start = span.start + offset;
+                Some(start..start + part.len())
+            }
+            _ => None,
+        }
+    }
+
+    /// Locates the rest of the path inside the value at the current
+    /// position, falling back to the value itself
+    fn locate_or_skip(&mut self, path: &[Segment]) -> Option<Range<usize>> {
+        self.skip_trivia();
+        let start = self.pos;
+        self.locate(path).or_else(|| {
+            self.pos = start;
+            self.value()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ErrorLocation;
+    use crate::model::serialization::DeserializationErrorStackItem as Stack;
+    use crate::model::DatabaseItemKind;
+
+    /// Text of the value at the path, which is given from the outermost
+    /// value like in the file, unlike the error stack
+    fn located(text: &str, path: &[Stack]) -> String {
+        let stack: Vec<_> = path.iter().rev().cloned().collect();
+        let location = ErrorLocation::new("item.json5".into(), text.into(), &stack);
+        text[location.span].to_string()
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let text = r#"{
+            // "id": "line",
+            /* "id": "block", */
+            "id": /* inline */ "scout", // trailing
+        }"#;
+        assert_eq!(located(text, &[Stack::Field("id")]), r#""scout""#);
+    }
+
+    #[test]
+    fn string_escapes_are_skipped() {
+        let text = r#"{ "name": "say \"id\": \\", 'quote': 'it\'s', "id": "scout" }"#;
+        assert_eq!(located(text, &[Stack::Field("id")]), r#""scout""#);
+        assert_eq!(
+            located(text, &[Stack::MapEntry("quote".into())]),
+            r#"'it\'s'"#
+        );
+    }
+
+    #[test]
+    fn keys_may_be_unquoted_or_single_quoted() {
+        let text = "{ modelScale: 1.5, 'sprite_name': 'a.png', \"id\": 'scout' }";
+        assert_eq!(located(text, &[Stack::Field("model_scale")]), "1.5");
+        assert_eq!(
+            located(text, &[Stack::MapEntry("sprite_name".into())]),
+            "'a.png'"
+        );
+        assert_eq!(located(text, &[Stack::Field("id")]), "'scout'");
+    }
+
+    #[test]
+    fn trailing_commas_are_allowed() {
+        let text = "{ sizes: [1, 2, 3,], engine: { thrust: 10, }, id: 'scout', }";
+        assert_eq!(located(text, &[Stack::Field("id")]), "'scout'");
+        assert_eq!(
+            located(text, &[Stack::Field("sizes"), Stack::Index(2)]),
+            "3"
+        );
+        assert_eq!(
+            located(text, &[Stack::Field("engine"), Stack::Field("thrust")]),
+            "10"
+        );
+    }
+
+    #[test]
+    fn nested_values_are_located() {
+        let text = r#"[
+            { "id": "a" },
+            {
+                "id": "b",
+                "weapons": [[1, 2], { "damage": "{base} * 2" }],
+                "cells": { "x": [0, [5, 6]] },
+            },
+        ]"#;
+        assert_eq!(
+            located(text, &[Stack::Index(1), Stack::Field("id")]),
+            r#""b""#
+        );
+        assert_eq!(
+            located(
+                text,
+                &[Stack::Index(1), Stack::Field("weapons"), Stack::Index(0)]
+            ),
+            "[1, 2]"
+        );
+        assert_eq!(
+            located(
+                text,
+                &[
+                    Stack::Index(1),
+                    Stack::Field("weapons"),
+                    Stack::Index(1),
+                    Stack::Field("damage"),
+                    Stack::ExprVariable("base".into()),
+                ]
+            ),
+            "base"
+        );
+        assert_eq!(
+            located(
+                text,
+                &[
+                    Stack::Index(1),
+                    Stack::Field("cells"),
+                    Stack::MapEntry("x".into()),
+                    Stack::Index(1),
+                    Stack::Index(0),
+                ]
+            ),
+            "5"
+        );
+    }
+
+    #[test]
+    fn missing_values_fall_back_to_the_enclosing_one() {
+        let text = r#"{ "id": "scout", "engine": { "thrust": 10 } }"#;
+        assert_eq!(
+            located(text, &[Stack::Field("engine"), Stack::Field("power")]),
+            r#"{ "thrust": 10 }"#
+        );
+        assert_eq!(
+            located(text, &[Stack::Field("id"), Stack::Index(0)]),
+            r#""scout""#
+        );
+        assert_eq!(located(text, &[Stack::Field("missing")]), text);
+        // Only the part of the stack below the item points inside the file
+        assert_eq!(
+            located(
+                text,
+                &[
+                    Stack::File("item.json5".into()),
+                    Stack::Item("scout".into(), DatabaseItemKind::Ship),
+                    Stack::Field("id"),
+                ]
+            ),
+            r#""scout""#
+        );
+    }
+
+    #[test]
+    fn line_and_column_count_characters() {
+        let text = "{\n  name: 'Ястреб', id: 'hawk' }";
+        let location = ErrorLocation::new("item.json5".into(), text.into(), &[Stack::Field("id")]);
+        assert_eq!(location.line_column(), (2, 23));
+    }
+}
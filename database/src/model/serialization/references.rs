@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::BuildHasher;
+
+use crate::model::{DatabaseItemKind, ItemId, ModelKind};
+
+use super::{DeserializationErrorStackItem, ItemIdSerialized, SerializationBoxingWrapper};
+
+/// Reference to another item, found inside a serialized item
+#[derive(Debug, Clone)]
+pub struct ItemReference {
+    pub kind: DatabaseItemKind,
+    pub id: ItemId,
+    /// Path to the referencing value, ordered from the innermost item like
+    /// the error stack, so it can be located with
+    /// [ErrorLocation](super::ErrorLocation)
+    pub stack: Vec<DeserializationErrorStackItem>,
+}
+
+/// All references of a serialized item
+#[derive(Debug, Clone, Default)]
+pub struct ItemReferences {
+    pub items: Vec<ItemReference>,
+    /// Paths to the formula strings, variables of which are included in the
+    /// item references
+    pub formulas: Vec<Vec<DeserializationErrorStackItem>>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ReferenceCollector {
+    /// Path to the current value, ordered from the outermost item
+    stack: Vec<DeserializationErrorStackItem>,
+    pub references: ItemReferences,
+}
+
+impl ReferenceCollector {
+    fn current_stack(&self) -> Vec<DeserializationErrorStackItem> {
+        self.stack.iter().rev().cloned().collect()
+    }
+
+    pub fn item(&mut self, kind: DatabaseItemKind, id: &str) {
+        let stack = self.current_stack();
+        self.references.items.push(ItemReference {
+            kind,
+            id: id.to_string(),
+            stack,
+        });
+    }
+
+    pub fn formula(&mut self) {
+        let stack = self.current_stack();
+        self.references.formulas.push(stack);
+    }
+
+    pub fn nested(&mut self, item: DeserializationErrorStackItem, f: impl FnOnce(&mut Self)) {
+        self.stack.push(item);
+        f(self);
+        self.stack.pop();
+    }
+}
+
+/// Collects references to other items out of the serialized models
+pub(crate) trait ModelReferences {
+    fn collect_references(&self, collector: &mut ReferenceCollector);
+}
+
+impl<T: ModelReferences> ModelReferences for Option<T> {
+    fn collect_references(&self, collector: &mut ReferenceCollector) {
+        if let Some(value) = self {
+            value.collect_references(collector);
+        }
+    }
+}
+
+impl<T: ModelReferences> ModelReferences for Vec<T> {
+    fn collect_references(&self, collector: &mut ReferenceCollector) {
+        for (i, value) in self.iter().enumerate() {
+            collector.nested(DeserializationErrorStackItem::Index(i), |collector| {
+                value.collect_references(collector)
+            });
+        }
+    }
+}
+
+impl<Key: Display, Value: ModelReferences, Hasher: BuildHasher> ModelReferences
+    for HashMap<Key, Value, Hasher>
+{
+    fn collect_references(&self, collector: &mut ReferenceCollector) {
+        for (key, value) in self {
+            collector.nested(
+                DeserializationErrorStackItem::MapEntry(key.to_string()),
+                |collector| value.collect_references(collector),
+            );
+        }
+    }
+}
+
+impl<T: ModelReferences> ModelReferences for SerializationBoxingWrapper<T> {
+    fn collect_references(&self, collector: &mut ReferenceCollector) {
+        self.0.collect_references(collector);
+    }
+}
+
+impl<T: ModelKind> ModelReferences for ItemIdSerialized<T> {
+    fn collect_references(&self, collector: &mut ReferenceCollector) {
+        collector.item(T::kind(), &self.0);
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt::Formatter;
+use std::ops::Range;
+
+use saphyr_parser::{Event, Parser, Span, StrInput};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use toml::Spanned;
+
+use super::location::Segment;
+
+/// Spans of the values of a parsed file, for the formats whose parsers
+/// report them
+#[derive(Debug)]
+pub(super) struct SpanTree {
+    span: Range<usize>,
+    children: Children,
+}
+
+#[derive(Debug)]
+enum Children {
+    Map(Vec<(String, SpanTree)>),
+    Seq(Vec<SpanTree>),
+    Leaf,
+}
+
+impl SpanTree {
+    pub(super) fn toml(text: &str) -> Option<Self> {
+        let value: TomlValue = toml::from_str(text).ok()?;
+        Some(value.into_tree(0..text.len()))
+    }
+
+    /// Spans of the values of the first document in the YAML stream
+    pub(super) fn yaml(text: &str) -> Option<Self> {
+        let mut events = YamlEvents {
+            text,
+            parser: Parser::new_from_str(text),
+            offsets: text
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([text.len()])
+                .collect(),
+        };
+        loop {
+            match events.next()? {
+                (Event::StreamStart | Event::DocumentStart(_), _) => continue,
+                (event, span) => return events.node(event, span),
+            }
+        }
+    }
+
+    /// Finds the span of the value at the path, falling back to the deepest
+    /// value of the path that could be found
+    pub(super) fn locate(&self, text: &str, path: &[Segment]) -> Range<usize> {
+        let Some((segment, rest)) = path.split_first() else {
+            return self.span.clone();
+        };
+        let child = match (&self.children, segment) {
+            (Children::Map(entries), Segment::Key(key)) => entries
+                .iter()
+                .find(|(other, _)| other == key)
+                .map(|(_, value)| value),
+            (Children::Seq(items), Segment::Index(i)) => items.get(*i),
+            (Children::Leaf, Segment::Substring(part)) => {
+                let offset = text
+                    .get(self.span.clone())
+                    .and_then(|value| value.find(part.as_str()));
+                if let Some(offset) = offset {
+                    let start = self.span.start + offset;
+                    return start..start + part.len();
+                }
+                None
+            }
+            _ => None,
+        };
+        match child {
+            Some(child) => child.locate(text, rest),
+            None => self.span.clone(),
+        }
+    }
+}
+
+/// Value of a TOML document, with the spans of the nested values reported
+/// by the parser
+#[derive(Debug)]
+enum TomlValue {
+    Map(Vec<(String, Spanned<TomlValue>)>),
+    Seq(Vec<Spanned<TomlValue>>),
+    Leaf,
+}
+
+impl TomlValue {
+    fn into_tree(self, span: Range<usize>) -> SpanTree {
+        let subtree = |value: Spanned<TomlValue>| {
+            let span = value.span();
+            value.into_inner().into_tree(span)
+        };
+        let children = match self {
+            TomlValue::Map(entries) => Children::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, subtree(value)))
+                    .collect(),
+            ),
+            TomlValue::Seq(items) => Children::Seq(items.into_iter().map(subtree).collect()),
+            TomlValue::Leaf => Children::Leaf,
+        };
+        SpanTree { span, children }
+    }
+}
+
+impl<'de> Deserialize<'de> for TomlValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TomlVisitor)
+    }
+}
+
+struct TomlVisitor;
+
+impl<'de> Visitor<'de> for TomlVisitor {
+    type Value = TomlValue;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a TOML value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<Self::Value, E> {
+        Ok(TomlValue::Leaf)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<Self::Value, E> {
+        Ok(TomlValue::Leaf)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<Self::Value, E> {
+        Ok(TomlValue::Leaf)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<Self::Value, E> {
+        Ok(TomlValue::Leaf)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<Self::Value, E> {
+        Ok(TomlValue::Leaf)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(TomlValue::Seq(items))
+    }
+
+    // Dates are represented as maps too, which doesn't matter for the spans
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(TomlValue::Map(entries))
+    }
+}
+
+struct YamlEvents<'a> {
+    text: &'a str,
+    parser: Parser<'a, StrInput<'a>>,
+    /// Byte offsets of the characters, which the parser counts positions in
+    offsets: Vec<usize>,
+}
+
+impl<'a> YamlEvents<'a> {
+    fn next(&mut self) -> Option<(Event<'a>, Span)> {
+        self.parser.next_event()?.ok()
+    }
+
+    fn offset(&self, index: usize) -> usize {
+        self.offsets.get(index).copied().unwrap_or(self.text.len())
+    }
+
+    /// Reads the node that starts with the event
+    fn node(&mut self, event: Event<'a>, span: Span) -> Option<SpanTree> {
+        let start = self.offset(span.start.index());
+        let (children, end) = match event {
+            Event::Scalar(..) | Event::Alias(_) => (Children::Leaf, self.offset(span.end.index())),
+            Event::SequenceStart(..) => {
+                let mut items: Vec<SpanTree> = Vec::new();
+                let end = loop {
+                    match self.next()? {
+                        (Event::SequenceEnd, span) => break self.collection_end(start, &span),
+                        (event, span) => items.push(self.node(event, span)?),
+                    }
+                };
+                let end = items.last().map_or(end, |item| end.max(item.span.end));
+                (Children::Seq(items), end)
+            }
+            Event::MappingStart(..) => {
+                let mut entries: Vec<(String, SpanTree)> = Vec::new();
+                let end = loop {
+                    let key = match self.next()? {
+                        (Event::MappingEnd, span) => break self.collection_end(start, &span),
+                        (Event::Scalar(key, ..), _) => key.to_string(),
+                        // Complex keys can't be referred to by the error path
+                        (event, span) => {
+                            self.node(event, span)?;
+                            String::new()
+                        }
+                    };
+                    let (event, span) = self.next()?;
+                    entries.push((key, self.node(event, span)?));
+                };
+                let end = entries
+                    .last()
+                    .map_or(end, |(_, value)| end.max(value.span.end));
+                (Children::Map(entries), end)
+            }
+            _ => return None,
+        };
+        Some(SpanTree {
+            span: start..end,
+            children,
+        })
+    }
+
+    /// End of the collection, which only includes the closing bracket of the
+    /// flow collections, since block collections end wherever the next value
+    /// starts
+    fn collection_end(&self, start: usize, end: &Span) -> usize {
+        match self.text[start..].chars().next() {
+            Some('[' | '{') => self.offset(end.end.index()),
+            _ => start,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::location::Segment;
+    use super::SpanTree;
+
+    fn key(key: &str) -> Segment {
+        Segment::Key(key.to_string())
+    }
+
+    fn variable(name: &str) -> Segment {
+        Segment::Substring(name.to_string())
+    }
+
+    fn toml<'a>(text: &'a str, path: &[Segment]) -> &'a str {
+        let tree = SpanTree::toml(text).expect("Text should parse");
+        &text[tree.locate(text, path)]
+    }
+
+    fn yaml<'a>(text: &'a str, path: &[Segment]) -> &'a str {
+        let tree = SpanTree::yaml(text).expect("Text should parse");
+        &text[tree.locate(text, path)]
+    }
+
+    #[test]
+    fn toml_values_are_located() {
+        let text = r#"
+type = "Ship"
+id = "scout"
+
+[engine]
+thrust = "{base} * 2"
+sizes = [1, 2, 3]
+
+[[weapons]]
+id = "laser"
+"#;
+        assert_eq!(toml(text, &[key("id")]), r#""scout""#);
+        assert_eq!(toml(text, &[key("engine"), key("sizes")]), "[1, 2, 3]");
+        assert_eq!(
+            toml(text, &[key("engine"), key("sizes"), Segment::Index(1)]),
+            "2"
+        );
+        assert_eq!(
+            toml(text, &[key("engine"), key("thrust"), variable("base")]),
+            "base"
+        );
+        assert_eq!(
+            toml(text, &[key("weapons"), Segment::Index(0), key("id")]),
+            r#""laser""#
+        );
+        // Missing values fall back to the deepest one found
+        assert_eq!(
+            toml(text, &[key("engine"), key("sizes"), Segment::Index(5)]),
+            "[1, 2, 3]"
+        );
+    }
+
+    #[test]
+    fn yaml_values_are_located() {
+        let text = "\
+# Scout
+type: Ship
+id: scout
+engine:
+  thrust: '{base} * 2'
+  sizes: [1, 2, 3]
+weapons:
+  - id: laser
+    damage: 10
+  - {id: \"rocket\", damage: 20}
+";
+        assert_eq!(yaml(text, &[key("id")]), "scout");
+        assert_eq!(yaml(text, &[key("engine"), key("sizes")]), "[1, 2, 3]");
+        assert_eq!(
+            yaml(text, &[key("engine"), key("sizes"), Segment::Index(1)]),
+            "2"
+        );
+        assert_eq!(
+            yaml(text, &[key("engine"), key("thrust"), variable("base")]),
+            "base"
+        );
+        assert_eq!(
+            yaml(text, &[key("weapons"), Segment::Index(0)]),
+            "id: laser\n    damage: 10"
+        );
+        assert_eq!(
+            yaml(text, &[key("weapons"), Segment::Index(1)]),
+            "{id: \"rocket\", damage: 20}"
+        );
+        assert_eq!(
+            yaml(text, &[key("weapons"), Segment::Index(1), key("id")]),
+            "\"rocket\""
+        );
+        // Missing values fall back to the deepest one found
+        assert_eq!(
+            yaml(text, &[key("weapons"), Segment::Index(2)]),
+            yaml(text, &[key("weapons")])
+        );
+    }
+
+    #[test]
+    fn yaml_offsets_count_bytes() {
+        assert_eq!(yaml("name: Ястреб\nid: hawk\n", &[key("id")]), "hawk");
+    }
+}
@@ -0,0 +1,452 @@
+//! Serde silently skips the fields it doesn't recognize, which hides typos
+//! in the item files. Files are instead checked against the JSON schema of
+//! the items, which describes the flattened and tagged structures the same
+//! way the editors see them.
+
+use std::fmt::{Display, Formatter, Write};
+use std::sync::OnceLock;
+
+use schemars::schema::{InstanceType, ObjectValidation, Schema, SchemaObject, SingleOrVec};
+use schemars::Map;
+use serde_json::Value;
+
+use crate::model::bundle::raw_items;
+use crate::model::serialization::DeserializationErrorStackItem;
+use crate::model::DatabaseItemSerialized;
+
+/// Limit on the nesting of schemas that are expanded for a single value,
+/// guarding against the schemas referencing themselves
+const MAX_DEPTH: usize = 32;
+
+/// Field of the database file that is not declared by the item
+#[derive(Debug, Clone)]
+pub struct UnknownField {
+    /// Path to the field inside the file, with the innermost segment first
+    /// like in the error stacks
+    pub stack: Vec<DeserializationErrorStackItem>,
+    /// Known field with the most similar name
+    pub suggestion: Option<String>,
+}
+
+impl UnknownField {
+    /// Path to the field in the `components[0].modelScale` form
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for item in self.stack.iter().rev() {
+            match item {
+                DeserializationErrorStackItem::Index(i) => {
+                    write!(path, "[{i}]").expect("Writing to a string should not fail");
+                }
+                DeserializationErrorStackItem::MapEntry(key) => {
+                    if !path.is_empty() {
+                        path.push('.');
+                    }
+                    path.push_str(key);
+                }
+                _ => {}
+            }
+        }
+        path
+    }
+}
+
+impl Display for UnknownField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`", self.path())?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean `{suggestion}`?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Fields of the database file that none of the schemas of its items
+/// declare
+///
+/// Positions of the items inside the file are laid out the same way as by
+/// [`DatabaseFile::items`](crate::model::DatabaseFile::items)
+pub(crate) fn unknown_fields(file: &Value) -> Vec<UnknownField> {
+    static SCHEMA: OnceLock<(Schema, Map<String, Schema>)> = OnceLock::new();
+    let (root, definitions) = SCHEMA.get_or_init(|| {
+        let schema = DatabaseItemSerialized::schema();
+        (schema.schema.into(), schema.definitions)
+    });
+
+    let mut checker = Checker {
+        definitions,
+        found: Vec::new(),
+    };
+    for (position, item) in raw_items(file) {
+        let mut path = position.into_iter().collect();
+        checker.check(&[root], item, &mut path);
+    }
+    checker.found
+}
+
+struct Checker<'a> {
+    definitions: &'a Map<String, Schema>,
+    found: Vec<UnknownField>,
+}
+
+impl<'a> Checker<'a> {
+    /// Checks fields of the value and everything nested in it against the
+    /// schemas that describe it
+    ///
+    /// Path is ordered from the outermost segment, unlike the stacks
+    fn check(
+        &mut self,
+        schemas: &[&'a Schema],
+        value: &Value,
+        path: &mut Vec<DeserializationErrorStackItem>,
+    ) {
+        let mut expanded = Vec::new();
+        let mut open = false;
+        for &schema in schemas {
+            open |= self.expand(schema, value, 0, &mut expanded);
+        }
+
+        match value {
+            Value::Object(fields) => {
+                let objects: Vec<&ObjectValidation> = expanded
+                    .iter()
+                    .filter_map(|schema| schema.object.as_deref())
+                    .collect();
+                // Values that no schema expects to be objects are reported
+                // by the deserialization itself
+                let open = open
+                    || objects.is_empty()
+                    || objects.iter().any(|object| {
+                        !object.pattern_properties.is_empty() || additional(object).is_some()
+                    });
+                for (key, field) in fields {
+                    let children: Vec<&Schema> = objects
+                        .iter()
+                        .filter_map(|&object| object.properties.get(key).or(additional(object)))
+                        .collect();
+                    path.push(DeserializationErrorStackItem::MapEntry(key.clone()));
+                    if !children.is_empty() {
+                        self.check(&children, field, path);
+                    } else if !open {
+                        let known = objects.iter().flat_map(|object| object.properties.keys());
+                        self.found.push(UnknownField {
+                            stack: path.iter().rev().cloned().collect(),
+                            suggestion: suggestion(key, known),
+                        });
+                    }
+                    path.pop();
+                }
+            }
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    let children: Vec<&Schema> = expanded
+                        .iter()
+                        .filter_map(|schema| schema.array.as_deref())
+                        .filter_map(|array| match array.items.as_ref()? {
+                            SingleOrVec::Single(schema) => Some(schema.as_ref()),
+                            SingleOrVec::Vec(schemas) => {
+                                schemas.get(i).or(array.additional_items.as_deref())
+                            }
+                        })
+                        .collect();
+                    if !children.is_empty() {
+                        path.push(DeserializationErrorStackItem::Index(i));
+                        self.check(&children, item, path);
+                        path.pop();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Collects the schemas that describe the structure of the value,
+    /// following the references and picking the matching variants of the
+    /// enums
+    ///
+    /// Returns whether any of the schemas allows arbitrary fields
+    fn expand(
+        &self,
+        schema: &'a Schema,
+        value: &Value,
+        depth: usize,
+        out: &mut Vec<&'a SchemaObject>,
+    ) -> bool {
+        let schema = match schema {
+            Schema::Bool(allowed) => return *allowed,
+            Schema::Object(schema) => schema,
+        };
+        if depth > MAX_DEPTH {
+            return true;
+        }
+
+        let mut open = false;
+        if let Some(target) = self.resolve(schema) {
+            open |= self.expand(target, value, depth + 1, out);
+        }
+        if let Some(subschemas) = &schema.subschemas {
+            for schema in subschemas.all_of.iter().flatten() {
+                open |= self.expand(schema, value, depth + 1, out);
+            }
+            for variants in [&subschemas.any_of, &subschemas.one_of]
+                .into_iter()
+                .flatten()
+            {
+                let matching: Vec<_> = variants
+                    .iter()
+                    .filter(|variant| self.matches(variant, value, depth + 1))
+                    .collect();
+                // Suggestions from every variant are still better than none
+                let variants = if matching.is_empty() {
+                    variants.iter().collect()
+                } else {
+                    matching
+                };
+                for variant in variants {
+                    open |= self.expand(variant, value, depth + 1, out);
+                }
+            }
+        }
+
+        if schema.object.is_some() || schema.array.is_some() {
+            out.push(schema);
+        } else if schema.reference.is_none() && schema.subschemas.is_none() {
+            // Schema of an arbitrary value
+            open |= schema.enum_values.is_none()
+                && schema.const_value.is_none()
+                && schema
+                    .instance_type
+                    .as_ref()
+                    .is_none_or(|types| types.contains(&InstanceType::Object));
+        }
+        open
+    }
+
+    /// Whether the value may belong to the schema, judging only by its type
+    /// and the constant fields, such as the tags of the enums
+    ///
+    /// Required fields are not checked, since items extending other items
+    /// may omit any of them
+    fn matches(&self, schema: &Schema, value: &Value, depth: usize) -> bool {
+        let schema = match schema {
+            Schema::Bool(allowed) => return *allowed,
+            Schema::Object(schema) => schema,
+        };
+        if depth > MAX_DEPTH {
+            return true;
+        }
+
+        if let Some(target) = self.resolve(schema) {
+            if !self.matches(target, value, depth + 1) {
+                return false;
+            }
+        }
+        if let Some(types) = &schema.instance_type {
+            if !types.contains(&instance_type(value)) {
+                let integer = instance_type(value) == InstanceType::Number
+                    && types.contains(&InstanceType::Integer);
+                if !integer {
+                    return false;
+                }
+            }
+        }
+        if let Some(values) = &schema.enum_values {
+            if !values.contains(value) {
+                return false;
+            }
+        }
+        if let Some(constant) = &schema.const_value {
+            if constant != value {
+                return false;
+            }
+        }
+        if let (Some(object), Value::Object(fields)) = (&schema.object, value) {
+            let conflicting = object.properties.iter().any(|(key, property)| {
+                let constant = matches!(
+                    property,
+                    Schema::Object(SchemaObject {
+                        enum_values: Some(_),
+                        ..
+                    }) | Schema::Object(SchemaObject {
+                        const_value: Some(_),
+                        ..
+                    })
+                );
+                constant
+                    && fields
+                        .get(key)
+                        .is_some_and(|field| !self.matches(property, field, depth + 1))
+            });
+            if conflicting {
+                return false;
+            }
+        }
+        if let Some(subschemas) = &schema.subschemas {
+            if !subschemas
+                .all_of
+                .iter()
+                .flatten()
+                .all(|schema| self.matches(schema, value, depth + 1))
+            {
+                return false;
+            }
+            for variants in [&subschemas.any_of, &subschemas.one_of]
+                .into_iter()
+                .flatten()
+            {
+                if !variants
+                    .iter()
+                    .any(|schema| self.matches(schema, value, depth + 1))
+                {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn resolve(&self, schema: &SchemaObject) -> Option<&'a Schema> {
+        let name = schema
+            .reference
+            .as_deref()?
+            .strip_prefix("#/definitions/")?;
+        self.definitions.get(name)
+    }
+}
+
+/// Schema of the fields not listed in the properties, if they are allowed
+fn additional(object: &ObjectValidation) -> Option<&Schema> {
+    object
+        .additional_properties
+        .as_deref()
+        .filter(|schema| !matches!(schema, Schema::Bool(false)))
+}
+
+fn instance_type(value: &Value) -> InstanceType {
+    match value {
+        Value::Null => InstanceType::Null,
+        Value::Bool(_) => InstanceType::Boolean,
+        Value::Number(_) => InstanceType::Number,
+        Value::String(_) => InstanceType::String,
+        Value::Array(_) => InstanceType::Array,
+        Value::Object(_) => InstanceType::Object,
+    }
+}
+
+/// Known field that is most likely meant by the unknown one, ignoring the
+/// case
+fn suggestion<'k>(field: &str, known: impl IntoIterator<Item = &'k String>) -> Option<String> {
+    let field = field.to_lowercase();
+    let threshold = (field.chars().count() / 3).max(1);
+    known
+        .into_iter()
+        .map(|candidate| (distance(&field, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone())
+}
+
+/// Levenshtein distance between the strings
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(a != *b))
+                .min(row[j] + 1)
+                .min(above + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use crate::model::{DataFormat, DatabaseAsset};
+
+    use super::{suggestion, unknown_fields, UnknownField};
+
+    fn paths(fields: &[UnknownField]) -> Vec<String> {
+        fields.iter().map(UnknownField::path).collect()
+    }
+
+    #[test]
+    fn typos_in_flattened_item_fields_are_found() {
+        let fields = unknown_fields(&json!({
+            "type": "Ship",
+            "id": "eh:scout",
+            "sprite": "scout.png",
+            "modelscale": 1,
+        }));
+        assert_eq!(paths(&fields), ["modelscale"]);
+        assert_eq!(fields[0].suggestion.as_deref(), Some("modelScale"));
+        assert_eq!(
+            fields[0].to_string(),
+            "`modelscale`, did you mean `modelScale`?"
+        );
+    }
+
+    #[test]
+    fn typos_in_fields_of_the_registry_entry_are_found() {
+        let fields = unknown_fields(&json!({
+            "type": "Ship",
+            "id": "eh:scout2",
+            "extend": "eh:scout",
+        }));
+        assert_eq!(paths(&fields), ["extend"]);
+        assert_eq!(fields[0].suggestion.as_deref(), Some("extends"));
+    }
+
+    #[test]
+    fn nested_fields_are_reported_with_their_paths() {
+        let fields = unknown_fields(&json!([
+            {
+                "type": "ShipBuild",
+                "id": "eh:scout",
+                "ship": "eh:scout",
+                "components": [{ "component": "eh:laser", "pos": [0, 0], "rotation": 90 }],
+            },
+            { "type": "Variable", "id": "eh:health", "name": "Health" },
+        ]));
+        assert_eq!(paths(&fields), ["[0].components[0].rotation"]);
+        assert_eq!(fields[0].suggestion, None);
+        assert_eq!(fields[0].to_string(), "`[0].components[0].rotation`");
+    }
+
+    #[test]
+    fn known_fields_are_not_reported() {
+        let fields = unknown_fields(&json!({
+            "type": "Variable",
+            "id": "eh:health",
+            "name": "Health",
+            "computed": "1 + 2",
+        }));
+        assert!(fields.is_empty(), "{fields:?}");
+    }
+
+    #[test]
+    fn stray_version_of_the_sample_mod_is_found() {
+        let text: Arc<str> = include_str!("../../../ehce/mods/mod2/ship.json").into();
+        let asset = DatabaseAsset::parse(&text, DataFormat::Json5).unwrap();
+        let (_, fields) = asset.load(0).unwrap();
+        assert_eq!(paths(&fields), ["version"]);
+    }
+
+    #[test]
+    fn suggestions_ignore_the_case_and_distant_names() {
+        let known = ["modelScale".to_string(), "sprite".to_string()];
+        assert_eq!(
+            suggestion("MODELSCALE", &known).as_deref(),
+            Some("modelScale")
+        );
+        assert_eq!(suggestion("sprit", &known).as_deref(), Some("sprite"));
+        assert_eq!(suggestion("color", &known), None);
+    }
+}
@@ -0,0 +1,206 @@
+use rustc_hash::FxHashMap;
+
+use crate::model::serialization::{
+    DeserializationError, DeserializationErrorKind, DeserializationErrorStackItem,
+};
+use crate::model::{DatabaseItemKind, ItemId, ModRegistry, VariableId};
+
+/// Order in which the variables can be evaluated, with every variable
+/// coming after all variables used by its formulas
+///
+/// Formulas of the variables are checked for cycles when the registry is
+/// built, so the order always exists
+#[derive(Debug, Default, Clone)]
+pub struct VariableOrder {
+    order: Vec<VariableId>,
+    /// Positions of the variables in the order
+    ranks: FxHashMap<VariableId, usize>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+impl VariableOrder {
+    /// Variables in the evaluation order
+    pub fn iter(&self) -> impl Iterator<Item = VariableId> + '_ {
+        self.order.iter().copied()
+    }
+
+    /// Position of the variable in the evaluation order, variables with
+    /// lower ranks never depend on the ones with higher ranks
+    pub fn rank(&self, id: VariableId) -> Option<usize> {
+        self.ranks.get(&id).copied()
+    }
+
+    /// Orders the variables by the `computed` and `default` formulas,
+    /// reporting every cycle among them
+    pub(crate) fn build(registry: &ModRegistry) -> Result<Self, DeserializationError> {
+        let mut visits: FxHashMap<VariableId, Visit> = FxHashMap::default();
+        let mut order = Vec::new();
+        let mut errors = Vec::new();
+        for (root, _) in registry.variable.iter() {
+            if visits.contains_key(&root) {
+                continue;
+            }
+            // Variables being visited, along with their dependencies that are
+            // not visited yet
+            visits.insert(root, Visit::InProgress);
+            let mut stack = vec![(root, dependencies(registry, root))];
+            while let Some((id, pending)) = stack.last_mut() {
+                let id = *id;
+                let Some(dependency) = pending.next() else {
+                    visits.insert(id, Visit::Done);
+                    order.push(id);
+                    stack.pop();
+                    continue;
+                };
+                match visits.get(&dependency) {
+                    None => {
+                        visits.insert(dependency, Visit::InProgress);
+                        stack.push((dependency, dependencies(registry, dependency)));
+                    }
+                    Some(Visit::InProgress) => {
+                        let start = stack
+                            .iter()
+                            .position(|(id, _)| *id == dependency)
+                            .expect("Variable in progress should be on the stack");
+                        let cycle: Vec<VariableId> =
+                            stack[start..].iter().map(|(id, _)| *id).collect();
+                        errors.push(cycle_error(registry, &cycle));
+                    }
+                    Some(Visit::Done) => {}
+                }
+            }
+        }
+
+        if let Some(err) = DeserializationError::combine(errors) {
+            return Err(err);
+        }
+        let ranks = order.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        Ok(Self { order, ranks })
+    }
+}
+
+/// Variables used by the formulas of the variable
+fn dependencies(registry: &ModRegistry, id: VariableId) -> std::vec::IntoIter<VariableId> {
+    let data = &registry.variable[id].data;
+    data.computed
+        .iter()
+        .chain(&data.default)
+        .flat_map(|formula| formula.args.iter().copied())
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Reports the cycle on the formula of its first variable that uses the
+/// next one
+fn cycle_error(registry: &ModRegistry, cycle: &[VariableId]) -> DeserializationError {
+    let key = |id: VariableId| -> ItemId {
+        registry
+            .variable
+            .id_to_key(id)
+            .cloned()
+            .unwrap_or_else(|| format!("{id:?}"))
+    };
+    let first = cycle[0];
+    let next = cycle.get(1).copied().unwrap_or(first);
+    let data = &registry.variable[first].data;
+    let field = if data
+        .computed
+        .as_ref()
+        .is_some_and(|formula| formula.args.contains(&next))
+    {
+        "computed"
+    } else {
+        "default"
+    };
+
+    let mut ids: Vec<ItemId> = cycle.iter().map(|id| key(*id)).collect();
+    ids.push(key(first));
+    DeserializationError::from(DeserializationErrorKind::VariableCycle(ids))
+        .context(DeserializationErrorStackItem::Field(field))
+        .context(DeserializationErrorStackItem::Item(
+            key(first),
+            DatabaseItemKind::Variable,
+        ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fixture::Fixture;
+    use crate::model::serialization::{DeserializationErrorKind, DeserializationErrorStackItem};
+    use crate::model::VariableId;
+
+    const CHAIN: &str = r#"[
+  { type: "Variable", id: "eh:total", name: "Total", computed: "{eh:boost} + {eh:speed}" },
+  { type: "Variable", id: "eh:boost", name: "Boost", default: "{eh:speed} * 2" },
+  { type: "Variable", id: "eh:speed", name: "Speed" },
+  { type: "Variable", id: "eh:armor", name: "Armor" },
+]"#;
+
+    const CYCLE: &str = r#"[
+  { type: "Variable", id: "eh:a", name: "A", computed: "{eh:b} + 1" },
+  { type: "Variable", id: "eh:b", name: "B", default: "{eh:c} + 1" },
+  { type: "Variable", id: "eh:c", name: "C", computed: "{eh:a} + 1" },
+  { type: "Variable", id: "eh:d", name: "D", computed: "{eh:a}" },
+]"#;
+
+    #[test]
+    fn variables_are_ranked_after_their_dependencies() {
+        let registry = Fixture::default()
+            .file("eh/variables.json", CHAIN)
+            .build()
+            .unwrap();
+        let order = registry.variable_order();
+        let id = |key: &str| -> VariableId { registry.variable.key_to_id(key).unwrap() };
+        let rank = |key: &str| order.rank(id(key)).unwrap();
+
+        assert!(rank("eh:speed") < rank("eh:boost"));
+        assert!(rank("eh:boost") < rank("eh:total"));
+        let ranked = order.iter().collect::<Vec<_>>();
+        assert_eq!(ranked.len(), 4);
+        for (i, id) in ranked.into_iter().enumerate() {
+            assert_eq!(order.rank(id), Some(i));
+        }
+    }
+
+    #[test]
+    fn cycles_are_reported_with_the_full_path() {
+        let err = Fixture::default()
+            .file("eh/variables.json", CYCLE)
+            .build()
+            .unwrap_err();
+        let errors = err.flatten();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+
+        let DeserializationErrorKind::VariableCycle(path) = &errors[0].kind else {
+            panic!("Expected a cycle, got {}", errors[0]);
+        };
+        // The cycle is reported from the variable it was found at, so only
+        // the order of the variables along it is fixed
+        assert_eq!(path.len(), 4, "{path:?}");
+        assert_eq!(path.first(), path.last());
+        for step in path.windows(2) {
+            assert!(
+                [["eh:a", "eh:b"], ["eh:b", "eh:c"], ["eh:c", "eh:a"]]
+                    .contains(&[step[0].as_str(), step[1].as_str()]),
+                "{path:?}"
+            );
+        }
+
+        let field = match path[0].as_str() {
+            "eh:b" => "default",
+            _ => "computed",
+        };
+        assert!(errors[0]
+            .stack
+            .iter()
+            .any(|item| matches!(item, DeserializationErrorStackItem::Field(f) if *f == field)));
+        assert!(errors[0].stack.iter().any(
+            |item| matches!(item, DeserializationErrorStackItem::Item(id, _) if *id == path[0])
+        ));
+    }
+}
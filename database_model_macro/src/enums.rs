@@ -61,10 +61,19 @@ pub fn process_enum(
             Self::#variant_name(item) => #model_name::#variant_name(#serialization_mod::ModelDeserializable::<#original_ty>::deserialize(item, registry)?),
         };
 
-        Result::<(TokenStream, TokenStream), Error>::Ok((serialized_variant, deserialization_match))
-    }).collect::<Result<Vec<(TokenStream, TokenStream)>, _>>()?;
+        let serialization_match = quote_spanned! {variant.span()=>
+            Self::#variant_name(item) => #serialized_name::#variant_name(#serialization_mod::ModelSerializable::<#serialized_ty>::serialize(item, registry)),
+        };
+
+        let references_match = quote_spanned! {variant.span()=>
+            Self::#variant_name(item) => #serialization_mod::ModelReferences::collect_references(item, collector),
+        };
 
-    let (variants, deserialization): (Vec<_>, Vec<_>) = variants.into_iter().unzip();
+        Result::<(TokenStream, TokenStream, TokenStream, TokenStream), Error>::Ok((serialized_variant, deserialization_match, serialization_match, references_match))
+    }).collect::<Result<Vec<(TokenStream, TokenStream, TokenStream, TokenStream)>, _>>()?;
+
+    let (variants, deserialization, serialization, references): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) =
+        variants.into_iter().multiunzip();
 
     let model_name_str = model_name.to_string();
     let schema_derive = attr.schema_derive();
@@ -99,6 +108,24 @@ pub fn process_enum(
                 })
             }
         }
+
+        #[automatically_derived]
+        impl #serialization_mod::ModelSerializable<#serialized_name> for #model_name {
+            fn serialize(&self, registry: &#model_mod::ModRegistry) -> #serialized_name {
+                match self {
+                    #(#serialization)*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl #serialization_mod::ModelReferences for #serialized_name {
+            fn collect_references(&self, collector: &mut #serialization_mod::ReferenceCollector) {
+                match self {
+                    #(#references)*
+                }
+            }
+        }
     }
         .into())
 }
@@ -3,7 +3,7 @@ use std::hash::{BuildHasher, BuildHasherDefault};
 
 use attribute_derive::Attribute;
 use convert_case::{Case, Casing};
-use proc_macro2::{Ident, Literal};
+use proc_macro2::{Ident, Literal, Span};
 use quote::{format_ident, quote, quote_spanned};
 use rustc_hash::FxHasher;
 use syn::spanned::Spanned;
@@ -21,6 +21,7 @@ enum Modifier {
 struct FieldData {
     name: Ident,
     original_type: Type,
+    serialized_type: proc_macro2::TokenStream,
     definition: proc_macro2::TokenStream,
     modifiers: Vec<Modifier>,
 }
@@ -39,6 +40,22 @@ struct FieldAttributeInput {
 }
 
 impl FieldAttributeInput {
+    /// Mirrors min and max validators in the schema
+    fn schema_range(&self, attr: &AttributeInput) -> Option<proc_macro2::TokenStream> {
+        if attr.no_schema {
+            return None;
+        }
+        let min = self.min.iter().map(|min| quote!(min = #min));
+        let max = self.max.iter().map(|max| quote!(max = #max));
+        let bounds: Vec<_> = min.chain(max).collect();
+        if bounds.is_empty() {
+            return None;
+        }
+        Some(quote! {
+            #[schemars(range(#(#bounds),*))]
+        })
+    }
+
     fn apply(self, data: &mut FieldData) {
         if let Some(min) = self.min {
             data.modifiers.push(Modifier::Min(min));
@@ -92,8 +109,10 @@ pub fn process_struct(attr: TokenStream, mut data: ItemStruct) -> Result<TokenSt
             serialized_type(ty)?
         };
         let fallthrough_attrs = fallthrough(&mut field.attrs);
+        let range_attr = attribute_data.schema_range(&attr);
         let definition = quote_spanned!(field.span()=>
             #(#fallthrough_attrs)*
+            #range_attr
             #name: #serialized_type
         );
 
@@ -102,6 +121,7 @@ pub fn process_struct(attr: TokenStream, mut data: ItemStruct) -> Result<TokenSt
             modifiers: Vec::new(),
             definition,
             original_type: ty.clone(),
+            serialized_type,
         };
         attribute_data.apply(&mut field_data);
 
@@ -147,11 +167,14 @@ pub fn process_struct(attr: TokenStream, mut data: ItemStruct) -> Result<TokenSt
     let _kind_name = format_ident!("{}", kind_name);
 
     let names = fields.iter().map(|e| &e.name);
+    let field_names = names.clone();
     let hasher = BuildHasherDefault::<FxHasher>::default();
 
     let _reservation_field_name = format_ident!("reserved_{}__", hasher.hash_one(&data.ident));
     let serialized_field_name = format_ident!("serialized_{}__", hasher.hash_one(&data.ident));
+    let errors_name = format_ident!("errors_{}__", hasher.hash_one(&data.ident));
 
+    let label = syn::Lifetime::new("'field", Span::call_site());
     let modifiers = fields.iter().map(|f| {
         let name = &f.name;
         let data = syn::Ident::new("data", name.span());
@@ -163,12 +186,12 @@ pub fn process_struct(attr: TokenStream, mut data: ItemStruct) -> Result<TokenSt
         let err_handler_end = quote! {
             {
                 Ok(data) => data,
-                Err(err) => return Err(err.context(#serialization_mod::DeserializationErrorStackItem::Field(#name_string))),
+                Err(err) => break #label Err(err.context(#serialization_mod::DeserializationErrorStackItem::Field(#name_string))),
             }
         };
         let modifier_body = f.modifiers
             .iter()
-            .rfold(quote!(#data), |stream, modifier| match modifier {
+            .rfold(quote!(Ok(#data)), |stream, modifier| match modifier {
                 Modifier::Min(num) => {
                     quote! {
                         let #data: #original_type = #err_handler_start #serialization_mod::ApplyMin::apply(#data, #num) #err_handler_end;
@@ -182,8 +205,10 @@ pub fn process_struct(attr: TokenStream, mut data: ItemStruct) -> Result<TokenSt
                     }
                 }
             });
+        // Fields are deserialized independently, so errors of all fields get
+        // reported at once
         quote_spanned! { original_type.span()=>
-            let #name = {
+            let #name: Result<#original_type, #serialization_mod::DeserializationError> = #label: {
                 let #data: #original_type = #err_handler_start #serialization_mod::ModelDeserializable::<#original_type>::deserialize(#serialized_field_name.#name, registry) #err_handler_end;
                 #modifier_body
             };
@@ -197,19 +222,69 @@ pub fn process_struct(attr: TokenStream, mut data: ItemStruct) -> Result<TokenSt
                 let #serialized_field_name = self;
                 #(#modifiers)*
 
+                let mut #errors_name: Vec<#serialization_mod::DeserializationError> = Vec::new();
+                #(
+                    let #field_names = #field_names.map_err(|err| #errors_name.push(err)).ok();
+                )*
+                if let Some(err) = #serialization_mod::DeserializationError::combine(#errors_name) {
+                    return Err(err);
+                }
+
                 Ok(#model_name {
-                    #(#names),*
+                    #(#names: #names.expect("Field should be deserialized if there are no errors")),*
                 })
             }
         }
     };
 
+    let serialization_fields = fields.iter().map(|f| {
+        let name = &f.name;
+        let serialized_type = &f.serialized_type;
+        quote_spanned! { f.original_type.span()=>
+            #name: #serialization_mod::ModelSerializable::<#serialized_type>::serialize(&self.#name, registry)
+        }
+    });
+
+    let serialization_impl = quote! {
+        #[automatically_derived]
+        impl #serialization_mod::ModelSerializable<#serialized_name> for #model_name {
+            fn serialize(&self, registry: &#model_mod::ModRegistry) -> #serialized_name {
+                #serialized_name {
+                    #(#serialization_fields),*
+                }
+            }
+        }
+    };
+
+    let reference_fields = fields.iter().map(|f| {
+        let name = &f.name;
+        let name_string = name.to_string();
+        quote_spanned! { f.original_type.span()=>
+            collector.nested(#serialization_mod::DeserializationErrorStackItem::Field(#name_string), |collector| {
+                #serialization_mod::ModelReferences::collect_references(&self.#name, collector)
+            });
+        }
+    });
+
+    let references_impl = quote! {
+        #[automatically_derived]
+        impl #serialization_mod::ModelReferences for #serialized_name {
+            fn collect_references(&self, collector: &mut #serialization_mod::ReferenceCollector) {
+                #(#reference_fields)*
+            }
+        }
+    };
+
     let all_together = quote! {
         #data
 
         #serialized_struct
 
         #deserialization_impl
+
+        #serialization_impl
+
+        #references_impl
     };
 
     Ok(all_together.into())
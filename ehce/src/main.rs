@@ -5,7 +5,7 @@ fn main() {
     App::new()
         .add_plugins((DefaultPlugins.set(AssetPlugin {
             mode: AssetMode::Unprocessed,
-            file_path: "mods".to_string(),
+            file_path: ehce_core::mods::MODS_FOLDER.to_string(),
             processed_file_path: "tmp".to_string(),
             ..Default::default()
         }),))
@@ -0,0 +1,138 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use bevy::app::{App, Plugin};
+use bevy::asset::io::Reader;
+use bevy::asset::{Asset, AssetApp, AssetLoader, AsyncReadExt, BoxedFuture, LoadContext};
+use database::model::{DataFormat, DatabaseAsset, FileCache, FormatError};
+use thiserror::Error;
+use tracing::error;
+
+/// Asset that keeps the text of the file it was loaded from
+pub trait SourceAsset {
+    fn set_source(&mut self, source: Arc<str>);
+}
+
+impl SourceAsset for DatabaseAsset {
+    fn set_source(&mut self, source: Arc<str>) {
+        self.1 = Some(source);
+    }
+}
+
+/// Plugin to load your asset type `A` from files in any of the given formats.
+pub struct DataAssetPlugin<A> {
+    formats: Vec<DataFormat>,
+    cache: Option<FileCache>,
+    _marker: PhantomData<A>,
+}
+
+impl<A> Plugin for DataAssetPlugin<A>
+where
+    for<'de> A: serde::Deserialize<'de> + serde::Serialize + Asset + SourceAsset,
+{
+    fn build(&self, app: &mut App) {
+        app.init_asset::<A>()
+            .register_asset_loader(DataAssetLoader::<A> {
+                extensions: self
+                    .formats
+                    .iter()
+                    .flat_map(|format| format.extensions())
+                    .copied()
+                    .collect(),
+                cache: self.cache.clone(),
+                _marker: PhantomData,
+            });
+    }
+}
+
+impl<A> DataAssetPlugin<A>
+where
+    for<'de> A: serde::Deserialize<'de> + Asset,
+{
+    /// Create a new plugin that will load assets from files of the given formats.
+    pub fn new(formats: &[DataFormat]) -> Self {
+        Self {
+            formats: formats.to_owned(),
+            cache: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Keep the parsed files in the cache, skipping the parsing of the
+    /// files that didn't change since the last load
+    pub fn with_cache(mut self, cache: FileCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+}
+
+struct DataAssetLoader<A> {
+    extensions: Vec<&'static str>,
+    cache: Option<FileCache>,
+    _marker: PhantomData<A>,
+}
+
+/// Possible errors that can be produced by [`DataAssetLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum DataLoaderError {
+    /// An [IO Error](std::io::Error)
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+    /// File is not valid UTF-8
+    #[error("Could not read the file: {0}")]
+    Utf8(#[from] std::string::FromUtf8Error),
+    /// File extension doesn't belong to any known format
+    #[error("Unsupported file extension of `{0}`")]
+    UnknownFormat(String),
+    /// A [parse Error](FormatError)
+    #[error("Could not parse the file: {0}")]
+    Parse(#[from] FormatError),
+}
+
+impl<A> AssetLoader for DataAssetLoader<A>
+where
+    for<'de> A: serde::Deserialize<'de> + serde::Serialize + Asset + SourceAsset,
+{
+    type Asset = A;
+    type Settings = ();
+    type Error = DataLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let Some(format) = DataFormat::from_path(load_context.path()) else {
+                return Err(DataLoaderError::UnknownFormat(
+                    load_context.asset_path().to_string(),
+                ));
+            };
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let text = String::from_utf8(bytes)?;
+            let parsed = match &self.cache {
+                Some(cache) => cache.parse::<A>(&text, format),
+                None => format.parse::<A>(&text),
+            };
+            match parsed {
+                Ok(mut data) => {
+                    if format.supports_locations() {
+                        data.set_source(text.into());
+                    }
+                    Ok(data)
+                }
+                Err(err) => {
+                    error!("Failed to load {}. {}", load_context.asset_path(), err);
+                    Err(err.into())
+                }
+            }
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &self.extensions
+    }
+}
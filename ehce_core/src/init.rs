@@ -34,25 +34,18 @@ fn init_tick(
         info!("Mod is loaded, switching to combat state");
         let mod_data = data.0;
 
-        let fleet = mod_data
-            .registry
-            .fleet
-            .values()
-            .next()
-            .expect("Should have at least one fleet")
-            .data
-            .clone();
+        let registry = &mod_data.registry;
+        let Some(defaults) = registry.defaults() else {
+            state.set(GameState::Error);
+            mod_state.set(ModState::None);
+            error!("Loaded mod doesn't declare defaults, switching to error state");
+            return;
+        };
+        let combat_settings = &registry[defaults.combat_settings].data;
         commands.insert_resource(CombatInit {
-            player_fleet: fleet.clone(),
-            enemy_fleet: fleet,
-            combat_settings: mod_data
-                .registry
-                .combat_settings
-                .values()
-                .next()
-                .expect("Should have at least one Combat settings")
-                .data
-                .clone(),
+            player_fleet: combat_settings.player_fleet.get(registry).clone(),
+            enemy_fleet: combat_settings.enemy_fleet.get(registry).clone(),
+            combat_settings: combat_settings.clone(),
         });
         commands.insert_resource(mod_data);
         mod_state.set(ModState::Ready);
@@ -1,23 +1,24 @@
 use std::marker::PhantomData;
 
 use bevy::app::App;
+use bevy::asset::io::file::FileAssetReader;
 use bevy::ecs::prelude::States;
 use bevy::prelude::*;
 
 // Re-export database
 pub use database;
-use database::model::DatabaseAsset;
+use database::model::{DataFormat, DatabaseAsset, FileCache};
 
+use crate::data_asset_plugin::DataAssetPlugin;
 use crate::init::InitPlugin;
-use crate::json5_asset_plugin::Json5AssetPlugin;
-use crate::mods::ModPlugin;
+use crate::mods::{ModCache, ModPlugin};
 
 pub mod glue;
 pub mod mods;
 
 mod init;
 
-mod json5_asset_plugin;
+mod data_asset_plugin;
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
 pub enum GameState {
@@ -36,8 +37,11 @@ pub struct CorePlugin;
 
 impl Plugin for CorePlugin {
     fn build(&self, app: &mut App) {
+        // Cache lives next to the mods folder, rather than inside of it
+        let cache = FileCache::new(FileAssetReader::get_base_path().join("cache"));
+        app.insert_resource(ModCache(cache.clone()));
         app.add_state::<GameState>().add_plugins((
-            Json5AssetPlugin::<DatabaseAsset>::new(&["json", "json5"]),
+            DataAssetPlugin::<DatabaseAsset>::new(DataFormat::ALL).with_cache(cache),
             InitPlugin,
             ModPlugin,
         ));
@@ -1,16 +1,35 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use bevy::app::{App, Plugin};
+use bevy::asset::io::file::FileAssetReader;
 use bevy::asset::{Handle, LoadedFolder};
 use bevy::prelude::{Event, First, Resource, States, SystemSet};
+use rustc_hash::FxHashMap;
 
-use database::model::{ModRegistry, RegistryId};
+use database::model::{ExportError, FileCache, ModRegistry, RegistryId};
 use slabmap::SlabMapId;
 
 use crate::mods::loading::ModLoadingPlugin;
 
 pub mod loading;
 
+/// Folder holding the mods, relative to the base path of the assets
+///
+/// Serves as the file path of the `AssetPlugin`, so mod folders are loaded
+/// by their names
+pub const MODS_FOLDER: &str = "mods";
+
+/// Absolute path of the folder holding the mods
+pub fn mods_folder() -> PathBuf {
+    FileAssetReader::get_base_path().join(MODS_FOLDER)
+}
+
+/// Cache of the parsed database files, shared with the asset loader
+///
+/// Pruned every time the mods are loaded
+#[derive(Debug, Clone, Resource)]
+pub struct ModCache(pub FileCache);
+
 #[derive(Debug)]
 pub struct ModPlugin;
 
@@ -25,13 +44,55 @@ impl Plugin for ModPlugin {
     }
 }
 
-#[derive(Debug, Resource)]
-pub struct ModData {
+/// Single mod folder layered into the [ModData] registry
+#[derive(Debug)]
+pub struct LoadedMod {
     pub name: String,
-    pub registry: ModRegistry,
     pub mod_path: PathBuf,
     pub folder_handle: Handle<LoadedFolder>,
-    // pub assets: FxBiHashMap<Utf8PathBuf, RegistryId>,
+}
+
+#[derive(Debug, Resource)]
+pub struct ModData {
+    /// Loaded mods, in the load order
+    pub mods: Vec<LoadedMod>,
+    pub registry: ModRegistry,
+    /// Files of the database items, relative to the [mods folder](MODS_FOLDER),
+    /// with all items of a bundle file sharing the same path
+    ///
+    /// Items overridden by later mods point to the files of the overriding
+    /// items, and deleted items are not included
+    pub assets: FxHashMap<RegistryId, PathBuf>,
+}
+
+impl ModData {
+    /// Names of the loaded mods, in the load order
+    pub fn load_order(&self) -> Vec<String> {
+        self.mods.iter().map(|e| e.name.clone()).collect()
+    }
+
+    /// Items declared by the file
+    pub fn file_items<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = RegistryId> + 'a {
+        self.assets
+            .iter()
+            .filter(move |(_, file)| *file == path)
+            .map(|(id, _)| *id)
+    }
+
+    /// Writes the item back into the file it was loaded from
+    ///
+    /// Item is written fully resolved, so the file no longer extends the
+    /// parent item afterwards. Items of the bundle files can't be written
+    /// one by one, since that would drop the rest of the bundle
+    pub fn save_item(&self, id: RegistryId) -> Result<(), ExportError> {
+        let path = self.assets.get(&id).ok_or(ExportError::MissingItem(id))?;
+        if self.file_items(path).any(|other| other != id) {
+            return Err(ExportError::Bundled(id, path.clone()));
+        }
+        // Asset paths are relative to the mods folder rather than to the
+        // base path of the assets
+        self.registry.export_item(id, mods_folder().join(path))
+    }
 }
 
 #[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
@@ -52,9 +113,13 @@ impl States for ModState {}
 
 /// Event that triggers loading of a new mod
 ///
+/// Payload is a list of mod folders, each mod being able to override, add or
+/// delete items of the mods loaded before it. Mods are loaded after their
+/// dependencies, and in the given order otherwise
+///
 /// Should generally be only raised by app code, but not listened to
 #[derive(Debug, Event)]
-pub struct WantLoadModEvent(String);
+pub struct WantLoadModEvent(pub Vec<String>);
 
 /// Event that is triggered when mod loading fails for any reason
 ///
@@ -76,6 +141,9 @@ pub struct ModLoadedEvent(pub ModData);
 
 /// Event that is triggered when hot reload happens
 ///
+/// Single item events are sent both for the reloaded items and for all items
+/// referencing them
+///
 /// For most use cases, [ModHotReloadEvent] is more ergonomic
 #[derive(Debug, Event)]
 pub enum ModUntypedHotReloadEvent {
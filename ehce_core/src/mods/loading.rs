@@ -2,35 +2,32 @@ use std::any::TypeId;
 use std::ops::DerefMut;
 use std::path::{Path, PathBuf};
 
-use bevy::asset::io::file::FileAssetReader;
 use bevy::asset::{LoadState, LoadedFolder, UntypedAssetId};
 use bevy::core::FrameCount;
 use bevy::prelude::*;
-use miette::Diagnostic;
-use rustc_hash::FxHashSet;
+use miette::Report;
+use rustc_hash::{FxHashMap, FxHashSet};
 
 use database::call_with_all_models;
+use database::model::migration::mod_data_version;
 use database::model::{
-    DatabaseAsset, DatabaseItemKind, DatabaseItemSerialized, ModRegistry, RegistryId,
+    load_order, DatabaseAsset, DatabaseItemKind, DatabaseItemSerializedTrait, LintSettings,
+    ModLayer, ModRegistry, RegistryId,
 };
 use utils::miette_ext::DiagnosticWrapper;
 
 use crate::mods::{
-    HotReloading, ModData, ModHotReloadEvent, ModLoadErrorEvent, ModLoadedEvent, ModState,
-    ModUntypedHotReloadEvent, WantLoadModEvent,
+    mods_folder, HotReloading, LoadedMod, ModCache, ModData, ModHotReloadEvent, ModLoadErrorEvent,
+    ModLoadedEvent, ModState, ModUntypedHotReloadEvent, WantLoadModEvent,
 };
 use crate::{report_error, SimpleStateObjectPlugin};
 
+/// Loads all mods of the mods folder, ordered by their names until the
+/// dependencies are known
 pub fn load_last_mod(mut evt: EventWriter<WantLoadModEvent>) {
-    let schema = serde_json5::to_string(&DatabaseItemSerialized::schema()).unwrap();
-    std::fs::write(
-        FileAssetReader::get_base_path()
-            .join("mods")
-            .join("$schema.json"),
-        schema,
-    )
-    .unwrap();
-    evt.send(WantLoadModEvent("mod".to_string()));
+    let mut mods: Vec<String> = available_mods([mods_folder()]).collect();
+    mods.sort();
+    evt.send(WantLoadModEvent(mods));
 }
 
 #[derive(Debug)]
@@ -66,8 +63,8 @@ impl Plugin for ModLoadingPlugin {
 
 #[derive(Debug, Default, Resource)]
 struct LoadingStateData {
-    name: String,
-    folder_handle: Handle<LoadedFolder>,
+    /// Names and folders of mods, in the load order
+    mods: Vec<(String, Handle<LoadedFolder>)>,
     not_ready_handles: Option<FxHashSet<UntypedAssetId>>,
 }
 
@@ -81,10 +78,13 @@ fn loading_initializer(
     let Some(evt) = evt.read().last() else {
         return;
     };
-    let mod_folder = asset_server.load_folder(&evt.0);
+    let mods = evt
+        .0
+        .iter()
+        .map(|name| (name.clone(), asset_server.load_folder(name)))
+        .collect();
     commands.insert_resource(LoadingStateData {
-        name: evt.0.clone(),
-        folder_handle: mod_folder,
+        mods,
         not_ready_handles: None,
     });
     next_state.set(ModState::Loading)
@@ -101,31 +101,42 @@ fn loader(
     mut switch_evt: EventWriter<ModLoadedEvent>,
     frame: Res<FrameCount>,
     mut state: ResMut<NextState<ModState>>,
+    cache: Option<Res<ModCache>>,
     mut wait_until: Local<Option<u32>>,
     mut first_load_flag: Local<bool>,
 ) {
-    match asset_server.load_state(&data.folder_handle) {
-        LoadState::NotLoaded => {
-            error!("Mod folder appears to be missing from asset server");
-            state.set(ModState::Pending);
-            err_evt.send(ModLoadErrorEvent);
-            return;
-        }
-        LoadState::Failed => {
-            error!("Failed to load mod files");
-            state.set(ModState::Pending);
-            err_evt.send(ModLoadErrorEvent);
-            return;
+    for (name, folder_handle) in &data.mods {
+        match asset_server.load_state(folder_handle) {
+            LoadState::NotLoaded => {
+                error!(name, "Mod folder appears to be missing from asset server");
+                state.set(ModState::Pending);
+                err_evt.send(ModLoadErrorEvent);
+                return;
+            }
+            LoadState::Failed => {
+                error!(name, "Failed to load mod files");
+                state.set(ModState::Pending);
+                err_evt.send(ModLoadErrorEvent);
+                return;
+            }
+            _ => {}
         }
-        _ => {}
     }
-    let Some(folder) = folder_assets.get(&data.folder_handle) else {
+    let Some(folders) = data
+        .mods
+        .iter()
+        .map(|(_, handle)| folder_assets.get(handle))
+        .collect::<Option<Vec<_>>>()
+    else {
         return;
     };
 
-    let handles = data
-        .not_ready_handles
-        .get_or_insert_with(|| folder.handles.iter().map(|e| e.id()).collect());
+    let handles = data.not_ready_handles.get_or_insert_with(|| {
+        folders
+            .iter()
+            .flat_map(|folder| folder.handles.iter().map(|e| e.id()))
+            .collect()
+    });
 
     let mut errors = Vec::new();
     handles.retain(|e| match asset_server.load_state(*e) {
@@ -163,71 +174,95 @@ fn loader(
     // Clear all pending asset events to avoid hot reloading all currently loaded files
     db_asset_events.clear();
 
-    let Some(path) = asset_server.get_path(&data.folder_handle) else {
-        error!("Mod folder is missing asset path");
-        state.set(ModState::Pending);
-        err_evt.send(ModLoadErrorEvent);
-        return;
-    };
-
     info!("Mod assets are loaded");
-    let mut db_files = Vec::new();
-    let mut db_images = Vec::new();
+    let mut mods = Vec::with_capacity(folders.len());
+    let mut layers = Vec::with_capacity(folders.len());
     let asset_type_id = TypeId::of::<DatabaseAsset>();
     let image_type_id = TypeId::of::<Image>();
-    for handle in &folder.handles {
-        match handle.type_id() {
-            id if id == asset_type_id => {
-                let Some(item) = database_items.get(handle) else {
-                    continue;
-                };
-                let Some(path) = asset_path(&asset_server, handle) else {
-                    continue;
-                };
+    for ((name, folder_handle), folder) in data.mods.iter().zip(folders) {
+        let Some(path) = asset_server.get_path(folder_handle) else {
+            error!(name, "Mod folder is missing asset path");
+            state.set(ModState::Pending);
+            err_evt.send(ModLoadErrorEvent);
+            return;
+        };
 
-                db_files.push((path, item));
-            }
-            id if id == image_type_id && images.contains(handle) => {
-                let Some(path) = asset_path(&asset_server, handle) else {
+        let mut layer = ModLayer {
+            name: name.clone(),
+            ..Default::default()
+        };
+        for handle in &folder.handles {
+            match handle.type_id() {
+                id if id == asset_type_id => {
+                    let Some(item) = database_items.get(handle) else {
+                        continue;
+                    };
+                    let Some(path) = asset_path(&asset_server, handle) else {
+                        continue;
+                    };
+
+                    layer.items.push((path, item));
+                }
+                id if id == image_type_id && images.contains(handle) => {
+                    let Some(path) = asset_path(&asset_server, handle) else {
+                        continue;
+                    };
+                    layer
+                        .images
+                        .push((path, handle.clone_weak().typed::<Image>()));
+                }
+                _ => {
                     continue;
-                };
-                db_images.push((path, handle.clone_weak().typed::<Image>()));
-            }
-            _ => {
-                continue;
+                }
             }
         }
+
+        mods.push(LoadedMod {
+            name: name.clone(),
+            mod_path: path.path().to_path_buf(),
+            folder_handle: folder_handle.clone(),
+        });
+        layers.push(layer);
     }
 
-    match construct_mod(
-        data.name.clone(),
-        path.path().to_path_buf(),
-        data.folder_handle.clone(),
-        db_files,
-        db_images,
-    ) {
+    // Mods are requested in any order, but have to be layered on top of
+    // their dependencies
+    let order = load_order(&layers);
+    let mut loaded: Vec<_> = mods.into_iter().zip(layers).map(Some).collect();
+    let (mods, layers): (Vec<_>, Vec<_>) =
+        order.into_iter().filter_map(|i| loaded[i].take()).unzip();
+
+    match construct_mod(mods, layers) {
         Ok(data) => {
+            // Every file went through the cache by now, so the entries left
+            // belong to the edited or removed files
+            if let Some(cache) = cache {
+                if let Err(err) = cache.0.prune() {
+                    warn!(?err, "Failed to prune the file cache");
+                }
+            }
             info!("Mod is constructed, sending events");
             state.set(ModState::Pending);
             switch_evt.send(ModLoadedEvent(data));
         }
         Err(err) => {
-            report_error(err.wrap("Failed to load a mod"));
+            report_error(err.wrap_err("Failed to load a mod"));
             state.set(ModState::Pending);
             err_evt.send(ModLoadErrorEvent);
         }
     }
 }
 
-pub fn available_mods<'a>(
-    folders: impl IntoIterator<Item = impl AsRef<&'a Path>>,
+/// Names of the mod folders inside the folders
+pub fn available_mods(
+    folders: impl IntoIterator<Item = impl AsRef<Path>>,
 ) -> impl Iterator<Item = String> {
     folders
         .into_iter()
         .filter_map(|e| std::fs::read_dir(e.as_ref()).ok())
         .flat_map(|e| {
             e.filter_map(|e| {
-                e.ok().and_then(|e| {
+                e.ok().filter(|e| e.path().is_dir()).and_then(|e| {
                     e.path()
                         .file_name()
                         .and_then(|e| e.to_str().map(|e| e.to_string()))
@@ -260,115 +295,200 @@ fn asset_tracer(
 
 fn hot_reload(
     mut evt: EventReader<AssetEvent<DatabaseAsset>>,
-    _hot_reload_event: EventWriter<InternalHotReloadEvent>,
-    _asset: Res<Assets<DatabaseAsset>>,
+    mut hot_reload_event: EventWriter<InternalHotReloadEvent>,
+    assets: Res<Assets<DatabaseAsset>>,
     asset_server: Res<AssetServer>,
-    loaded_mod: ResMut<ModData>,
+    mut loaded_mod: ResMut<ModData>,
     mut load_mod_evt: EventWriter<WantLoadModEvent>,
+    // Changed items accumulated while waiting for the file changes to settle
+    mut pending: Local<FxHashSet<AssetId<DatabaseAsset>>>,
     mut buffer_timer: Local<Option<Timer>>,
     time: Res<Time>,
     windows: Query<&Window>,
 ) {
-    enum Action {
-        Add,
-        Update,
-    }
     let mut want_reload = false;
     for evt in evt.read() {
-        let (asset_id, _action) = match evt {
-            AssetEvent::Added { id } => (id, Action::Add),
-            AssetEvent::Modified { id } => (id, Action::Update),
+        let asset_id = match evt {
+            AssetEvent::Added { id } => id,
+            AssetEvent::Modified { id } => id,
             AssetEvent::Removed { .. } => continue,
             AssetEvent::LoadedWithDependencies { .. } => continue,
         };
         let Some(path) = asset_server.get_path(*asset_id) else {
             continue;
         };
-        if !path.path().starts_with(&loaded_mod.mod_path) {
+        if !loaded_mod
+            .mods
+            .iter()
+            .any(|e| path.path().starts_with(&e.mod_path))
+        {
             continue;
         }
-        info!("Item reload is detected, queueing the hot reload.");
+        info!(path=%path, "Item reload is detected, queueing the hot reload.");
+        pending.insert(*asset_id);
         want_reload = true;
-        // let Ok(path) = Utf8PathBuf::from_path_buf(path.path().to_path_buf()) else {
-        //     error!(
-        //         ?path,
-        //         "Asset path contains non-UTF8 symbols, canceling hot-reloading"
-        //     );
-        //     continue;
-        // };
-        // let Some(asset) = asset.get(*asset_id) else {
-        //     error!(?path, "Failed to fetch updated asset");
-        //     continue;
-        // };
-        // let item = asset.database_item();
-        // let id = item.id().clone();
-        //
-        // match item
-        //     .deserialize(&mut loaded_mod.registry)
-        //     .with_context(|| format!("While hot reloading item {}", id))
-        // {
-        //     Err(err) => report_error(err),
-        //     Ok((new_id, old)) => {
-        //         match (
-        //             action,
-        //             loaded_mod.assets.get_by_left(&path),
-        //             loaded_mod.assets.get_by_right(&new_id).zip(old),
-        //         ) {
-        //             // New asset is added, but there is already an item with this ID
-        //             (Action::Add, _, Some((conflict, old))) => {
-        //                 error!(
-        //                     item_path = %path,
-        //                     conflicting_path = %conflict,
-        //                     id = id,
-        //                     "Duplicate item, hot reloading canceled"
-        //                 );
-        //                 loaded_mod.registry.insert(old);
-        //             }
-        //             // New asset is added, but it was already in a system previously?
-        //             // Weird situation, trigger full reload to be sure
-        //             (Action::Add, Some(_), _) => {
-        //                 todo!("Full DB reload");
-        //             }
-        //             // New asset is added, resulting in no collisions
-        //             (Action::Add, None, None) => {
-        //                 info!(id, %path, "Hot reloaded item (new)");
-        //                 // New item, assets update is required
-        //                 loaded_mod.assets.insert(path, new_id);
-        //                 hot_reload_event.send(InternalHotReloadEvent::Single(new_id));
-        //             }
-        //             // Asset is updated, keeping the same ID and only conflicting with itself
-        //             (Action::Update, Some(old_id), Some((conflict, _)))
-        //                 if old_id == &new_id && conflict == &path =>
-        //             {
-        //                 info!(id, %path, "Hot reloaded item (updated)");
-        //                 hot_reload_event.send(InternalHotReloadEvent::Single(new_id));
-        //             }
-        //             // Asset is updated, but ID got changed, trigger full reload
-        //             (Action::Update, Some(_), _) => {
-        //                 todo!("Full DB reload");
-        //             }
-        //             // Asset is updated, but no matching asset is already in a system?
-        //             // Weird situation, trigger full reload to be sure
-        //             (Action::Update, None, _) => {
-        //                 todo!("Full DB reload");
-        //             }
-        //         }
-        //     }
-        // }
     }
 
     if want_reload {
-        *buffer_timer = Some(Timer::from_seconds(1.0, TimerMode::Once))
-    } else if windows.iter().any(|e| e.focused) {
-        if let Some(timer) = buffer_timer.deref_mut() {
-            timer.tick(time.elapsed());
-            if timer.just_finished() {
-                info!("Initializing hot reload");
-                load_mod_evt.send(WantLoadModEvent(loaded_mod.name.clone()));
+        *buffer_timer = Some(Timer::from_seconds(1.0, TimerMode::Once));
+        return;
+    }
+
+    if !windows.iter().any(|e| e.focused) {
+        return;
+    }
+
+    let Some(timer) = buffer_timer.deref_mut() else {
+        return;
+    };
+
+    timer.tick(time.delta());
+    if !timer.finished() {
+        return;
+    }
+    *buffer_timer = None;
+
+    let mut full = false;
+    let mut layers: Vec<ModLayer> = Vec::new();
+    for asset_id in pending.drain() {
+        let Some(path) = asset_server.get_path(asset_id) else {
+            continue;
+        };
+        let path = path.path().to_path_buf();
+        let Some(asset) = assets.get(asset_id) else {
+            error!(?path, "Failed to fetch updated asset");
+            full = true;
+            continue;
+        };
+        let Some(mod_name) = in_place_reload_mod(&loaded_mod, &path, asset) else {
+            full = true;
+            continue;
+        };
+        match layers.iter_mut().find(|layer| layer.name == mod_name) {
+            Some(layer) => layer.items.push((path, asset)),
+            None => layers.push(ModLayer {
+                name: mod_name,
+                items: vec![(path, asset)],
+                ..Default::default()
+            }),
+        }
+    }
+
+    if full {
+        info!("Initializing full hot reload");
+        load_mod_evt.send(WantLoadModEvent(loaded_mod.load_order()));
+        return;
+    }
+
+    if layers.is_empty() {
+        return;
+    }
+
+    let loaded_mod = loaded_mod.deref_mut();
+    match loaded_mod.registry.hot_reload(layers) {
+        Ok(reloaded) => {
+            // Items referencing the reloaded ones observe the new data too, so
+            // anything derived from them has to be refreshed as well
+            let dependencies = loaded_mod.registry.dependencies();
+            let dependents: FxHashSet<RegistryId> = reloaded
+                .iter()
+                .flat_map(|(_, id)| dependencies.transitive_dependents(*id))
+                .filter(|id| !reloaded.iter().any(|(_, reloaded)| reloaded == id))
+                .collect();
+            for (path, id) in reloaded {
+                info!(?path, ?id, "Hot reloaded item");
+                loaded_mod.assets.insert(id, path);
+                hot_reload_event.send(InternalHotReloadEvent::Single(id));
+            }
+            for id in dependents {
+                debug!(?id, "Dependent of a hot reloaded item");
+                hot_reload_event.send(InternalHotReloadEvent::Single(id));
+            }
+        }
+        Err(err) => {
+            report_error(err.diagnostic().wrap("Failed to hot reload mod items"));
+        }
+    }
+}
+
+/// Checks whether items of the changed file can be reloaded in place,
+/// returning the name of the mod the file belongs to
+///
+/// Files that delete items, take part in inheritance, change the constants
+/// or the data version of the mod, or no longer declare some of their items
+/// require a full reload
+fn in_place_reload_mod(loaded_mod: &ModData, path: &Path, asset: &DatabaseAsset) -> Option<String> {
+    let Some(loaded) = loaded_mod
+        .mods
+        .iter()
+        .find(|e| path.starts_with(&e.mod_path))
+    else {
+        info!(?path, "File doesn't belong to any loaded mod");
+        return None;
+    };
+    let registry = &loaded_mod.registry;
+    // Errors are reported by the full reload
+    let version = registry.data_version(&loaded.name).unwrap_or_default();
+    // Files of the mod are migrated from its data version, so all of them
+    // have to be loaded again once it changes
+    let declared = mod_data_version([(path, &asset.0)]);
+    if !matches!(declared, Ok(declared) if declared.unwrap_or(version) == version) {
+        info!(?path, "Data version of the mod is changed");
+        return None;
+    }
+    let Ok((file, _)) = asset.load(version) else {
+        info!(?path, "File fails to load");
+        return None;
+    };
+    let old_ids: FxHashSet<RegistryId> = loaded_mod.file_items(path).collect();
+    let mut new_ids = FxHashSet::default();
+    let mut in_place = true;
+    for (_, item) in file.items() {
+        // Item IDs are relative to the mod declaring the item, so they have
+        // to be qualified before the item can be looked up
+        let Some(id) = registry.qualified_id(&loaded.name, item.kind(), item.id()) else {
+            info!(?path, id = item.id(), "Item ID is invalid");
+            in_place = false;
+            continue;
+        };
+        if item.is_delete() {
+            info!(?path, %id, "Item deletion is detected");
+            in_place = false;
+        }
+        if item.extends().is_some() || registry.has_inheritance(item.kind(), &id) {
+            // Changes need to be propagated through the whole inheritance
+            // chain, which requires all involved items to be present
+            info!(?path, %id, "Item takes part in inheritance");
+            in_place = false;
+        }
+        if registry.constants_changed(item) {
+            // Constants are compiled into the formulas of the whole mod
+            info!(?path, %id, "Mod constants are changed");
+            in_place = false;
+        }
+        match registry.registry_id(item.kind(), &id) {
+            // Item is updated, keeping the same ID
+            Some(new_id) if old_ids.contains(&new_id) => {
+                new_ids.insert(new_id);
+            }
+            // New item is added, but there is already an item with this ID
+            // in another file. Item might have been moved, so trigger full
+            // reload to be sure
+            Some(_) => {
+                info!(?path, %id, "New item collides with an existing one");
+                in_place = false;
             }
-            *buffer_timer = None;
+            // New item is added, resulting in no collisions
+            None => {}
         }
     }
+    // Old items can't be dropped without invalidating items referencing
+    // them, so IDs that are changed or gone trigger full reload
+    if old_ids.iter().any(|id| !new_ids.contains(id)) {
+        info!(?path, "Some items of the file got removed or changed IDs");
+        in_place = false;
+    }
+    in_place.then(|| loaded.name.clone())
 }
 
 macro_rules! typed_events {
@@ -429,49 +549,65 @@ pub enum InternalHotReloadEvent {
     Single(RegistryId),
 }
 
-fn construct_mod<'a, 'path>(
-    mod_name: String,
-    mod_path: PathBuf,
-    folder_handle: Handle<LoadedFolder>,
-    files: impl IntoIterator<Item = (impl AsRef<Path>, &'a DatabaseAsset)>,
-    images: impl IntoIterator<Item = (impl AsRef<Path>, Handle<Image>)>,
-) -> Result<ModData, impl Diagnostic + 'static> {
-    let registry = match ModRegistry::build(files, images) {
+fn construct_mod(mods: Vec<LoadedMod>, layers: Vec<ModLayer>) -> Result<ModData, Report> {
+    let sources = layers
+        .iter()
+        .map(|layer| (layer.name.clone(), layer.items.clone()))
+        .collect::<Vec<_>>();
+
+    let registry = match ModRegistry::build(layers) {
         Ok(data) => data,
         Err(err) => {
-            return Err(err.diagnostic());
+            return Err(Report::new(err.diagnostic()));
         }
     };
 
-    // let mut asset_paths: FxBiHashMap<Utf8PathBuf, RegistryId> = Default::default();
-    // for (path, asset) in files {
-    //     let item = asset.database_item();
-    //     let display_id = item.id().to_string();
-    //     let (id, old) = item.deserialize(&mut registry)?;
-    //     if old.is_some() {
-    //         let Some(old_path) = asset_paths.get_by_right(&id) else {
-    //             error!(path=path.to_string(),
-    //                 id=display_id,
-    //                 raw_id=?id,
-    //                 "Conflicting mod items detected, \
-    //                 but conflicting asset path was not found. What's going on?");
-    //             continue;
-    //         };
-    //         error!(
-    //             first_item = old_path.to_string(),
-    //             second_item = path.to_string(),
-    //             id=display_id,
-    //             raw_id=?id,
-    //             "Conflicting mod items detected"
-    //         )
-    //     }
-    //     asset_paths.insert(path, id);
-    // }
+    // Files are read again in the data versions of their mods, which are
+    // only known once the registry is built. Only paths of the items are
+    // needed, and every file is known to load by now
+    let mut files = Vec::new();
+    for (mod_name, items) in sources {
+        let version = registry.data_version(&mod_name).unwrap_or_default();
+        for (path, asset) in items {
+            let Ok((file, _)) = asset.load(version) else {
+                continue;
+            };
+            files.extend(
+                file.items()
+                    .filter(|(_, item)| !item.is_delete())
+                    .map(|(_, item)| {
+                        (
+                            path.clone(),
+                            mod_name.clone(),
+                            item.kind(),
+                            item.id().clone(),
+                        )
+                    }),
+            );
+        }
+    }
+
+    for lint in registry.lint(&LintSettings::default()) {
+        warn!("{lint}");
+    }
+
+    // Files are inserted in the load order, so overriding items replace
+    // the overridden ones
+    let mut asset_paths: FxHashMap<RegistryId, PathBuf> = Default::default();
+    for (path, mod_name, kind, id) in files {
+        // Item was deleted by one of the later mods
+        let Some(id) = registry
+            .qualified_id(&mod_name, kind, &id)
+            .and_then(|id| registry.registry_id(kind, &id))
+        else {
+            continue;
+        };
+        asset_paths.insert(id, path);
+    }
+
     Ok(ModData {
-        name: mod_name,
+        mods,
         registry,
-        mod_path,
-        folder_handle,
-        // assets: asset_paths,
+        assets: asset_paths,
     })
 }
@@ -0,0 +1,493 @@
+//! Language server for the mod database files, communicating over stdio
+//!
+//! Reports the loading errors as diagnostics, and provides completion,
+//! go to definition and find references for item IDs
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, References, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    CompletionTextEdit, Diagnostic, DiagnosticSeverity, GotoDefinitionParams,
+    GotoDefinitionResponse, InitializeParams, Location, OneOf, PublishDiagnosticsParams,
+    ReferenceParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextEdit, Url,
+};
+
+use database::model::{DatabaseItemKind, NAMESPACE_SEPARATOR};
+
+use crate::workspace::Workspace;
+
+mod text;
+mod workspace;
+
+/// Characters that end a variable name inside a formula
+const FORMULA_SEPARATORS: &[char] = &[
+    ' ', '+', '-', '*', '/', '^', '(', ')', ',', '{', '}', '"', '\'',
+];
+
+fn main() -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Initializes the server and handles the messages until the client shuts
+/// it down
+fn serve(connection: Connection) -> anyhow::Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["\"".to_string(), ":".to_string(), "{".to_string()]),
+            ..Default::default()
+        }),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+
+    #[allow(deprecated)]
+    let root = params
+        .workspace_folders
+        .and_then(|folders| folders.into_iter().next())
+        .map(|folder| folder.uri)
+        .or(params.root_uri)
+        .and_then(|uri| uri.to_file_path().ok());
+
+    let mut server = Server {
+        workspace: root.as_deref().map(Workspace::load).unwrap_or_default(),
+        connection,
+        published: Default::default(),
+    };
+    server.publish_diagnostics()?;
+    server.run()
+}
+
+struct Server {
+    connection: Connection,
+    workspace: Workspace,
+    /// Files that currently have diagnostics shown
+    published: BTreeSet<PathBuf>,
+}
+
+impl Server {
+    fn run(&mut self) -> anyhow::Result<()> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => {
+                    if self.handle_notification(notification)? {
+                        self.publish_diagnostics()?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            Completion::METHOD => {
+                parse(request, |params: CompletionParams| self.completion(params))
+            }
+            GotoDefinition::METHOD => parse(request, |params: GotoDefinitionParams| {
+                self.definition(params)
+            }),
+            References::METHOD => parse(request, |params: ReferenceParams| self.references(params)),
+            _ => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("Unsupported request `{}`", request.method),
+                )
+            }
+        };
+        match result {
+            Ok((id, result)) => Response::new_ok(id, result),
+            Err(err) => Response::new_err(id, ErrorCode::InvalidParams as i32, err.to_string()),
+        }
+    }
+
+    /// Returns whether the documents have changed
+    fn handle_notification(&mut self, notification: Notification) -> anyhow::Result<bool> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let Some(path) = file_path(&params.text_document.uri) {
+                    self.workspace.update(path, params.text_document.text);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // Documents are synced in full, so the last change has the
+                // whole text
+                let (Some(path), Some(change)) = (
+                    file_path(&params.text_document.uri),
+                    params.content_changes.into_iter().last(),
+                ) else {
+                    return Ok(false);
+                };
+                self.workspace.update(path, change.text);
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let Some(path) = file_path(&params.text_document.uri) {
+                    self.workspace.reload(&path);
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn publish_diagnostics(&mut self) -> anyhow::Result<()> {
+        let diagnostics = self.workspace.diagnostics();
+        let mut published = BTreeSet::new();
+        for (path, errors) in &diagnostics {
+            // Clean files only need to be published once to clear the
+            // diagnostics shown before
+            if errors.is_empty() && !self.published.contains(*path) {
+                continue;
+            }
+            let (Ok(uri), Some(document)) =
+                (Url::from_file_path(path), self.workspace.document(path))
+            else {
+                continue;
+            };
+            let diagnostics = errors
+                .iter()
+                .map(|(span, message)| Diagnostic {
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    ..Diagnostic::new_simple(text::range(&document.text, span), message.clone())
+                })
+                .collect();
+            let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+            self.connection
+                .sender
+                .send(Message::Notification(Notification::new(
+                    PublishDiagnostics::METHOD.to_string(),
+                    params,
+                )))?;
+            if !errors.is_empty() {
+                published.insert(path.to_path_buf());
+            }
+        }
+        self.published = published;
+        Ok(())
+    }
+
+    /// Completes item IDs of the referenced kind, and variable IDs inside
+    /// formulas
+    ///
+    /// Items of the document's own mod are completed with the relative IDs.
+    /// Only works in documents that deserialize, so the expected kind of
+    /// the value is known
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let path = file_path(&position.text_document.uri)?;
+        let document = self.workspace.document(&path)?;
+        let offset = text::offset(&document.text, position.position);
+        let namespace = self.workspace.namespace(&path);
+
+        if let Some(formula) = self.workspace.formula_at(&path, offset) {
+            let start = document.text[formula.start + 1..offset]
+                .rfind(FORMULA_SEPARATORS)
+                .map_or(formula.start + 1, |i| formula.start + 1 + i + 1);
+            let braced = document.text[..start].ends_with('{');
+            let range = text::range(&document.text, &(start..offset));
+            let items = self
+                .workspace
+                .declarations(DatabaseItemKind::Variable)
+                .into_iter()
+                .map(|id| {
+                    let id = relative(&id, namespace.as_deref());
+                    // Names with other characters have to be put in braces
+                    // to be parsed as a single variable
+                    let plain = id.chars().all(|c| c.is_alphanumeric() || c == '_');
+                    let text = if braced || plain {
+                        id.to_string()
+                    } else {
+                        format!("{{{id}}}")
+                    };
+                    completion_item(id, DatabaseItemKind::Variable, TextEdit::new(range, text))
+                })
+                .collect();
+            return Some(CompletionResponse::Array(items));
+        }
+
+        let at = self.workspace.id_at(&path, offset)?;
+        if at.declaration {
+            return None;
+        }
+        // Whole string content is replaced, leaving the quotes in place
+        let range = text::range(&document.text, &(at.span.start + 1..at.span.end - 1));
+        let items = self
+            .workspace
+            .declarations(at.kind)
+            .into_iter()
+            .map(|id| {
+                let id = relative(&id, namespace.as_deref());
+                completion_item(id, at.kind, TextEdit::new(range, id.to_string()))
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let path = file_path(&position.text_document.uri)?;
+        let document = self.workspace.document(&path)?;
+        let at = self
+            .workspace
+            .id_at(&path, text::offset(&document.text, position.position))?;
+        let locations = self
+            .workspace
+            .declaration(at.kind, &at.id)
+            .into_iter()
+            .filter_map(|(path, span)| self.location(path, &span))
+            .collect();
+        Some(GotoDefinitionResponse::Array(locations))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = params.text_document_position;
+        let path = file_path(&position.text_document.uri)?;
+        let document = self.workspace.document(&path)?;
+        let at = self
+            .workspace
+            .id_at(&path, text::offset(&document.text, position.position))?;
+        let mut locations = Vec::new();
+        if params.context.include_declaration {
+            locations.extend(self.workspace.declaration(at.kind, &at.id));
+        }
+        locations.extend(self.workspace.references(at.kind, &at.id));
+        Some(
+            locations
+                .into_iter()
+                .filter_map(|(path, span)| self.location(path, &span))
+                .collect(),
+        )
+    }
+
+    fn location(&self, path: &Path, span: &std::ops::Range<usize>) -> Option<Location> {
+        let document = self.workspace.document(path)?;
+        Some(Location::new(
+            Url::from_file_path(path).ok()?,
+            text::range(&document.text, span),
+        ))
+    }
+}
+
+fn parse<P: serde::de::DeserializeOwned, R: serde::Serialize>(
+    request: Request,
+    handler: impl FnOnce(P) -> R,
+) -> Result<(RequestId, serde_json::Value), serde_json::Error> {
+    let params = serde_json::from_value(request.params)?;
+    Ok((request.id, serde_json::to_value(handler(params))?))
+}
+
+fn file_path(uri: &Url) -> Option<PathBuf> {
+    uri.to_file_path().ok()
+}
+
+/// Shortest form of the qualified ID referring to the item from the mod with
+/// the namespace
+fn relative<'a>(id: &'a str, namespace: Option<&str>) -> &'a str {
+    namespace
+        .and_then(|namespace| {
+            id.strip_prefix(namespace)?
+                .strip_prefix(NAMESPACE_SEPARATOR)
+        })
+        .unwrap_or(id)
+}
+
+fn completion_item(id: &str, kind: DatabaseItemKind, edit: TextEdit) -> CompletionItem {
+    CompletionItem {
+        label: id.to_string(),
+        kind: Some(CompletionItemKind::REFERENCE),
+        detail: Some(kind.to_string()),
+        text_edit: Some(CompletionTextEdit::Edit(edit)),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use lsp_server::{Connection, Message, Notification, Request, Response};
+    use lsp_types::notification::{
+        DidOpenTextDocument, Exit, Initialized, Notification as _, PublishDiagnostics,
+    };
+    use lsp_types::request::{Completion, GotoDefinition, Initialize, Shutdown};
+    use lsp_types::{
+        CompletionParams, CompletionResponse, DidOpenTextDocumentParams, GotoDefinitionParams,
+        GotoDefinitionResponse, InitializeParams, InitializedParams, Location, Position,
+        PublishDiagnosticsParams, Range, TextDocumentIdentifier, TextDocumentItem,
+        TextDocumentPositionParams, Url, WorkspaceFolder,
+    };
+
+    use database::fixture::{Fixture, SETTINGS};
+
+    use super::serve;
+
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Client end of the in-memory connection
+    struct Client {
+        connection: Connection,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> R::Result {
+            self.next_id += 1;
+            let request = Request::new(self.next_id.into(), R::METHOD.to_string(), params);
+            self.connection.sender.send(request.into()).unwrap();
+            match self.receive() {
+                Message::Response(Response {
+                    result: Some(result),
+                    error: None,
+                    ..
+                }) => serde_json::from_value(result).unwrap(),
+                message => panic!("Expected a response to `{}`, got {message:?}", R::METHOD),
+            }
+        }
+
+        fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+            let notification = Notification::new(N::METHOD.to_string(), params);
+            self.connection.sender.send(notification.into()).unwrap();
+        }
+
+        fn receive(&self) -> Message {
+            self.connection
+                .receiver
+                .recv_timeout(TIMEOUT)
+                .expect("Server should send a message")
+        }
+
+        fn diagnostics(&self) -> PublishDiagnosticsParams {
+            match self.receive() {
+                Message::Notification(notification)
+                    if notification.method == PublishDiagnostics::METHOD =>
+                {
+                    serde_json::from_value(notification.params).unwrap()
+                }
+                message => panic!("Expected diagnostics, got {message:?}"),
+            }
+        }
+    }
+
+    fn position(path: &Path, line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(
+            TextDocumentIdentifier::new(Url::from_file_path(path).unwrap()),
+            Position::new(line, character),
+        )
+    }
+
+    fn write_workspace() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("mod_lsp_test_{}", std::process::id()));
+        for (path, text) in Fixture::default().files() {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn server_reports_errors_and_resolves_ids_of_the_workspace() {
+        let root = write_workspace();
+        let settings = root.join("eh").join("settings.json");
+        let fleet = root.join("eh").join("fleet.json");
+
+        let (connection, client) = Connection::memory();
+        let server = std::thread::spawn(move || serve(connection));
+        let mut client = Client {
+            connection: client,
+            next_id: 0,
+        };
+
+        client.request::<Initialize>(InitializeParams {
+            workspace_folders: Some(vec![WorkspaceFolder {
+                uri: Url::from_file_path(&root).unwrap(),
+                name: "mods".to_string(),
+            }]),
+            ..Default::default()
+        });
+        client.notify::<Initialized>(InitializedParams {});
+
+        // Valid workspace has nothing to report, so the first diagnostics
+        // come from the opened document
+        let broken = SETTINGS.replacen("eh:fleet", "eh:missing", 1);
+        client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                Url::from_file_path(&settings).unwrap(),
+                "json".to_string(),
+                1,
+                broken,
+            ),
+        });
+        let diagnostics = client.diagnostics();
+        assert_eq!(diagnostics.uri, Url::from_file_path(&settings).unwrap());
+        assert_eq!(diagnostics.diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(
+            diagnostics.diagnostics[0].range,
+            Range::new(Position::new(3, 17), Position::new(3, 29))
+        );
+
+        let completion = client.request::<Completion>(CompletionParams {
+            text_document_position: position(&settings, 3, 20),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        });
+        let Some(CompletionResponse::Array(items)) = completion else {
+            panic!("Expected completion items, got {completion:?}");
+        };
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["fleet"]);
+
+        let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: position(&settings, 4, 20),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let Some(GotoDefinitionResponse::Array(locations)) = definition else {
+            panic!("Expected definition locations, got {definition:?}");
+        };
+        assert_eq!(
+            locations,
+            [Location::new(
+                Url::from_file_path(&fleet).unwrap(),
+                Range::new(Position::new(2, 8), Position::new(2, 18))
+            )]
+        );
+
+        client.request::<Shutdown>(());
+        client.notify::<Exit>(());
+        server.join().unwrap().unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
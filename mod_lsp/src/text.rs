@@ -0,0 +1,94 @@
+use std::ops::Range;
+
+use lsp_types::Position;
+
+/// Converts a byte offset into a position, counting characters in UTF-16
+/// code units as the protocol expects by default
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character = before[line_start..].encode_utf16().count();
+    Position::new(line as u32, character as u32)
+}
+
+pub fn range(text: &str, span: &Range<usize>) -> lsp_types::Range {
+    lsp_types::Range::new(position(text, span.start), position(text, span.end))
+}
+
+/// Converts a position into a byte offset, clamping it to the text
+pub fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Position;
+
+    use super::{offset, position};
+
+    const TEXT: &str = "id: \"ship\"\nname: \"Ястреб 🦅\"\r\nend";
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        assert_eq!(position(TEXT, 0), Position::new(0, 0));
+        assert_eq!(position(TEXT, 4), Position::new(0, 4));
+        // Cyrillic letters take two bytes but a single code unit
+        let name = TEXT.find('Я').unwrap();
+        assert_eq!(position(TEXT, name), Position::new(1, 7));
+        assert_eq!(position(TEXT, name + 'Я'.len_utf8()), Position::new(1, 8));
+        // Characters outside of the BMP take a surrogate pair
+        let emoji = TEXT.find('🦅').unwrap();
+        assert_eq!(position(TEXT, emoji), Position::new(1, 14));
+        assert_eq!(
+            position(TEXT, emoji + '🦅'.len_utf8()),
+            Position::new(1, 16)
+        );
+        assert_eq!(position(TEXT, TEXT.len()), Position::new(2, 3));
+    }
+
+    #[test]
+    fn offsets_count_utf16_code_units() {
+        assert_eq!(offset(TEXT, Position::new(0, 4)), 4);
+        let name = TEXT.find('Я').unwrap();
+        assert_eq!(offset(TEXT, Position::new(1, 7)), name);
+        assert_eq!(offset(TEXT, Position::new(1, 8)), name + 'Я'.len_utf8());
+        let emoji = TEXT.find('🦅').unwrap();
+        assert_eq!(offset(TEXT, Position::new(1, 14)), emoji);
+        assert_eq!(offset(TEXT, Position::new(1, 16)), emoji + '🦅'.len_utf8());
+    }
+
+    #[test]
+    fn offsets_are_clamped_to_the_text() {
+        // Past the end of the line, before the line break
+        assert_eq!(
+            offset(TEXT, Position::new(0, 100)),
+            TEXT.find('\n').unwrap()
+        );
+        // Past the last line
+        assert_eq!(offset(TEXT, Position::new(5, 0)), TEXT.len());
+        assert_eq!(position(TEXT, TEXT.len() + 10), Position::new(2, 3));
+    }
+
+    #[test]
+    fn positions_and_offsets_round_trip() {
+        for (i, _) in TEXT.char_indices() {
+            assert_eq!(offset(TEXT, position(TEXT, i)), i, "offset {i}");
+        }
+    }
+}
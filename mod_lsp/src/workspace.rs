@@ -0,0 +1,501 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bevy::asset::Handle;
+use bevy::render::texture::Image;
+
+use database::model::migration::mod_data_version;
+use database::model::{
+    qualify_id, DataFormat, DataVersion, DatabaseAsset, DatabaseFile, DatabaseItemKind,
+    DatabaseItemSerialized, DatabaseItemSerializedTrait, DeserializationErrorStackItem,
+    ErrorLocation, FormatError, ItemId, ItemReferences, ModLayer, ModRegistry,
+};
+
+/// Extensions of the files loaded as images by the game
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+#[derive(Debug)]
+pub struct Document {
+    pub text: Arc<str>,
+    pub format: DataFormat,
+    pub asset: Result<DatabaseAsset, FormatError>,
+    /// Items of the document upgraded from the data version of its mod,
+    /// unless they fail to load
+    pub file: Option<DatabaseFile>,
+    pub references: ItemReferences,
+}
+
+impl Document {
+    /// Parses the document, leaving the items unloaded until the data
+    /// version of the mod is known
+    pub fn new(text: String, format: DataFormat) -> Self {
+        let text: Arc<str> = text.into();
+        let asset = DatabaseAsset::parse(&text, format);
+        Self {
+            text,
+            format,
+            asset,
+            file: None,
+            references: Default::default(),
+        }
+    }
+
+    /// Loads the items written in the data version
+    fn load(&mut self, version: DataVersion) {
+        self.file = self
+            .asset
+            .as_ref()
+            .ok()
+            .and_then(|asset| asset.load(version).ok())
+            .map(|(file, _)| file);
+        self.references = self
+            .file
+            .as_ref()
+            .map(DatabaseFile::references)
+            .unwrap_or_default();
+    }
+
+    /// Whether the document holds the manifest of a mod
+    fn is_manifest(&self, path: &Path) -> bool {
+        self.asset.as_ref().is_ok_and(|asset| {
+            mod_data_version([(path, &asset.0)]).map_or(true, |version| version.is_some())
+        })
+    }
+
+    /// Items of the document along with their positions inside it
+    pub fn items(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            Option<DeserializationErrorStackItem>,
+            &DatabaseItemSerialized,
+        ),
+    > + '_ {
+        self.file.iter().flat_map(DatabaseFile::items)
+    }
+
+    /// Span of the value at the path inside the document
+    ///
+    /// Returns `None` if the exact value can't be found, rather than the
+    /// enclosing value the error location falls back to
+    fn locate(&self, path: &Path, stack: &[DeserializationErrorStackItem]) -> Option<Range<usize>> {
+        if !self.format.supports_locations() {
+            return None;
+        }
+        let span = ErrorLocation::new(path.to_path_buf(), self.text.clone(), stack).span;
+        let exact = match stack.first() {
+            Some(
+                DeserializationErrorStackItem::ExprVariable(name)
+                | DeserializationErrorStackItem::ExprFunction(name),
+            ) => &self.text[span.clone()] == name,
+            // Missing values fall back to the enclosing one, which is then
+            // located for the shorter path as well
+            Some(_) => {
+                ErrorLocation::new(path.to_path_buf(), self.text.clone(), &stack[1..]).span != span
+            }
+            None => true,
+        };
+        exact.then_some(span)
+    }
+
+    /// Span of the item's own ID
+    ///
+    /// Items of the maps may omit the ID, in which case the span of the key
+    /// is returned
+    fn id_span(
+        &self,
+        path: &Path,
+        position: Option<&DeserializationErrorStackItem>,
+    ) -> Option<Range<usize>> {
+        let mut stack = vec![DeserializationErrorStackItem::Field("id")];
+        stack.extend(position.cloned());
+        self.locate(path, &stack).or_else(|| {
+            let DeserializationErrorStackItem::MapEntry(key) = position? else {
+                return None;
+            };
+            let item = ErrorLocation::new(path.to_path_buf(), self.text.clone(), &stack[1..]).span;
+            let start = self.text[..item.start].rfind(key.as_str())?;
+            Some(start..start + key.len())
+        })
+    }
+}
+
+/// ID under the cursor
+#[derive(Debug)]
+pub struct IdAt {
+    pub kind: DatabaseItemKind,
+    /// Qualified ID, unless it's invalid or written outside of any mod
+    pub id: ItemId,
+    pub span: Range<usize>,
+    /// Whether this is the ID of the item itself rather than a reference
+    pub declaration: bool,
+}
+
+/// Database files of the workspace, with the open ones taken from the editor
+#[derive(Debug, Default)]
+pub struct Workspace {
+    documents: BTreeMap<PathBuf, Document>,
+    images: BTreeSet<PathBuf>,
+}
+
+impl Workspace {
+    pub fn load(root: &Path) -> Self {
+        let mut workspace = Workspace::default();
+        let mut paths = Vec::new();
+        collect_files(root, &mut paths);
+        for path in paths {
+            match extension(&path) {
+                Some(ext) if IMAGE_EXTENSIONS.contains(&ext.as_str()) => {
+                    workspace.images.insert(path);
+                }
+                Some(ext) if DataFormat::from_extension(&ext).is_some() => workspace.reload(&path),
+                _ => {}
+            }
+        }
+        workspace
+    }
+
+    pub fn document(&self, path: &Path) -> Option<&Document> {
+        self.documents.get(path)
+    }
+
+    /// Replaces the document with the text from the editor
+    ///
+    /// Files with unknown extensions are treated as JSON5. Changes to the
+    /// manifest reload the whole mod, since the manifest declares the data
+    /// version of all of its files
+    pub fn update(&mut self, path: PathBuf, text: String) {
+        let format = DataFormat::from_path(&path).unwrap_or(DataFormat::Json5);
+        let document = Document::new(text, format);
+        let mut manifest = document.is_manifest(&path);
+        if let Some(old) = self.documents.insert(path.clone(), document) {
+            manifest |= old.is_manifest(&path);
+        }
+
+        let root = match path.parent() {
+            Some(root) if manifest => root.to_path_buf(),
+            _ => path.clone(),
+        };
+        let versions: Vec<(PathBuf, DataVersion)> = self
+            .documents
+            .keys()
+            .filter(|other| other.starts_with(&root))
+            .map(|other| (other.clone(), self.data_version(other)))
+            .collect();
+        for (path, version) in versions {
+            if let Some(document) = self.documents.get_mut(&path) {
+                document.load(version);
+            }
+        }
+    }
+
+    /// Data version of the mod the file belongs to, as declared by the
+    /// manifest in the closest enclosing folder
+    fn data_version(&self, path: &Path) -> DataVersion {
+        self.documents
+            .iter()
+            .filter(|(manifest, _)| manifest.parent().is_some_and(|root| path.starts_with(root)))
+            .filter_map(|(manifest, document)| {
+                let asset = document.asset.as_ref().ok()?;
+                // Broken versions are reported by the build
+                let version = mod_data_version([(manifest.as_path(), &asset.0)]).ok()??;
+                Some((manifest, version))
+            })
+            .max_by_key(|(manifest, _)| manifest.components().count())
+            .map_or(0, |(_, version)| version)
+    }
+
+    /// Reads the document from the disk again, dropping it if the file is
+    /// gone
+    pub fn reload(&mut self, path: &Path) {
+        match std::fs::read_to_string(path) {
+            Ok(text) => self.update(path.to_path_buf(), text),
+            Err(_) => {
+                self.documents.remove(path);
+            }
+        }
+    }
+
+    /// Namespace of the mod the file belongs to
+    pub fn namespace(&self, path: &Path) -> Option<String> {
+        mod_of(&self.mods(), path).map(|(_, id)| id.clone())
+    }
+
+    /// Qualified IDs of all declared items of the kind
+    pub fn declarations(&self, kind: DatabaseItemKind) -> BTreeSet<ItemId> {
+        let mods = self.mods();
+        self.documents
+            .iter()
+            .flat_map(|(path, document)| {
+                let mods = &mods;
+                document
+                    .items()
+                    .filter(move |(_, item)| !item.is_delete() && item.kind() == kind)
+                    .filter_map(move |(_, item)| qualify(mods, path, kind, item.id()))
+            })
+            .collect()
+    }
+
+    /// Locations of the declarations of the item with the qualified ID
+    pub fn declaration(&self, kind: DatabaseItemKind, id: &str) -> Vec<(&Path, Range<usize>)> {
+        let mods = self.mods();
+        self.documents
+            .iter()
+            .flat_map(|(path, document)| {
+                let mods = &mods;
+                document
+                    .items()
+                    .filter(move |(_, item)| {
+                        !item.is_delete()
+                            && item.kind() == kind
+                            && qualify(mods, path, kind, item.id()).as_deref() == Some(id)
+                    })
+                    .filter_map(move |(position, _)| {
+                        Some((path.as_path(), document.id_span(path, position.as_ref())?))
+                    })
+            })
+            .collect()
+    }
+
+    /// Locations of all references to the item with the qualified ID
+    pub fn references(&self, kind: DatabaseItemKind, id: &str) -> Vec<(&Path, Range<usize>)> {
+        let mods = self.mods();
+        self.documents
+            .iter()
+            .flat_map(|(path, document)| {
+                let mods = &mods;
+                document
+                    .references
+                    .items
+                    .iter()
+                    .filter(move |reference| {
+                        reference.kind == kind
+                            && qualify(mods, path, kind, &reference.id).as_deref() == Some(id)
+                    })
+                    .filter_map(move |reference| {
+                        Some((path.as_path(), document.locate(path, &reference.stack)?))
+                    })
+            })
+            .collect()
+    }
+
+    /// Item ID under the cursor, either referenced or declared
+    pub fn id_at(&self, path: &Path, offset: usize) -> Option<IdAt> {
+        let document = self.documents.get(path)?;
+        let mods = self.mods();
+        let qualified = |kind: DatabaseItemKind, id: &ItemId| {
+            qualify(&mods, path, kind, id).unwrap_or_else(|| id.clone())
+        };
+        let contains = |span: &Range<usize>| span.start <= offset && offset <= span.end;
+        for (position, item) in document.items() {
+            if let Some(span) = document.id_span(path, position.as_ref()).filter(contains) {
+                return Some(IdAt {
+                    kind: item.kind(),
+                    id: qualified(item.kind(), item.id()),
+                    span,
+                    declaration: !item.is_delete(),
+                });
+            }
+        }
+        document.references.items.iter().find_map(|reference| {
+            let span = document.locate(path, &reference.stack)?;
+            contains(&span).then(|| IdAt {
+                kind: reference.kind,
+                id: qualified(reference.kind, &reference.id),
+                span,
+                declaration: false,
+            })
+        })
+    }
+
+    /// Span of the formula string under the cursor
+    pub fn formula_at(&self, path: &Path, offset: usize) -> Option<Range<usize>> {
+        let document = self.documents.get(path)?;
+        document.references.formulas.iter().find_map(|stack| {
+            let span = document.locate(path, stack)?;
+            (span.start < offset && offset < span.end).then_some(span)
+        })
+    }
+
+    /// Errors of every file, in the same form the game reports them when
+    /// loading the mods
+    ///
+    /// Files without errors are included with no diagnostics, so the
+    /// previously published ones get cleared
+    pub fn diagnostics(&self) -> BTreeMap<&Path, Vec<(Range<usize>, String)>> {
+        let mut diagnostics: BTreeMap<&Path, Vec<_>> = self
+            .documents
+            .keys()
+            .map(|path| (path.as_path(), Vec::new()))
+            .collect();
+
+        let mut parsed = true;
+        for (path, document) in &self.documents {
+            if let Err(err) = &document.asset {
+                let offset = err.offset(&document.text).unwrap_or(0);
+                diagnostics
+                    .entry(path)
+                    .or_default()
+                    .push((offset..offset, err.message()));
+                parsed = false;
+            }
+        }
+        // Items referencing the unparsed files would only produce
+        // misleading errors
+        if !parsed {
+            return diagnostics;
+        }
+
+        let Err(err) = self.build() else {
+            return diagnostics;
+        };
+        let manifests: Vec<&Path> = self
+            .documents
+            .iter()
+            .filter(|(_, document)| document.items().any(|(_, item)| item.is_mod_settings()))
+            .map(|(path, _)| path.as_path())
+            .collect();
+        for err in err.flatten() {
+            let message = std::iter::once(err.kind.to_string())
+                .chain(
+                    err.stack
+                        .iter()
+                        .filter(|item| !matches!(item, DeserializationErrorStackItem::File(_)))
+                        .map(|item| item.to_string()),
+                )
+                .collect::<Vec<_>>()
+                .join("\n");
+            let span = err
+                .location
+                .as_ref()
+                .map_or(0..0, |location| location.span.clone());
+            match err
+                .file()
+                .and_then(|file| self.documents.get_key_value(file))
+            {
+                Some((path, _)) => diagnostics.entry(path).or_default().push((span, message)),
+                // Errors outside of item files concern the mods as a whole
+                None => {
+                    for path in &manifests {
+                        diagnostics
+                            .entry(path)
+                            .or_default()
+                            .push((0..0, message.clone()));
+                    }
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// Builds the registry out of the mods found in the workspace
+    fn build(&self) -> Result<ModRegistry, database::model::DeserializationError> {
+        let mods = self.mods();
+        let mod_of = |path: &Path| mod_of(&mods, path).map(|(root, _)| root.as_path());
+
+        // Images are only referred to by name, so the handles don't need to
+        // point to the actual assets, as long as they are unique
+        let mut image_count = 0u128;
+        let layers = mods.iter().map(|(root, name)| ModLayer {
+            name: name.clone(),
+            items: self
+                .documents
+                .iter()
+                .filter(|(path, _)| mod_of(path.as_path()) == Some(root.as_path()))
+                .filter_map(|(path, document)| Some((path.clone(), document.asset.as_ref().ok()?)))
+                .collect(),
+            images: self
+                .images
+                .iter()
+                .filter(|path| mod_of(path.as_path()) == Some(root.as_path()))
+                .map(|path| {
+                    image_count += 1;
+                    (path.clone(), Handle::<Image>::weak_from_u128(image_count))
+                })
+                .collect(),
+        });
+        ModRegistry::build(layers)
+    }
+
+    /// Folders of the mods along with their IDs, ordered so that every mod
+    /// comes after its dependencies
+    ///
+    /// Mod folder is the one containing its manifest
+    fn mods(&self) -> Vec<(PathBuf, String)> {
+        let mut pending: Vec<(PathBuf, &ItemId, Vec<&ItemId>)> = self
+            .documents
+            .iter()
+            .flat_map(|(path, document)| {
+                document.items().filter_map(move |(_, item)| {
+                    let DatabaseItemSerialized::ModSettings(settings) = item else {
+                        return None;
+                    };
+                    let dependencies = settings
+                        .data
+                        .complete()
+                        .map(|data| data.dependencies().collect())
+                        .unwrap_or_default();
+                    Some((path.parent()?.to_path_buf(), &settings.id, dependencies))
+                })
+            })
+            .collect();
+        pending.sort_by(|a, b| a.1.cmp(b.1));
+
+        let mut ordered: Vec<(PathBuf, String)> = Vec::with_capacity(pending.len());
+        while !pending.is_empty() {
+            let ready = pending
+                .iter()
+                .position(|(_, _, dependencies)| {
+                    dependencies.iter().all(|dependency| {
+                        ordered.iter().any(|(_, id)| id == *dependency)
+                            || !pending.iter().any(|(_, id, _)| id == dependency)
+                    })
+                })
+                // Dependency cycles are left for the loader to report
+                .unwrap_or(0);
+            let (root, id, _) = pending.remove(ready);
+            ordered.push((root, id.clone()));
+        }
+        ordered
+    }
+}
+
+/// Mod with the innermost folder containing the file
+fn mod_of<'a>(mods: &'a [(PathBuf, String)], path: &Path) -> Option<&'a (PathBuf, String)> {
+    mods.iter()
+        .filter(|(root, _)| path.starts_with(root))
+        .max_by_key(|(root, _)| root.components().count())
+}
+
+/// Resolves the ID written in the file against the namespace of its mod
+fn qualify(
+    mods: &[(PathBuf, String)],
+    path: &Path,
+    kind: DatabaseItemKind,
+    id: &str,
+) -> Option<ItemId> {
+    let namespace = mod_of(mods, path).map(|(_, id)| id.as_str());
+    qualify_id(id, kind, namespace).ok().map(ItemId::from)
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, out);
+        } else {
+            out.push(path);
+        }
+    }
+}
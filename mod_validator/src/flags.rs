@@ -0,0 +1,30 @@
+#![allow(unreachable_pub)]
+
+use std::path::PathBuf;
+
+use database::model::LintSeverity;
+
+xflags::xflags! {
+    /// Builds the database of the mods straight from the disk, reporting
+    /// all errors without launching the game
+    cmd mod-validator {
+        /// Mod folders, in the load order
+        repeated mods: PathBuf
+        /// Writes JSON schemas of the items into the directory
+        optional --schemas dir: PathBuf
+        /// Severity of items not used by any fleet, combat settings or mod
+        /// manifest: `allow`, `warn` or `deny`
+        optional --unused-items severity: LintSeverity
+        /// Severity of images not used by any ship: `allow`, `warn` or `deny`
+        optional --unused-images severity: LintSeverity
+        /// Severity of fields that no item declares, such as misspelled
+        /// ones: `allow`, `warn` or `deny`
+        optional --unknown-fields severity: LintSeverity
+        /// Fails on unknown fields, overriding `--unknown-fields`
+        optional --strict
+        /// Keeps the parsed files in the directory, skipping the parsing of
+        /// the unchanged files on the next runs. Files not read by the run
+        /// are dropped from the cache
+        optional --cache dir: PathBuf
+    }
+}
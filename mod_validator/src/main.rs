@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use bevy::asset::Handle;
+use bevy::render::texture::Image;
+use miette::{Diagnostic, Report};
+use thiserror::Error;
+
+use database::model::{
+    export_schemas, DataFormat, DatabaseAsset, FileCache, FormatError, LintSettings, LintSeverity,
+    ModLayer, ModRegistry,
+};
+
+mod flags;
+
+/// Extensions of the files loaded as images by the game
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+#[derive(Debug, Error, Diagnostic)]
+enum FileError {
+    #[error("Failed to read `{}`", .0.to_string_lossy())]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Failed to parse `{}`", .0.to_string_lossy())]
+    Parse(PathBuf, #[source] FormatError),
+}
+
+#[derive(Debug, Default)]
+struct ModFiles {
+    name: String,
+    items: Vec<(PathBuf, DatabaseAsset)>,
+    images: Vec<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let flags = flags::ModValidator::from_env_or_exit();
+    if let Some(dir) = &flags.schemas {
+        match export_schemas(dir) {
+            Ok(paths) => println!(
+                "Wrote {} schemas to `{}`",
+                paths.len(),
+                dir.to_string_lossy()
+            ),
+            Err(err) => {
+                eprintln!("{:?}", Report::new(err));
+                return ExitCode::FAILURE;
+            }
+        }
+        if flags.mods.is_empty() {
+            return ExitCode::SUCCESS;
+        }
+    }
+    if flags.mods.is_empty() {
+        eprintln!("No mod folders were given");
+        return ExitCode::FAILURE;
+    }
+
+    let cache = flags.cache.as_ref().map(FileCache::new);
+    let mut errors = Vec::new();
+    let mods: Vec<_> = flags
+        .mods
+        .iter()
+        .map(|path| read_mod(path, cache.as_ref(), &mut errors))
+        .collect();
+    if let Some(cache) = &cache {
+        if let Err(err) = cache.prune() {
+            eprintln!("Failed to prune the cache: {err}");
+        }
+    }
+
+    // Registry is not built if some files are unreadable, since items
+    // referencing them would only produce misleading errors
+    if !errors.is_empty() {
+        let count = errors.len();
+        for err in errors {
+            eprintln!("{:?}", Report::new(err));
+        }
+        eprintln!("Failed to load {count} file(s)");
+        return ExitCode::FAILURE;
+    }
+
+    // Images are only referred to by name, so the handles don't need to
+    // point to the actual assets, as long as they are unique
+    let mut image_count = 0u128;
+    let layers = mods.iter().map(|files| ModLayer {
+        name: files.name.clone(),
+        items: files
+            .items
+            .iter()
+            .map(|(path, item)| (path.clone(), item))
+            .collect(),
+        images: files
+            .images
+            .iter()
+            .map(|path| {
+                image_count += 1;
+                (path.clone(), Handle::<Image>::weak_from_u128(image_count))
+            })
+            .collect(),
+    });
+
+    let settings = LintSettings {
+        unused_items: flags.unused_items.unwrap_or_default(),
+        unused_images: flags.unused_images.unwrap_or_default(),
+        unknown_fields: if flags.strict {
+            LintSeverity::Deny
+        } else {
+            flags.unknown_fields.unwrap_or_default()
+        },
+    };
+    match ModRegistry::build(layers) {
+        Ok(registry) => {
+            let lints = registry.lint(&settings);
+            let denied = lints
+                .iter()
+                .filter(|lint| lint.severity == LintSeverity::Deny)
+                .count();
+            for lint in lints {
+                eprintln!("{:?}", Report::new(lint));
+            }
+            if denied > 0 {
+                eprintln!("Mods failed {denied} lint(s)");
+                return ExitCode::FAILURE;
+            }
+            println!(
+                "Mods are valid: {}",
+                mods.iter()
+                    .map(|files| files.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{:?}", Report::new(err.diagnostic()));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn read_mod(path: &Path, cache: Option<&FileCache>, errors: &mut Vec<FileError>) -> ModFiles {
+    let mut files = ModFiles {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+
+    let mut paths = Vec::new();
+    if let Err(err) = collect_files(path, &mut paths) {
+        errors.push(FileError::Io(path.to_path_buf(), err));
+        return files;
+    }
+    paths.sort();
+
+    for path in paths {
+        let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+            continue;
+        };
+        let extension = extension.to_ascii_lowercase();
+        if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+            files.images.push(path);
+        } else if let Some(format) = DataFormat::from_extension(&extension) {
+            let text: Arc<str> = match std::fs::read_to_string(&path) {
+                Ok(text) => text.into(),
+                Err(err) => {
+                    errors.push(FileError::Io(path, err));
+                    continue;
+                }
+            };
+            let asset = match cache {
+                Some(cache) => DatabaseAsset::parse_cached(&text, format, cache),
+                None => DatabaseAsset::parse(&text, format),
+            };
+            match asset {
+                Ok(item) => files.items.push((path, item)),
+                Err(err) => errors.push(FileError::Parse(path, err)),
+            }
+        }
+    }
+
+    files
+}
+
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, out)?;
+        } else {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
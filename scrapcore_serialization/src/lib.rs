@@ -3,6 +3,30 @@ mod registry;
 mod reservation;
 mod serialization;
 
+pub use registry::entry::{RegistryEntry, RegistryEntrySerialized};
+pub use registry::index::RegistryIndex;
+pub use registry::inline::{InlineOrId, InlineOrIdSerialized};
+pub use registry::kind::{AssetKindProvider, ItemKindProvider};
+pub use registry::{
+    AssetsCollection, AssetsHolder, CollectionHolder, CollectionItemId, ItemCollection,
+    PartialCollectionHolder, PartialItemCollection, PartialSingleton, PartialSingletonHolder,
+    RawItemCollection, SerializationRegistry, Singleton, SingletonHolder,
+};
+pub use serialization::box_wrapper::SerializationBoxingWrapper;
+pub use serialization::error::{
+    DeserializationError, DeserializationErrorKind, DeserializationErrorStackItem,
+};
+pub use serialization::helpers::DeserializeFrom;
+pub use serialization::{DeserializeModel, SerializationFallback};
+
+/// Helpers called by the code generated with `#[registry]`
+#[doc(hidden)]
+pub use registry::convert_partial;
+#[doc(hidden)]
+pub use registry::insert::{
+    asset_insert, registry_drain, registry_insert, singleton_deserialize, singleton_insert,
+};
+
 #[cfg(test)]
 mod test;
 
@@ -1,5 +1,5 @@
 use rustc_hash::FxHashMap;
-use slabmap::SlabMap;
+use slabmap::{SlabMap, SlabMapId};
 use std::error::Error;
 use std::fmt::{Debug, Display};
 use std::path::PathBuf;
@@ -25,11 +25,29 @@ pub type RawItemCollection<T> = FxHashMap<
     ),
 >;
 
+/// Typed ID of an item in the collection
+pub type CollectionItemId<T> = SlabMapId<RegistryEntry<T>>;
+
 pub type Singleton<T> = T;
 pub type PartialSingleton<T> = Option<(PathBuf, <T as SerializationFallback>::Fallback)>;
 
 pub type AssetsCollection<T> = FxHashMap<AssetName, (T, PathBuf)>;
 
+/// Converts the collection filled during deserialization into the final one,
+/// keeping IDs of the items
+pub fn convert_partial<T>(partial: PartialItemCollection<T>) -> ItemCollection<T> {
+    let mut items = partial.into_iter().collect::<Vec<_>>();
+    items.sort_by_key(|(_, id, _)| *id);
+
+    let mut out = ItemCollection::default();
+    for (key, id, value) in items {
+        let value = value.expect("All reserved items should be filled before conversion");
+        let (inserted_id, _) = out.insert(key, value);
+        assert_eq!(inserted_id.raw(), id, "Should be inserted via the same ID");
+    }
+    out
+}
+
 pub trait CollectionHolder<Value>: SerializationRegistry + ItemKindProvider<Value> {
     fn get_collection(&self) -> &ItemCollection<Value>;
     fn get_collection_mut(&mut self) -> &mut ItemCollection<Value>;
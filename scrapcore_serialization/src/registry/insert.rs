@@ -1,11 +1,14 @@
-use crate::registry::entry::RegistryEntrySerialized;
+use crate::registry::entry::{RegistryEntry, RegistryEntrySerialized};
+use crate::registry::kind::ItemKindProvider;
 use crate::registry::{
     AssetsHolder, PartialCollectionHolder, PartialSingletonHolder, SerializationRegistry,
 };
+use crate::reservation::reserve;
 use crate::serialization::error::{
     DeserializationError, DeserializationErrorKind, DeserializationErrorStackItem,
 };
-use crate::serialization::SerializationFallback;
+use crate::serialization::{DeserializeModel, SerializationFallback};
+use slabmap::SlabMapId;
 use std::collections::hash_map::Entry;
 use std::path::PathBuf;
 
@@ -93,3 +96,50 @@ pub fn asset_insert<Registry: SerializationRegistry + AssetsHolder<T>, T>(
         }
     }
 }
+
+/// Deserializes all items still waiting in the raw collection
+///
+/// Slots of all items are reserved upfront, so the items can reference each
+/// other regardless of the order they are deserialized in
+pub fn registry_drain<
+    Registry: SerializationRegistry + PartialCollectionHolder<T>,
+    T: SerializationFallback,
+>(
+    registry: &mut Registry,
+) -> Result<(), DeserializationError<Registry>>
+where
+    T::Fallback: DeserializeModel<T, Registry>,
+{
+    let raw = std::mem::take(registry.get_raw_collection());
+    for id in raw.keys() {
+        reserve(registry.get_collection(), id.clone());
+    }
+    for (item, path) in raw.into_values() {
+        DeserializeModel::<SlabMapId<RegistryEntry<T>>, Registry>::deserialize(item, registry)
+            .map_err(|e| {
+                e.context(DeserializationErrorStackItem::ItemByPath(
+                    path,
+                    <Registry as ItemKindProvider<T>>::kind(),
+                ))
+            })?;
+    }
+    Ok(())
+}
+
+/// Takes the singleton out of the registry and deserializes it
+pub fn singleton_deserialize<
+    Registry: SerializationRegistry + PartialSingletonHolder<T>,
+    T: SerializationFallback,
+>(
+    registry: &mut Registry,
+) -> Result<T, DeserializationError<Registry>>
+where
+    T::Fallback: DeserializeModel<T, Registry>,
+{
+    let kind = <Registry as ItemKindProvider<T>>::kind();
+    let Some((path, item)) = registry.get_singleton().take() else {
+        return Err(DeserializationErrorKind::MissingSingleton(kind).into());
+    };
+    item.deserialize(registry)
+        .map_err(|e| e.context(DeserializationErrorStackItem::ItemByPath(path, kind)))
+}
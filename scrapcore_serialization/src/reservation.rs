@@ -5,12 +5,26 @@ use std::hash::Hash;
 
 pub(crate) struct SlabMapReservation<T>(SlabMapId<T>);
 
+/// Reserves the slot for the item, panics if the item is already filled in
+///
+/// Raw items are taken out of the raw collection before being deserialized,
+/// so every item is only filled in once
 pub(crate) fn reserve<T>(
     map: &mut SlabMap<ItemId, Option<T>>,
     key: ItemId,
-) -> Result<SlabMapReservation<T>, SlabMapDuplicateError<ItemId, Option<T>>> {
-    map.insert_new(key, None)
-        .map(|e| SlabMapReservation(e.as_untyped().as_typed_unchecked()))
+) -> SlabMapReservation<T> {
+    // Slots reserved upfront are filled in place
+    if let Some(id) = map.key_to_id(&key) {
+        if map[id].is_none() {
+            return SlabMapReservation(id.as_untyped().as_typed_unchecked());
+        }
+    }
+    let id = map
+        .insert_new(key, None)
+        .unwrap_or_else(|SlabMapDuplicateError(key, _)| {
+            panic!("Item `{key}` is deserialized twice")
+        });
+    SlabMapReservation(id.as_untyped().as_typed_unchecked())
 }
 
 impl<T> SlabMapReservation<T> {
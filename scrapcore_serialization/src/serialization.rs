@@ -163,7 +163,7 @@ where
         registry: &mut Registry,
     ) -> Result<SlabMapId<RegistryEntry<Data>>, DeserializationError<Registry>> {
         let items = registry.get_collection();
-        let reserved = reserve(items, self.id.clone())?;
+        let reserved = reserve(items, self.id.clone());
         let data =
             DeserializeModel::<Data, Registry>::deserialize(self.data, registry).map_err(|e| {
                 e.context(DeserializationErrorStackItem::ItemById(
@@ -1,7 +1,5 @@
-use crate::registry::kind::ItemKindProvider;
 use crate::registry::SerializationRegistry;
 use crate::{AssetName, ItemId};
-use slabmap::SlabMapDuplicateError;
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
 use thiserror::Error;
@@ -20,8 +18,6 @@ pub enum DeserializationErrorKind<Registry: SerializationRegistry> {
         path_a: PathBuf,
         path_b: PathBuf,
     },
-    #[error("Item {}({}) is already declared", .1, .0)]
-    DuplicateItemLowInfo(ItemId, Registry::ItemKind),
     #[error("Image `{}` is missing", .0)]
     MissingAsset(AssetName, Registry::AssetKind),
     #[error("Asset name `{}` is contested by `{}` and `{}`", .name, .path_a.to_string_lossy(), .path_b.to_string_lossy())]
@@ -37,6 +33,8 @@ pub enum DeserializationErrorKind<Registry: SerializationRegistry> {
         path_a: PathBuf,
         path_b: PathBuf,
     },
+    #[error("Singleton item {} is missing", .0)]
+    MissingSingleton(Registry::ItemKind),
     #[error("File at `{}` doesn't have a name", .0.to_string_lossy())]
     MissingName(PathBuf),
     #[error("File path at `{}` is not UTF8", .0.to_string_lossy())]
@@ -131,13 +129,3 @@ impl<Registry: SerializationRegistry> From<DeserializationErrorKind<Registry>>
 //         DeserializationErrorKind::BadExpression(value).into()
 //     }
 // }
-
-impl<T, Registry: SerializationRegistry> From<SlabMapDuplicateError<ItemId, T>>
-    for DeserializationError<Registry>
-where
-    Registry: ItemKindProvider<T>,
-{
-    fn from(SlabMapDuplicateError(id, _): SlabMapDuplicateError<ItemId, T>) -> Self {
-        DeserializationErrorKind::DuplicateItemLowInfo(id, Registry::kind()).into()
-    }
-}
@@ -2,18 +2,291 @@ use schemars::JsonSchema;
 use scrapcore_serialization_macro::registry;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    AssetsHolder, CollectionHolder, CollectionItemId, DeserializationError,
+    DeserializationErrorKind, DeserializeModel, ItemId, PartialCollectionHolder,
+    PartialSingletonHolder, RegistryEntrySerialized, SerializationFallback, SerializationRegistry,
+    SingletonHolder,
+};
+
 #[derive(Debug)]
 struct A {}
 
-impl crate::serialization::SerializationFallback for A {
+impl SerializationFallback for A {
     type Fallback = ASerialzied;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 struct ASerialzied {}
 
+impl<Registry: SerializationRegistry> DeserializeModel<A, Registry> for ASerialzied {
+    fn deserialize(self, _registry: &mut Registry) -> Result<A, DeserializationError<Registry>> {
+        Ok(A {})
+    }
+}
+
+/// Item referencing another item of the same collection
+#[derive(Debug)]
+struct Linked {
+    next: Option<CollectionItemId<Linked>>,
+}
+
+impl SerializationFallback for Linked {
+    type Fallback = LinkedSerialized;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct LinkedSerialized {
+    next: Option<ItemId>,
+}
+
+impl<Registry: SerializationRegistry + PartialCollectionHolder<Linked>>
+    DeserializeModel<Linked, Registry> for LinkedSerialized
+{
+    fn deserialize(
+        self,
+        registry: &mut Registry,
+    ) -> Result<Linked, DeserializationError<Registry>> {
+        Ok(Linked {
+            next: self
+                .next
+                .map(|next| next.as_str().deserialize(registry))
+                .transpose()?,
+        })
+    }
+}
+
+/// Singleton referencing an item of a collection
+#[derive(Debug)]
+struct Settings {
+    start: CollectionItemId<Linked>,
+}
+
+impl SerializationFallback for Settings {
+    type Fallback = SettingsSerialized;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct SettingsSerialized {
+    start: ItemId,
+}
+
+impl<Registry: SerializationRegistry + PartialCollectionHolder<Linked>>
+    DeserializeModel<Settings, Registry> for SettingsSerialized
+{
+    fn deserialize(
+        self,
+        registry: &mut Registry,
+    ) -> Result<Settings, DeserializationError<Registry>> {
+        Ok(Settings {
+            start: self.start.as_str().deserialize(registry)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Image(u32);
+
+impl SerializationFallback for Image {
+    type Fallback = Image;
+}
+
 #[registry]
 struct Model {
     #[model(collection)]
     test: A,
+    #[model(collection)]
+    linked: Linked,
+    #[model(singleton)]
+    settings: Settings,
+    #[model(asset)]
+    image: Image,
+}
+
+fn linked(id: &str, next: Option<&str>) -> ModelItemSerialized {
+    ModelItemSerialized::Linked(RegistryEntrySerialized {
+        id: id.to_string(),
+        data: LinkedSerialized {
+            next: next.map(str::to_string),
+        },
+    })
+}
+
+fn settings(start: &str) -> ModelItemSerialized {
+    ModelItemSerialized::Settings(SettingsSerialized {
+        start: start.to_string(),
+    })
+}
+
+/// Partial registry with a cycle of linked items and the settings pointing
+/// into it
+fn partial() -> PartialModelRegistry {
+    let mut registry = PartialModelRegistry::default();
+    registry
+        .insert(
+            "a.json".into(),
+            ModelItemSerialized::Test(RegistryEntrySerialized {
+                id: "a".to_string(),
+                data: ASerialzied {},
+            }),
+        )
+        .unwrap();
+    for (id, next) in [("first", "second"), ("second", "third"), ("third", "first")] {
+        registry
+            .insert(format!("{id}.json").into(), linked(id, Some(next)))
+            .unwrap();
+    }
+    registry
+        .insert("settings.json".into(), settings("second"))
+        .unwrap();
+    registry
+}
+
+#[test]
+fn registry_deserializes_items() {
+    let registry = partial().into_registry().unwrap();
+    let id: TestId = registry.test.key_to_id("a").unwrap();
+    assert_eq!(registry[id].id, id);
+    assert_eq!(registry[&id].id, id);
+}
+
+#[test]
+fn items_reference_each_other_in_any_order() {
+    let registry = partial().into_registry().unwrap();
+
+    let ids: Vec<LinkedId> = ["first", "second", "third"]
+        .into_iter()
+        .map(|id| registry.linked.key_to_id(id).unwrap())
+        .collect();
+    assert_eq!(registry[ids[0]].data.next, Some(ids[1]));
+    assert_eq!(registry[ids[1]].data.next, Some(ids[2]));
+    assert_eq!(registry[ids[2]].data.next, Some(ids[0]));
+    assert_eq!(registry.settings.start, ids[1]);
+}
+
+#[test]
+fn missing_references_are_reported() {
+    let mut registry = PartialModelRegistry::default();
+    registry
+        .insert("settings.json".into(), settings("first"))
+        .unwrap();
+    registry
+        .insert("first.json".into(), linked("first", Some("absent")))
+        .unwrap();
+
+    let err = registry.into_registry().unwrap_err();
+    assert!(
+        matches!(
+            &err.kind,
+            DeserializationErrorKind::MissingItem(id, ModelItemKind::Linked) if id == "absent"
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn duplicate_items_are_reported_with_both_paths() {
+    let mut registry = partial();
+    let err = registry
+        .insert("copy.json".into(), linked("first", None))
+        .unwrap_err();
+
+    let DeserializationErrorKind::DuplicateItem {
+        id, path_a, path_b, ..
+    } = &err.kind
+    else {
+        panic!("Expected a duplicate, got {err}");
+    };
+    assert_eq!(id, "first");
+    assert_eq!(path_a.to_str(), Some("first.json"));
+    assert_eq!(path_b.to_str(), Some("copy.json"));
+}
+
+#[test]
+fn singletons_are_required_and_unique() {
+    let err = PartialModelRegistry::default().into_registry().unwrap_err();
+    assert!(
+        matches!(
+            err.kind,
+            DeserializationErrorKind::MissingSingleton(ModelItemKind::Settings)
+        ),
+        "{err}"
+    );
+
+    let mut registry = partial();
+    let err = registry
+        .insert("other.json".into(), settings("first"))
+        .unwrap_err();
+    assert!(
+        matches!(
+            err.kind,
+            DeserializationErrorKind::DuplicateSingleton {
+                kind: ModelItemKind::Settings,
+                ..
+            }
+        ),
+        "{err}"
+    );
+}
+
+#[test]
+fn assets_are_named_by_their_files() {
+    let mut registry = partial();
+    registry
+        .insert_asset("images/Ship.png".into(), Image(1))
+        .unwrap();
+    let err = registry
+        .insert_asset("other/ship.png".into(), Image(2))
+        .unwrap_err();
+    assert!(
+        matches!(
+            &err.kind,
+            DeserializationErrorKind::DuplicateAsset { kind: ModelItemAssetKind::Image, name, .. }
+                if name == "ship.png"
+        ),
+        "{err}"
+    );
+
+    let registry = registry.into_registry().unwrap();
+    assert_eq!(registry.image["ship.png"].0, Image(1));
+}
+
+fn count<T, R: CollectionHolder<T>>(registry: &R) -> usize {
+    registry.get_collection().values().count()
+}
+
+#[test]
+fn holders_expose_their_fields() {
+    let mut partial = partial();
+    assert_eq!(
+        PartialCollectionHolder::<Linked>::get_raw_collection(&mut partial).len(),
+        3
+    );
+    assert_eq!(
+        PartialCollectionHolder::<Linked>::get_collection(&mut partial)
+            .values()
+            .count(),
+        0
+    );
+    assert!(PartialSingletonHolder::<Settings>::get_singleton(&mut partial).is_some());
+    partial.insert_asset("a.png".into(), Image(1)).unwrap();
+    assert_eq!(AssetsHolder::<Image>::get_assets(&partial).len(), 1);
+    AssetsHolder::<Image>::get_assets_mut(&mut partial).clear();
+
+    let mut registry = partial.into_registry().unwrap();
+    assert!(AssetsHolder::<Image>::get_assets(&registry).is_empty());
+    assert_eq!(count::<A, _>(&registry), 1);
+    assert_eq!(count::<Linked, _>(&registry), 3);
+
+    let first = registry.linked.key_to_id("first").unwrap();
+    CollectionHolder::<Linked>::get_collection_mut(&mut registry)[first]
+        .data
+        .next = None;
+    assert_eq!(registry[first].data.next, None);
+
+    SingletonHolder::<Settings>::get_singleton_mut(&mut registry).start = first;
+    assert_eq!(
+        SingletonHolder::<Settings>::get_singleton(&registry).start,
+        first
+    );
 }
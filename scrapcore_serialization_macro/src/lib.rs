@@ -27,12 +27,6 @@ fn model_mod() -> proc_macro2::TokenStream {
 #[derive(Debug)]
 struct IdentSync(String);
 
-impl IdentSync {
-    fn join(&self, path: &str) -> IdentSync {
-        IdentSync(format!("{}::{}", self.0, path))
-    }
-}
-
 impl ToTokens for IdentSync {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let ty: syn::Type = parse_str(&self.0).unwrap();
@@ -43,8 +37,6 @@ impl ToTokens for IdentSync {
 
 lazy_static! {
     static ref SERIALIZATION_CRATE: IdentSync = crate_name("scrapcore_serialization");
-    static ref MOD_REGISTRY: IdentSync = SERIALIZATION_CRATE.join("registry");
-    static ref MOD_SERIALIZATION: IdentSync = SERIALIZATION_CRATE.join("serialization");
 }
 fn crate_name(name: &str) -> IdentSync {
     match proc_macro_crate::crate_name(name) {
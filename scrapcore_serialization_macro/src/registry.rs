@@ -1,12 +1,11 @@
 use std::ops::Deref;
 
 use proc_macro2::{Ident, Span, TokenStream};
-use quote::{quote, quote_spanned};
-use rustc_hash::FxHashMap;
-use syn::{parse_macro_input, ItemStruct, Type};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{parse_macro_input, ItemStruct, Type, Visibility};
 
 use crate::registry::parser::parse_struct_defs;
-use crate::{MOD_REGISTRY, MOD_SERIALIZATION};
+use crate::SERIALIZATION_CRATE;
 
 mod parser;
 
@@ -65,6 +64,19 @@ impl ModelSet {
              }| { quote_spanned!(*span=>#variant_name(#ty)) },
         )
     }
+    fn serialized_entry_variants(&self) -> impl Iterator<Item = TokenStream> + '_ {
+        let reg = SERIALIZATION_CRATE.deref();
+        self.iter().map(
+            move |ModelKind {
+                      variant_name,
+                      ty_serialized,
+                      span,
+                      ..
+                  }| {
+                quote_spanned!(*span=>#variant_name(#reg::RegistryEntrySerialized<#ty_serialized>),)
+            },
+        )
+    }
     fn serialized_variants(&self) -> impl Iterator<Item = TokenStream> + '_ {
         self.iter().map(
             |ModelKind {
@@ -87,16 +99,20 @@ impl Deref for ModelSet {
 
 #[derive(Debug)]
 struct RegistryDefinitions {
+    vis: Visibility,
     pascal_name: Ident,
     model_name: Ident,
     serialized_model_name: Ident,
     registry_name: Ident,
     partial_registry_name: Ident,
+    kind_name: Ident,
+    asset_kind_name: Ident,
+    error: Type,
     schema: bool,
 
     singletons: ModelSet,
     collections: ModelSet,
-    assets: FxHashMap<Ident, Type>,
+    assets: ModelSet,
 }
 
 fn registry_impl_inner(
@@ -106,18 +122,25 @@ fn registry_impl_inner(
     let definitions = parse_struct_defs(attr, &mut item_struct)?;
 
     let model = definitions.model();
+    let kinds = definitions.kinds();
+    let registry = definitions.registry();
     let partial_registry = definitions.partial_registry();
+    let ids = definitions.ids();
 
     Ok(quote! {
         #model
+        #kinds
+        #registry
         #partial_registry
+        #ids
     })
 }
 
 impl RegistryDefinitions {
     fn model(&self) -> TokenStream {
+        let vis = &self.vis;
         let singletons = self.singletons.serialized_variants();
-        let registries = self.collections.serialized_variants();
+        let registries = self.collections.serialized_entry_variants();
         let serialized_model_name = &self.serialized_model_name;
         let schema_derive = if self.schema {
             quote!(#[derive(schemars::JsonSchema)])
@@ -131,7 +154,7 @@ impl RegistryDefinitions {
             #[serde(tag = "type")]
             #[serde(rename_all = "PascalCase")]
             #[serde(rename = #model_name_str)]
-            enum #serialized_model_name {
+            #vis enum #serialized_model_name {
                 #(#singletons)*
                 #(#registries)*
             }
@@ -140,15 +163,213 @@ impl RegistryDefinitions {
         model_enum
     }
 
+    /// Enums of item and asset kinds, along with the registry traits
+    /// providing them
+    fn kinds(&self) -> TokenStream {
+        let Self {
+            vis,
+            kind_name,
+            asset_kind_name,
+            error,
+            registry_name,
+            partial_registry_name,
+            singletons,
+            collections,
+            assets,
+            ..
+        } = self;
+        let reg = SERIALIZATION_CRATE.deref();
+
+        let item_kind_enum = kind_enum(vis, kind_name, singletons.iter().chain(collections.iter()));
+        let asset_kind_enum = kind_enum(vis, asset_kind_name, assets.iter());
+
+        let impls = [registry_name, partial_registry_name]
+            .into_iter()
+            .map(|name| {
+                let item_kinds = singletons.iter().chain(collections.iter()).map(
+                    |ModelKind {
+                         span,
+                         variant_name,
+                         ty,
+                         ..
+                     }| {
+                        quote_spanned! {*span=>
+                            impl #reg::ItemKindProvider<#ty> for #name {
+                                fn kind() -> Self::ItemKind {
+                                    #kind_name::#variant_name
+                                }
+                            }
+                        }
+                    },
+                );
+                let asset_kinds = assets.iter().map(
+                    |ModelKind {
+                         span,
+                         variant_name,
+                         ty,
+                         ..
+                     }| {
+                        quote_spanned! {*span=>
+                            impl #reg::AssetKindProvider<#ty> for #name {
+                                fn asset_kind() -> Self::AssetKind {
+                                    #asset_kind_name::#variant_name
+                                }
+                            }
+                        }
+                    },
+                );
+                quote! {
+                    impl #reg::SerializationRegistry for #name {
+                        type ItemKind = #kind_name;
+                        type AssetKind = #asset_kind_name;
+                        type Error = #error;
+                    }
+
+                    #(#item_kinds)*
+                    #(#asset_kinds)*
+                }
+            });
+
+        quote! {
+            #item_kind_enum
+            #asset_kind_enum
+            #(#impls)*
+        }
+    }
+
+    fn registry(&self) -> TokenStream {
+        let Self {
+            vis,
+            registry_name,
+            singletons,
+            collections,
+            assets,
+            ..
+        } = self;
+        let reg = SERIALIZATION_CRATE.deref();
+
+        let singleton_fields = singletons.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    pub #field_name: #reg::Singleton<#ty>,
+                }
+            },
+        );
+        let collection_fields = collections.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    pub #field_name: #reg::ItemCollection<#ty>,
+                }
+            },
+        );
+        let asset_fields = assets.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    pub #field_name: #reg::AssetsCollection<#ty>,
+                }
+            },
+        );
+
+        let singleton_impls = singletons.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    impl #reg::SingletonHolder<#ty> for #registry_name {
+                        fn get_singleton(&self) -> &#reg::Singleton<#ty> {
+                            &self.#field_name
+                        }
+
+                        fn get_singleton_mut(&mut self) -> &mut #reg::Singleton<#ty> {
+                            &mut self.#field_name
+                        }
+                    }
+                }
+            },
+        );
+        let collection_impls = collections.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    impl #reg::CollectionHolder<#ty> for #registry_name {
+                        fn get_collection(&self) -> &#reg::ItemCollection<#ty> {
+                            &self.#field_name
+                        }
+
+                        fn get_collection_mut(&mut self) -> &mut #reg::ItemCollection<#ty> {
+                            &mut self.#field_name
+                        }
+                    }
+
+                    impl std::ops::Index<#reg::CollectionItemId<#ty>> for #registry_name {
+                        type Output = #reg::RegistryEntry<#ty>;
+
+                        fn index(&self, index: #reg::CollectionItemId<#ty>) -> &Self::Output {
+                            &self.#field_name[index]
+                        }
+                    }
+
+                    impl std::ops::Index<&#reg::CollectionItemId<#ty>> for #registry_name {
+                        type Output = #reg::RegistryEntry<#ty>;
+
+                        fn index(&self, index: &#reg::CollectionItemId<#ty>) -> &Self::Output {
+                            &self.#field_name[*index]
+                        }
+                    }
+                }
+            },
+        );
+        let asset_impls = assets_holder_impls(registry_name, assets);
+
+        quote! {
+            #[derive(Debug)]
+            #vis struct #registry_name {
+                #(#singleton_fields)*
+                #(#collection_fields)*
+                #(#asset_fields)*
+            }
+
+            #(#singleton_impls)*
+            #(#collection_impls)*
+            #asset_impls
+        }
+    }
+
     fn partial_registry(&self) -> TokenStream {
         let Self {
+            vis,
+            registry_name,
             partial_registry_name,
+            serialized_model_name,
             singletons,
             collections,
+            assets,
             ..
         } = self;
-        let reg = MOD_REGISTRY.deref();
-        let singletons = singletons.iter().map(
+        let reg = SERIALIZATION_CRATE.deref();
+        let singleton_fields = singletons.iter().map(
             |ModelKind {
                  span,
                  field_name,
@@ -160,7 +381,7 @@ impl RegistryDefinitions {
                 }
             },
         );
-        let collections = collections.iter().map(
+        let collection_fields = collections.iter().map(
             |ModelKind {
                  span,
                  field_name,
@@ -174,19 +395,267 @@ impl RegistryDefinitions {
                 }
             },
         );
+        let asset_fields = assets.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    #field_name: #reg::AssetsCollection<#ty>,
+                }
+            },
+        );
+
+        let singleton_impls = singletons.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    impl #reg::PartialSingletonHolder<#ty> for #partial_registry_name {
+                        fn get_singleton(&mut self) -> &mut #reg::PartialSingleton<#ty> {
+                            &mut self.#field_name
+                        }
+                    }
+                }
+            },
+        );
+        let collection_impls = collections.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 raw_field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    impl #reg::PartialCollectionHolder<#ty> for #partial_registry_name {
+                        fn get_collection(&mut self) -> &mut #reg::PartialItemCollection<#ty> {
+                            &mut self.#field_name
+                        }
+
+                        fn get_raw_collection(&mut self) -> &mut #reg::RawItemCollection<#ty> {
+                            &mut self.#raw_field_name
+                        }
+                    }
+                }
+            },
+        );
+        let asset_impls = assets_holder_impls(partial_registry_name, assets);
+
+        let singleton_inserts = singletons.iter().map(
+            |ModelKind {
+                 span,
+                 variant_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    #serialized_model_name::#variant_name(item) => {
+                        #reg::singleton_insert::<Self, #ty>(self, path, item)
+                    }
+                }
+            },
+        );
+        let collection_inserts = collections.iter().map(
+            |ModelKind {
+                 span,
+                 variant_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    #serialized_model_name::#variant_name(item) => {
+                        #reg::registry_insert::<Self, #ty>(self, path, item)
+                    }
+                }
+            },
+        );
+
+        // Singletons go first, pulling in the items they reference, and the
+        // rest of the items is drained afterwards
+        let singleton_deserialize = singletons.iter().map(
+            |ModelKind {
+                 span,
+                 field_name,
+                 ty,
+                 ..
+             }| {
+                quote_spanned! {*span=>
+                    let #field_name = #reg::singleton_deserialize::<Self, #ty>(&mut self)?;
+                }
+            },
+        );
+        let collection_drains = collections.iter().map(|ModelKind { span, ty, .. }| {
+            quote_spanned! {*span=>
+                #reg::registry_drain::<Self, #ty>(&mut self)?;
+            }
+        });
+        let singleton_names = singletons
+            .iter()
+            .map(|ModelKind { field_name, .. }| field_name);
+        let collection_conversions = collections.iter().map(
+            |ModelKind {
+                 span, field_name, ..
+             }| {
+                quote_spanned! {*span=>
+                    #field_name: #reg::convert_partial(self.#field_name),
+                }
+            },
+        );
+        let asset_moves = assets
+            .iter()
+            .map(|ModelKind { field_name, .. }| quote!(#field_name: self.#field_name,));
+
+        let insert_asset = (!assets.is_empty()).then(|| {
+            quote! {
+                /// Adds the asset to the registry, asset name is taken from
+                /// the file name
+                pub fn insert_asset<T>(
+                    &mut self,
+                    path: std::path::PathBuf,
+                    asset: T,
+                ) -> Result<(), #reg::DeserializationError<Self>>
+                where
+                    Self: #reg::AssetsHolder<T>,
+                {
+                    #reg::asset_insert(self, path, asset)
+                }
+            }
+        });
+
         quote! {
             #[derive(Debug, Default)]
-            struct #partial_registry_name {
-                #(#singletons)*
-                #(#collections)*
+            #vis struct #partial_registry_name {
+                #(#singleton_fields)*
+                #(#collection_fields)*
+                #(#asset_fields)*
+            }
+
+            #(#singleton_impls)*
+            #(#collection_impls)*
+            #asset_impls
+
+            impl #partial_registry_name {
+                /// Adds the item to the registry, to be deserialized later
+                pub fn insert(
+                    &mut self,
+                    path: std::path::PathBuf,
+                    item: #serialized_model_name,
+                ) -> Result<(), #reg::DeserializationError<Self>> {
+                    match item {
+                        #(#singleton_inserts)*
+                        #(#collection_inserts)*
+                    }
+                }
+
+                #insert_asset
+
+                /// Deserializes all inserted items, and converts them into
+                /// the final registry
+                pub fn into_registry(
+                    mut self,
+                ) -> Result<#registry_name, #reg::DeserializationError<Self>> {
+                    #(#singleton_deserialize)*
+                    #(#collection_drains)*
+                    Ok(#registry_name {
+                        #(#singleton_names,)*
+                        #(#collection_conversions)*
+                        #(#asset_moves)*
+                    })
+                }
+            }
+        }
+    }
+
+    /// Typed ID aliases for items of every collection
+    fn ids(&self) -> TokenStream {
+        let vis = &self.vis;
+        let reg = SERIALIZATION_CRATE.deref();
+        let ids = self.collections.iter().map(
+            |ModelKind {
+                 span,
+                 variant_name,
+                 ty,
+                 ..
+             }| {
+                let name = format_ident!("{variant_name}Id");
+                quote_spanned! {*span=>
+                    #vis type #name = #reg::CollectionItemId<#ty>;
+                }
+            },
+        );
+        quote! {
+            #(#ids)*
+        }
+    }
+}
+
+fn kind_enum<'a>(
+    vis: &Visibility,
+    name: &Ident,
+    kinds: impl Iterator<Item = &'a ModelKind> + Clone,
+) -> TokenStream {
+    let variants = kinds
+        .clone()
+        .map(|ModelKind { variant_name, .. }| variant_name);
+    let labels = kinds.map(|ModelKind { variant_name, .. }| {
+        let label = variant_name.to_string();
+        quote!(#name::#variant_name => #label)
+    });
+    quote! {
+        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+        #vis enum #name {
+            #(#variants,)*
+        }
+
+        impl std::fmt::Display for #name {
+            // Registries without assets have an empty asset kind enum
+            #[allow(unreachable_code)]
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let label: &str = match *self {
+                    #(#labels,)*
+                };
+                write!(f, "{}", label)
             }
         }
     }
 }
 
+fn assets_holder_impls(registry_name: &Ident, assets: &ModelSet) -> TokenStream {
+    let reg = SERIALIZATION_CRATE.deref();
+    let impls = assets.iter().map(
+        |ModelKind {
+             span,
+             field_name,
+             ty,
+             ..
+         }| {
+            quote_spanned! {*span=>
+                impl #reg::AssetsHolder<#ty> for #registry_name {
+                    fn get_assets(&self) -> &#reg::AssetsCollection<#ty> {
+                        &self.#field_name
+                    }
+
+                    fn get_assets_mut(&mut self) -> &mut #reg::AssetsCollection<#ty> {
+                        &mut self.#field_name
+                    }
+                }
+            }
+        },
+    );
+    quote! {
+        #(#impls)*
+    }
+}
+
 fn serialized_of(ty: &Type) -> syn::Result<Type> {
-    let ser = MOD_SERIALIZATION.deref();
+    let reg = SERIALIZATION_CRATE.deref();
     syn::parse2(quote! {
-        <#ty as #ser::SerializationFallback>::Fallback
+        <#ty as #reg::SerializationFallback>::Fallback
     })
 }
@@ -5,7 +5,7 @@ use proc_macro2::Ident;
 use quote::format_ident;
 use rustc_hash::FxHashSet;
 use syn::spanned::Spanned;
-use syn::ItemStruct;
+use syn::{parse_quote, ItemStruct, Type};
 
 #[derive(Debug, Attribute)]
 struct RegistryAttributeInput {
@@ -15,6 +15,10 @@ struct RegistryAttributeInput {
     serialized_item_name: Option<Ident>,
     registry_name: Option<Ident>,
     partial_registry_name: Option<Ident>,
+    kind_name: Option<Ident>,
+    asset_kind_name: Option<Ident>,
+    /// Custom error type of the registry, [std::convert::Infallible] by default
+    error: Option<Type>,
 }
 
 #[derive(Debug, Attribute)]
@@ -42,7 +46,14 @@ pub(super) fn parse_struct_defs(
     let partial_registry_name = input
         .partial_registry_name
         .unwrap_or_else(|| format_ident!("Partial{}", registry_name));
+    let kind_name = input
+        .kind_name
+        .unwrap_or_else(|| format_ident!("{registry_item_name}Kind"));
+    let asset_kind_name = input
+        .asset_kind_name
+        .unwrap_or_else(|| format_ident!("{registry_item_name}AssetKind"));
     let mut registry = RegistryDefinitions {
+        vis: data.vis.clone(),
         pascal_name: registry_item_name.clone(),
         serialized_model_name: input
             .serialized_item_name
@@ -50,6 +61,11 @@ pub(super) fn parse_struct_defs(
         registry_name,
         partial_registry_name,
         model_name: registry_item_name,
+        kind_name,
+        asset_kind_name,
+        error: input
+            .error
+            .unwrap_or_else(|| parse_quote!(std::convert::Infallible)),
         schema: input.schema,
         singletons: Default::default(),
         collections: Default::default(),
@@ -68,33 +84,31 @@ pub(super) fn parse_struct_defs(
             .clone()
             .ok_or_else(|| syn::Error::new(field.span(), "Tuple enums are not supported"))?;
 
+        let model = ModelKind {
+            span: field.span(),
+            variant_name: Ident::new(
+                &name
+                    .to_string()
+                    .from_case(Case::Snake)
+                    .to_case(Case::Pascal),
+                field.span(),
+            ),
+            raw_field_name: format_ident!("{name}_raw"),
+            field_name: name,
+            ty: field.ty.clone(),
+            ty_serialized: serialized_of(&field.ty)?,
+        };
         if attribute.asset {
-            registry.assets.insert(name, field.ty.clone());
+            registry.assets.0.push(model);
+        } else if attribute.collection {
+            registry.collections.0.push(model);
+        } else if attribute.singleton {
+            registry.singletons.0.push(model);
         } else {
-            let model = ModelKind {
-                span: field.span(),
-                variant_name: Ident::new(
-                    &name
-                        .to_string()
-                        .from_case(Case::Snake)
-                        .to_case(Case::Pascal),
-                    field.span(),
-                ),
-                raw_field_name: format_ident!("{name}_raw"),
-                field_name: name,
-                ty: field.ty.clone(),
-                ty_serialized: serialized_of(&field.ty)?,
-            };
-            if attribute.collection {
-                registry.collections.0.push(model);
-            } else if attribute.singleton {
-                registry.singletons.0.push(model);
-            } else {
-                return Err(syn::Error::new(
-                    field.span(),
-                    "All fields must be annotated with #[model(asset)], #[model(collection)], or #[model(singleton)]",
-                ));
-            }
+            return Err(syn::Error::new(
+                field.span(),
+                "All fields must be annotated with #[model(asset)], #[model(collection)], or #[model(singleton)]",
+            ));
         }
     }
     Ok(registry)
@@ -272,6 +272,17 @@ impl<K: Eq + Hash, V, Hasher: BuildHasher> SlabMap<K, V, Hasher> {
         self.keys.get_by_left(key).map(|e| SlabMapId::new(*e))
     }
 
+    /// Removes the item with the key, allowing its ID to be reused by the
+    /// items inserted later
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (_, id) = self.keys.remove_by_left(key)?;
+        Some(self.items.remove(id))
+    }
+
     pub fn id_to_key(&self, id: SlabMapId<V>) -> Option<&K> {
         self.keys.get_by_right(&id.0)
     }
@@ -296,6 +307,14 @@ impl<K: Eq + Hash, V, Hasher: BuildHasher> SlabMap<K, V, Hasher> {
         self.items.iter_mut().map(|(id, e)| (SlabMapId::new(id), e))
     }
 
+    /// Maps all values of the map, keeping their keys and IDs intact
+    pub fn map_values<U>(self, mut f: impl FnMut(V) -> U) -> SlabMap<K, U, Hasher> {
+        SlabMap {
+            items: self.items.into_iter().map(|(id, v)| (id, f(v))).collect(),
+            keys: self.keys,
+        }
+    }
+
     pub fn into_iter(mut self) -> impl Iterator<Item = (K, usize, V)> {
         self.items.into_iter().map(move |(id, v)| {
             let (key, _) = self